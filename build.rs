@@ -0,0 +1,126 @@
+//! Build-time metadata for the About dialog: git revision and dependency licenses
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR"));
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR"));
+
+    let hash = git(&["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    let branch =
+        git(&["rev-parse", "--abbrev-ref", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=ETCH_GIT_HASH={hash}");
+    println!("cargo:rustc-env=ETCH_GIT_BRANCH={branch}");
+
+    // A commit moves the branch ref (loose or packed), not HEAD itself.
+    // Only watch files that exist, otherwise cargo reruns the script on every build.
+    let branch_ref = git(&["symbolic-ref", "-q", "HEAD"]).map(|name| format!(".git/{name}"));
+    let watched = [".git/HEAD", ".git/index", ".git/packed-refs", "Cargo.lock"]
+        .into_iter()
+        .map(str::to_string)
+        .chain(branch_ref);
+    for watched in watched {
+        if manifest_dir.join(&watched).exists() {
+            println!("cargo:rerun-if-changed={watched}");
+        }
+    }
+    println!("cargo:rerun-if-changed=Cargo.toml");
+
+    let licenses = dependency_licenses(&manifest_dir.join("Cargo.toml"));
+    fs::write(out_dir.join("licenses.txt"), licenses.join("\n"))
+        .expect("Failed to write licenses.txt");
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+/// "name version — license" for every package linked into the binary
+///
+/// Walks the resolved graph from the root over normal dependencies only, on
+/// the target platform, so build-time and dev-only crates and other
+/// platforms' crates are left out. Empty when cargo metadata is unavailable
+/// (e.g. vendored offline builds); the About dialog then simply omits the section.
+fn dependency_licenses(manifest: &Path) -> Vec<String> {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let target = env::var("TARGET").unwrap_or_default();
+    let Ok(output) = Command::new(cargo)
+        .args([
+            "metadata",
+            "--format-version",
+            "1",
+            "--offline",
+            "--filter-platform",
+            &target,
+            "--manifest-path",
+        ])
+        .arg(manifest)
+        .output()
+    else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
+    }
+
+    let Ok(metadata) = serde_json::from_slice::<serde_json::Value>(&output.stdout) else {
+        return Vec::new();
+    };
+    let Some(root) = metadata["resolve"]["root"].as_str() else {
+        return Vec::new();
+    };
+    let nodes: HashMap<&str, &serde_json::Value> = metadata["resolve"]["nodes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|node| Some((node["id"].as_str()?, node)))
+        .collect();
+
+    let mut linked = HashSet::new();
+    let mut pending = vec![root];
+    while let Some(id) = pending.pop() {
+        let Some(node) = nodes.get(id) else {
+            continue;
+        };
+        for dep in node["deps"].as_array().into_iter().flatten() {
+            // A null kind is a normal dependency; "build" and "dev" are not linked
+            let normal = dep["dep_kinds"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .any(|kind| kind["kind"].is_null());
+            if let Some(pkg) = dep["pkg"].as_str().filter(|_| normal) {
+                if linked.insert(pkg) {
+                    pending.push(pkg);
+                }
+            }
+        }
+    }
+
+    let mut licenses: Vec<String> = metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|package| package["id"].as_str().is_some_and(|id| linked.contains(id)))
+        .map(|package| {
+            format!(
+                "{} {} — {}",
+                package["name"].as_str().unwrap_or("?"),
+                package["version"].as_str().unwrap_or("?"),
+                package["license"].as_str().unwrap_or("unknown license")
+            )
+        })
+        .collect();
+    licenses.sort();
+    licenses.dedup();
+    licenses
+}
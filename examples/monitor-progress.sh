@@ -0,0 +1,9 @@
+#!/bin/sh
+# Follow a running Etch write from another terminal or a dashboard.
+#
+# Etch publishes Phase, Device, BytesProcessed, TotalBytes and BytesPerSecond
+# on the session bus and emits Finished("succeeded"|"failed") when an
+# operation ends. This prints one line per change.
+set -eu
+
+gdbus monitor --session --dest org.etch.Etch1 --object-path /org/etch/Etch/Progress
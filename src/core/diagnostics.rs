@@ -0,0 +1,207 @@
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Outcome of a single environment check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    /// Etch works, but something is degraded
+    Warn,
+    /// Writing will fail until this is fixed
+    Fail,
+}
+
+impl CheckStatus {
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Pass => "PASS",
+            Self::Warn => "WARN",
+            Self::Fail => "FAIL",
+        }
+    }
+}
+
+/// One prerequisite with what was found and how to fix it
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// What the user should do; None when the check passed
+    pub remediation: Option<&'static str>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            remediation: None,
+        }
+    }
+
+    fn problem(
+        name: &'static str,
+        status: CheckStatus,
+        detail: impl Into<String>,
+        remediation: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            remediation: Some(remediation),
+        }
+    }
+}
+
+/// Check everything Etch needs from the environment
+///
+/// Shared by the Diagnostics dialog and `etch --doctor`.
+pub fn run_checks() -> Vec<Check> {
+    vec![
+        check_privileges(),
+        check_devices(),
+        check_mount_table(),
+        check_icons(),
+        check_writable(
+            "Device locks",
+            &crate::io::lock::lock_dir(),
+            "Etch cannot stop two instances writing the same device. Run Etch as root so it can use /run/etch.",
+        ),
+        check_writable(
+            "Operation logs",
+            &crate::io::oplog::log_dir(),
+            "Writes work but leave no diagnostic log. Make the directory writable or set XDG_STATE_HOME.",
+        ),
+    ]
+}
+
+/// Whether any check would make a write fail
+pub fn has_failures(checks: &[Check]) -> bool {
+    checks.iter().any(|check| check.status == CheckStatus::Fail)
+}
+
+/// Plain-text report, one check per line with remediation indented below
+pub fn format_report(checks: &[Check]) -> String {
+    let mut report = String::new();
+    for check in checks {
+        let _ = writeln!(
+            report,
+            "[{}] {}: {}",
+            check.status.label(),
+            check.name,
+            check.detail
+        );
+        if let Some(remediation) = check.remediation {
+            let _ = writeln!(report, "       {remediation}");
+        }
+    }
+    report
+}
+
+fn check_privileges() -> Check {
+    const NAME: &str = "Root privileges";
+
+    // SAFETY: geteuid has no preconditions and cannot fail
+    if unsafe { libc::geteuid() } == 0 {
+        Check::pass(NAME, "running as root")
+    } else {
+        Check::problem(
+            NAME,
+            CheckStatus::Fail,
+            "not running as root; block devices cannot be opened for writing",
+            "Start Etch with sudo (sudo -E etch keeps your desktop theme).",
+        )
+    }
+}
+
+fn check_devices() -> Check {
+    const NAME: &str = "Removable devices";
+
+    match crate::io::devices::list_removable_devices() {
+        Ok(devices) if devices.is_empty() => Check::problem(
+            NAME,
+            CheckStatus::Warn,
+            "none detected",
+            "Plug in a USB drive. Card readers only show up with a card inserted.",
+        ),
+        Ok(devices) => Check::pass(
+            NAME,
+            format!(
+                "{} detected via {}",
+                devices.len(),
+                crate::io::devices::probe_enumeration_backend()
+                    .map_or("unknown backend", |backend| backend.label())
+            ),
+        ),
+        Err(e) => Check::problem(
+            NAME,
+            CheckStatus::Fail,
+            format!("{e:#}"),
+            "Etch reads devices from /sys/block, or from lsblk without it; mount sysfs or install util-linux.",
+        ),
+    }
+}
+
+fn check_mount_table() -> Check {
+    const NAME: &str = "Mount table";
+
+    match fs::read_to_string("/proc/mounts") {
+        Ok(_) => Check::pass(NAME, "/proc/mounts readable"),
+        Err(e) => Check::problem(
+            NAME,
+            CheckStatus::Fail,
+            format!("cannot read /proc/mounts: {e}"),
+            "Etch refuses to write mounted devices and needs /proc to tell. Make sure procfs is mounted.",
+        ),
+    }
+}
+
+fn check_icons() -> Check {
+    const NAME: &str = "Symbolic icons";
+
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+
+    let theme = data_dirs
+        .split(':')
+        .map(|dir| PathBuf::from(dir).join("icons/Adwaita"))
+        .find(|theme| theme.join("index.theme").exists());
+
+    match theme {
+        Some(theme) => Check::pass(NAME, format!("Adwaita found in {}", theme.display())),
+        None => Check::problem(
+            NAME,
+            CheckStatus::Warn,
+            "Adwaita icon theme not found; buttons may show missing-icon placeholders",
+            "Install the adwaita-icon-theme package.",
+        ),
+    }
+}
+
+fn check_writable(name: &'static str, dir: &Path, remediation: &'static str) -> Check {
+    let probe = dir.join(format!(".etch-doctor-{}", std::process::id()));
+
+    let result = fs::create_dir_all(dir).and_then(|()| {
+        File::options()
+            .write(true)
+            .create_new(true)
+            .open(&probe)
+            .and_then(|_| fs::remove_file(&probe))
+    });
+
+    match result {
+        Ok(()) => Check::pass(name, format!("{} writable", dir.display())),
+        Err(e) => Check::problem(
+            name,
+            CheckStatus::Warn,
+            format!("{}: {e}", dir.display()),
+            remediation,
+        ),
+    }
+}
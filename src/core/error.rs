@@ -0,0 +1,161 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Failures the UI tells apart, returned inside `anyhow::Error`
+///
+/// Callers find these with `downcast_ref` rather than by matching message
+/// text, so the UI can show a specific, translated explanation. Everything
+/// else stays a plain `anyhow` error with context.
+#[derive(Debug)]
+pub enum EtchError {
+    /// The selected source is a directory, device, fifo or similar
+    NotAnImage {
+        path: PathBuf,
+        kind: &'static str,
+    },
+    /// The image lives on the device it would be written to
+    ImageOnTarget {
+        image: PathBuf,
+        device: PathBuf,
+    },
+    /// The source is empty, or has neither an ISO9660 filesystem nor a boot sector
+    UnrecognizedImage {
+        path: PathBuf,
+        size: u64,
+    },
+    NotBlockDevice(PathBuf),
+    /// The image does not fit on the device
+    DeviceTooSmall {
+        device: PathBuf,
+        device_bytes: u64,
+        image_bytes: u64,
+    },
+    /// The device or one of its partitions is mounted
+    DeviceMounted(PathBuf),
+    /// The device cannot be opened for writing, usually for lack of root
+    PermissionDenied(PathBuf),
+    /// Write-protected, or a self-encrypting drive that is still locked
+    DeviceReadOnly(PathBuf),
+    /// The device dropped out and has no identity to recognise it by on return
+    DeviceDisconnected(PathBuf),
+    /// Another Etch process holds the device lock
+    DeviceBusy {
+        device: PathBuf,
+        pid: u32,
+    },
+    /// The source does not match the checksum the user supplied
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    /// The device or file holds no spanned-write part header
+    NotSpannedPart(PathBuf),
+    /// A part of a different spanned write was inserted
+    PartFromOtherSet(PathBuf),
+    /// A part of this spanned write, but not the next one (both counted from 1)
+    PartOutOfOrder {
+        path: PathBuf,
+        expected: u32,
+        found: u32,
+    },
+    /// A part's data does not match the SHA-256 in its header
+    PartCorrupt {
+        path: PathBuf,
+        index: u32,
+    },
+    /// Stopped through the cancel flag; writes are flushed first
+    Cancelled {
+        bytes_done: u64,
+    },
+}
+
+impl fmt::Display for EtchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAnImage { path, kind } => write!(
+                f,
+                "{} is {kind}. Select a disk image file (.iso or .img).",
+                path.display()
+            ),
+            Self::ImageOnTarget { image, device } => write!(
+                f,
+                "{} is stored on {}. Writing it there would destroy the image while it is being read. Copy the image to another drive first.",
+                image.display(),
+                device.display()
+            ),
+            Self::UnrecognizedImage { path, size: 0 } => {
+                write!(f, "{} is empty", path.display())
+            }
+            Self::UnrecognizedImage { path, size } => write!(
+                f,
+                "{} ({size} bytes) is neither an ISO9660 image nor a disk image",
+                path.display()
+            ),
+            Self::NotBlockDevice(path) => write!(f, "{} is not a block device", path.display()),
+            Self::DeviceTooSmall {
+                device,
+                device_bytes,
+                image_bytes,
+            } => write!(
+                f,
+                "{} is too small: {device_bytes} bytes for a {image_bytes} byte image",
+                device.display()
+            ),
+            Self::DeviceMounted(path) => write!(
+                f,
+                "{} or one of its partitions is currently mounted. Unmount it first.",
+                path.display()
+            ),
+            Self::PermissionDenied(path) => write!(
+                f,
+                "Cannot open {} for writing. Run with sudo/root privileges.",
+                path.display()
+            ),
+            Self::DeviceReadOnly(path) => write!(
+                f,
+                "{} is read-only or locked",
+                path.display()
+            ),
+            Self::DeviceDisconnected(path) => write!(
+                f,
+                "{} disconnected and has no serial number to recognise it by, so the operation cannot safely resume",
+                path.display()
+            ),
+            Self::DeviceBusy { device, pid } => write!(
+                f,
+                "{} is being written by another Etch process (PID {pid})",
+                device.display()
+            ),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Source ISO is corrupt: SHA-256 mismatch. Expected {expected}, got {actual}."
+            ),
+            Self::NotSpannedPart(path) => {
+                write!(f, "{} holds no part of a spanned write", path.display())
+            }
+            Self::PartFromOtherSet(path) => write!(
+                f,
+                "{} holds a part of a different spanned write",
+                path.display()
+            ),
+            Self::PartOutOfOrder {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} holds part {found}, but part {expected} is next",
+                path.display()
+            ),
+            Self::PartCorrupt { path, index } => write!(
+                f,
+                "Part {} on {} does not match its checksum",
+                index + 1,
+                path.display()
+            ),
+            Self::Cancelled { bytes_done } => write!(f, "Cancelled after {bytes_done} bytes"),
+        }
+    }
+}
+
+impl std::error::Error for EtchError {}
@@ -0,0 +1,826 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+/// MBR and GPT entries count 512-byte sectors in an image file
+const SECTOR_SIZE: u64 = 512;
+
+/// Refuse GPT entry arrays larger than this; real tables are 16 KB
+const MAX_GPT_TABLE_BYTES: u64 = 1024 * 1024;
+
+/// Free runs shorter than this are written anyway; skipping them saves little
+const MIN_SKIP: u64 = 1024 * 1024;
+
+/// The ext2/3/4 superblock sits 1024 bytes into the filesystem, whatever the block size
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT4_MAGIC: u16 = 0xEF53;
+
+/// Refuse descriptor tables larger than this (a filesystem of about 256 TiB)
+const MAX_DESCRIPTOR_BYTES: u64 = 64 * 1024 * 1024;
+
+/// `s_state`: cleanly unmounted, and no errors recorded
+const STATE_VALID: u16 = 0x1;
+const STATE_ERRORS: u16 = 0x2;
+
+/// Backup superblocks at the two groups named in the superblock, not at powers of 3, 5, 7
+const COMPAT_SPARSE_SUPER2: u32 = 0x200;
+
+const INCOMPAT_FILETYPE: u32 = 0x2;
+const INCOMPAT_EXTENTS: u32 = 0x40;
+const INCOMPAT_64BIT: u32 = 0x80;
+const INCOMPAT_MMP: u32 = 0x100;
+const INCOMPAT_FLEX_BG: u32 = 0x200;
+const INCOMPAT_EA_INODE: u32 = 0x400;
+const INCOMPAT_DIRDATA: u32 = 0x1000;
+const INCOMPAT_CSUM_SEED: u32 = 0x2000;
+const INCOMPAT_LARGEDIR: u32 = 0x4000;
+const INCOMPAT_INLINE_DATA: u32 = 0x8000;
+const INCOMPAT_ENCRYPT: u32 = 0x10000;
+const INCOMPAT_CASEFOLD: u32 = 0x20000;
+
+/// Incompatible features that leave the bitmap and descriptor layout as read here
+///
+/// Anything else (needs_recovery, meta_bg, an external journal, features newer
+/// than this list) means the map cannot be trusted.
+const KNOWN_INCOMPAT: u32 = INCOMPAT_FILETYPE
+    | INCOMPAT_EXTENTS
+    | INCOMPAT_64BIT
+    | INCOMPAT_MMP
+    | INCOMPAT_FLEX_BG
+    | INCOMPAT_EA_INODE
+    | INCOMPAT_DIRDATA
+    | INCOMPAT_CSUM_SEED
+    | INCOMPAT_LARGEDIR
+    | INCOMPAT_INLINE_DATA
+    | INCOMPAT_ENCRYPT
+    | INCOMPAT_CASEFOLD;
+
+const RO_COMPAT_SPARSE_SUPER: u32 = 0x1;
+const RO_COMPAT_LARGE_FILE: u32 = 0x2;
+const RO_COMPAT_BTREE_DIR: u32 = 0x4;
+const RO_COMPAT_HUGE_FILE: u32 = 0x8;
+const RO_COMPAT_GDT_CSUM: u32 = 0x10;
+const RO_COMPAT_DIR_NLINK: u32 = 0x20;
+const RO_COMPAT_EXTRA_ISIZE: u32 = 0x40;
+const RO_COMPAT_QUOTA: u32 = 0x100;
+const RO_COMPAT_METADATA_CSUM: u32 = 0x400;
+const RO_COMPAT_READONLY: u32 = 0x1000;
+const RO_COMPAT_PROJECT: u32 = 0x2000;
+const RO_COMPAT_VERITY: u32 = 0x8000;
+const RO_COMPAT_ORPHAN_PRESENT: u32 = 0x10000;
+
+/// Read-only features that keep one bitmap bit per block (so not bigalloc)
+const KNOWN_RO_COMPAT: u32 = RO_COMPAT_SPARSE_SUPER
+    | RO_COMPAT_LARGE_FILE
+    | RO_COMPAT_BTREE_DIR
+    | RO_COMPAT_HUGE_FILE
+    | RO_COMPAT_GDT_CSUM
+    | RO_COMPAT_DIR_NLINK
+    | RO_COMPAT_EXTRA_ISIZE
+    | RO_COMPAT_QUOTA
+    | RO_COMPAT_METADATA_CSUM
+    | RO_COMPAT_READONLY
+    | RO_COMPAT_PROJECT
+    | RO_COMPAT_VERITY
+    | RO_COMPAT_ORPHAN_PRESENT;
+
+/// Block group flag: the block bitmap was never written, only the group's own metadata is in use
+///
+/// Only meaningful when descriptors are checksummed.
+const BG_BLOCK_UNINIT: u16 = 0x2;
+
+/// Byte ranges of a raw disk image that hold data, sorted and disjoint
+///
+/// The unused blocks of ext4 filesystems (in MBR or GPT partitions, or a bare
+/// filesystem image) are left out; everything else, including partition
+/// tables, gaps and other filesystems, is kept. `None` when there is nothing
+/// worth skipping or any part of the layout is not fully understood, in which
+/// case the whole image must be written.
+pub fn used_extents(image: &Path) -> Option<Vec<Range<u64>>> {
+    let mut file = File::open(image).ok()?;
+    let size = file.metadata().ok()?.len();
+    used_extents_of(&mut file, size)
+}
+
+fn used_extents_of(image: &mut (impl Read + Seek), size: u64) -> Option<Vec<Range<u64>>> {
+    let mut free = Vec::new();
+    for partition in partitions(image, size)? {
+        free.extend(free_ranges(image, &partition)?);
+    }
+    free.retain(|range| range.end - range.start >= MIN_SKIP);
+
+    let whole_image = 0..size;
+    let extents = subtract(std::slice::from_ref(&whole_image), &normalize(free));
+    (extent_bytes(&extents) < size).then_some(extents)
+}
+
+/// Total length of `extents`
+pub fn extent_bytes(extents: &[Range<u64>]) -> u64 {
+    extents.iter().map(|extent| extent.end - extent.start).sum()
+}
+
+/// The part of the first extent that ends after `offset`, starting no earlier than `offset`
+pub fn next_extent(extents: &[Range<u64>], offset: u64) -> Option<Range<u64>> {
+    let index = extents.partition_point(|extent| extent.end <= offset);
+    extents
+        .get(index)
+        .map(|extent| extent.start.max(offset)..extent.end)
+}
+
+/// The parts of `range` covered by `extents`
+pub fn clip(extents: &[Range<u64>], range: Range<u64>) -> Vec<Range<u64>> {
+    let mut pieces = Vec::new();
+    let mut offset = range.start;
+    while let Some(extent) = next_extent(extents, offset) {
+        if extent.start >= range.end {
+            break;
+        }
+        let end = extent.end.min(range.end);
+        pieces.push(extent.start..end);
+        offset = end;
+    }
+    pieces
+}
+
+/// Partition byte ranges from the MBR or GPT, or the whole image if it has no table
+fn partitions(image: &mut (impl Read + Seek), size: u64) -> Option<Vec<Range<u64>>> {
+    let mut mbr = [0u8; 512];
+    read_at(image, 0, &mut mbr).ok()?;
+    if !crate::core::iso::has_mbr_signature(&mbr) {
+        return Some(std::iter::once(0..size).collect());
+    }
+
+    let mut found = Vec::new();
+    for entry in mbr[446..510].chunks_exact(16) {
+        match entry[4] {
+            0x00 => {}
+            // Protective MBR: the real table is the GPT
+            0xEE => return gpt_partitions(image, size),
+            _ => {
+                let start = u64::from(u32_le(&entry[8..])) * SECTOR_SIZE;
+                let length = u64::from(u32_le(&entry[12..])) * SECTOR_SIZE;
+                found.push(start..start + length);
+            }
+        }
+    }
+    checked_layout(found, size)
+}
+
+fn gpt_partitions(image: &mut (impl Read + Seek), size: u64) -> Option<Vec<Range<u64>>> {
+    let mut header = [0u8; 92];
+    read_at(image, SECTOR_SIZE, &mut header).ok()?;
+    if &header[..8] != b"EFI PART" {
+        return None;
+    }
+
+    let table_start = u64_le(&header[72..]).checked_mul(SECTOR_SIZE)?;
+    let entries = u64::from(u32_le(&header[80..]));
+    let entry_size = u64::from(u32_le(&header[84..]));
+    let table_bytes = entries * entry_size;
+    if entry_size < 128 || table_bytes > MAX_GPT_TABLE_BYTES {
+        return None;
+    }
+
+    let mut table = vec![0u8; usize::try_from(table_bytes).ok()?];
+    read_at(image, table_start, &mut table).ok()?;
+
+    let mut found = Vec::new();
+    for entry in table.chunks_exact(usize::try_from(entry_size).ok()?) {
+        // An all-zero type GUID marks an unused slot
+        if entry[..16].iter().all(|&byte| byte == 0) {
+            continue;
+        }
+        let first = u64_le(&entry[32..]);
+        let last = u64_le(&entry[40..]);
+        if last < first {
+            return None;
+        }
+        found.push(first.checked_mul(SECTOR_SIZE)?..(last + 1).checked_mul(SECTOR_SIZE)?);
+    }
+    checked_layout(found, size)
+}
+
+/// Sorted partitions, or `None` if they overlap or run past the image
+///
+/// Hybrid ISOs nest partitions inside one another; such an image is always
+/// written in full.
+fn checked_layout(mut partitions: Vec<Range<u64>>, size: u64) -> Option<Vec<Range<u64>>> {
+    partitions.sort_by_key(|partition| partition.start);
+    let fits = partitions
+        .iter()
+        .all(|partition| !partition.is_empty() && partition.end <= size);
+    let disjoint = partitions
+        .windows(2)
+        .all(|pair| pair[0].end <= pair[1].start);
+    (fits && disjoint).then_some(partitions)
+}
+
+/// Fields of interest from an ext2/3/4 superblock
+#[derive(Debug, Clone)]
+struct Superblock {
+    block_size: u64,
+    blocks_count: u64,
+    first_data_block: u64,
+    blocks_per_group: u64,
+    inodes_per_group: u64,
+    inode_size: u64,
+    desc_size: u64,
+    reserved_gdt_blocks: u64,
+    sparse_super: bool,
+    /// Whether `BG_BLOCK_UNINIT` may be trusted
+    uninit_flags: bool,
+}
+
+impl Superblock {
+    /// Parse the 1024-byte superblock, or `None` if its layout is not handled
+    fn parse(raw: &[u8]) -> Option<Self> {
+        let state = u16_le(&raw[0x3A..]);
+        let compat = u32_le(&raw[0x5C..]);
+        let incompat = u32_le(&raw[0x60..]);
+        let ro_compat = u32_le(&raw[0x64..]);
+        if state & STATE_VALID == 0
+            || state & STATE_ERRORS != 0
+            || compat & COMPAT_SPARSE_SUPER2 != 0
+            || incompat & !KNOWN_INCOMPAT != 0
+            || ro_compat & !KNOWN_RO_COMPAT != 0
+        {
+            return None;
+        }
+
+        let log_block_size = u32_le(&raw[0x18..]);
+        if log_block_size > 6 {
+            return None;
+        }
+        let block_size = 1024u64 << log_block_size;
+
+        let is_64bit = incompat & INCOMPAT_64BIT != 0;
+        let mut blocks_count = u64::from(u32_le(&raw[0x04..]));
+        if is_64bit {
+            blocks_count |= u64::from(u32_le(&raw[0x150..])) << 32;
+        }
+        let desc_size = if is_64bit {
+            u64::from(u16_le(&raw[0xFE..]))
+        } else {
+            32
+        };
+        if is_64bit && desc_size < 64 {
+            return None;
+        }
+        let inode_size = if u32_le(&raw[0x4C..]) == 0 {
+            128
+        } else {
+            u64::from(u16_le(&raw[0x58..]))
+        };
+
+        let superblock = Self {
+            block_size,
+            blocks_count,
+            first_data_block: u64::from(u32_le(&raw[0x14..])),
+            blocks_per_group: u64::from(u32_le(&raw[0x20..])),
+            inodes_per_group: u64::from(u32_le(&raw[0x28..])),
+            inode_size,
+            desc_size,
+            reserved_gdt_blocks: u64::from(u16_le(&raw[0xCE..])),
+            sparse_super: ro_compat & RO_COMPAT_SPARSE_SUPER != 0,
+            uninit_flags: ro_compat & (RO_COMPAT_GDT_CSUM | RO_COMPAT_METADATA_CSUM) != 0,
+        };
+        superblock.is_consistent().then_some(superblock)
+    }
+
+    /// Whether the geometry is one mke2fs would produce
+    fn is_consistent(&self) -> bool {
+        // Block 0 holds the superblock unless blocks are 1 KB
+        let first_data_block = u64::from(self.block_size == 1024);
+        self.first_data_block == first_data_block
+            && self.blocks_count > self.first_data_block
+            && (1..=self.block_size * 8).contains(&self.blocks_per_group)
+            && self.inodes_per_group > 0
+            && self.inode_size.is_power_of_two()
+            && (128..=self.block_size).contains(&self.inode_size)
+            && self.desc_size.is_power_of_two()
+            && (32..=1024).contains(&self.desc_size)
+    }
+
+    fn groups(&self) -> u64 {
+        (self.blocks_count - self.first_data_block).div_ceil(self.blocks_per_group)
+    }
+
+    /// Blocks taken by the group descriptor table
+    fn descriptor_blocks(&self) -> u64 {
+        (self.groups() * self.desc_size).div_ceil(self.block_size)
+    }
+
+    /// Whether `group` carries a copy of the superblock and descriptors
+    fn has_super(&self, group: u64) -> bool {
+        !self.sparse_super
+            || group <= 1
+            || [3, 5, 7].into_iter().any(|base| is_power_of(group, base))
+    }
+}
+
+/// Where a block group keeps its bitmaps and inode table, and its flags
+#[derive(Debug, Clone)]
+struct GroupDescriptor {
+    block_bitmap: u64,
+    inode_bitmap: u64,
+    inode_table: u64,
+    flags: u16,
+}
+
+impl GroupDescriptor {
+    fn parse(raw: &[u8]) -> Self {
+        let mut descriptor = Self {
+            block_bitmap: u64::from(u32_le(&raw[0x00..])),
+            inode_bitmap: u64::from(u32_le(&raw[0x04..])),
+            inode_table: u64::from(u32_le(&raw[0x08..])),
+            flags: u16_le(&raw[0x12..]),
+        };
+        if raw.len() >= 64 {
+            descriptor.block_bitmap |= u64::from(u32_le(&raw[0x20..])) << 32;
+            descriptor.inode_bitmap |= u64::from(u32_le(&raw[0x24..])) << 32;
+            descriptor.inode_table |= u64::from(u32_le(&raw[0x28..])) << 32;
+        }
+        descriptor
+    }
+}
+
+/// Byte ranges of the unused blocks of the ext4 filesystem starting at `partition`
+///
+/// Empty when the partition holds something else; `None` when it is ext4 but
+/// something about it is not understood, or the image is cut short.
+fn free_ranges(image: &mut (impl Read + Seek), partition: &Range<u64>) -> Option<Vec<Range<u64>>> {
+    let mut raw = [0u8; 1024];
+    if partition.end - partition.start < SUPERBLOCK_OFFSET + 1024
+        || read_at(image, partition.start + SUPERBLOCK_OFFSET, &mut raw).is_err()
+        || u16_le(&raw[0x38..]) != EXT4_MAGIC
+    {
+        return Some(Vec::new());
+    }
+
+    let superblock = Superblock::parse(&raw)?;
+    let block_size = superblock.block_size;
+    if superblock.blocks_count.checked_mul(block_size)? > partition.end - partition.start {
+        return None;
+    }
+
+    // The descriptor table follows the superblock's block
+    let table_bytes = superblock.groups() * superblock.desc_size;
+    if table_bytes > MAX_DESCRIPTOR_BYTES {
+        return None;
+    }
+    let mut table = vec![0u8; usize::try_from(table_bytes).ok()?];
+    let table_offset = partition.start + (superblock.first_data_block + 1) * block_size;
+    read_at(image, table_offset, &mut table).ok()?;
+    let descriptors: Vec<GroupDescriptor> = table
+        .chunks_exact(usize::try_from(superblock.desc_size).ok()?)
+        .map(GroupDescriptor::parse)
+        .collect();
+
+    // Bitmaps and inode tables of every group, wherever flex_bg placed them
+    let table_blocks = (superblock.inodes_per_group * superblock.inode_size).div_ceil(block_size);
+    let mut metadata = Vec::with_capacity(descriptors.len() * 3);
+    for descriptor in &descriptors {
+        metadata.push(descriptor.block_bitmap..descriptor.block_bitmap + 1);
+        metadata.push(descriptor.inode_bitmap..descriptor.inode_bitmap + 1);
+        metadata.push(descriptor.inode_table..descriptor.inode_table + table_blocks);
+    }
+    if metadata.iter().any(|blocks| {
+        blocks.start < superblock.first_data_block || blocks.end > superblock.blocks_count
+    }) {
+        return None;
+    }
+
+    let mut free_blocks = Vec::new();
+    let mut bitmap = vec![0u8; usize::try_from(block_size).ok()?];
+    for (group, descriptor) in (0u64..).zip(&descriptors) {
+        let start = superblock.first_data_block + group * superblock.blocks_per_group;
+        let count = superblock
+            .blocks_per_group
+            .min(superblock.blocks_count - start);
+
+        if superblock.uninit_flags && descriptor.flags & BG_BLOCK_UNINIT != 0 {
+            // Only the superblock and descriptor copies; the bitmaps and
+            // inode table are kept through `metadata` above
+            let reserved = if superblock.has_super(group) {
+                1 + superblock.descriptor_blocks() + superblock.reserved_gdt_blocks
+            } else {
+                0
+            };
+            free_blocks.push(start + reserved.min(count)..start + count);
+            continue;
+        }
+
+        read_at(
+            image,
+            partition.start + descriptor.block_bitmap * block_size,
+            &mut bitmap,
+        )
+        .ok()?;
+        free_blocks.extend(
+            clear_bit_runs(&bitmap, count).map(|bits| start + bits.start..start + bits.end),
+        );
+    }
+
+    let free_blocks = subtract(&normalize(free_blocks), &normalize(metadata));
+    Some(
+        free_blocks
+            .into_iter()
+            .map(|blocks| {
+                partition.start + blocks.start * block_size
+                    ..partition.start + blocks.end * block_size
+            })
+            .collect(),
+    )
+}
+
+/// Runs of clear bits among the first `count` bits of a little-endian bitmap
+fn clear_bit_runs(bitmap: &[u8], count: u64) -> impl Iterator<Item = Range<u64>> + '_ {
+    let is_set = move |bit: u64| {
+        usize::try_from(bit / 8)
+            .ok()
+            .and_then(|byte| bitmap.get(byte))
+            .is_none_or(|byte| byte >> (bit % 8) & 1 == 1)
+    };
+    let mut bit = 0;
+    std::iter::from_fn(move || {
+        while bit < count && is_set(bit) {
+            bit += 1;
+        }
+        if bit == count {
+            return None;
+        }
+        let start = bit;
+        while bit < count && !is_set(bit) {
+            bit += 1;
+        }
+        Some(start..bit)
+    })
+}
+
+fn is_power_of(mut value: u64, base: u64) -> bool {
+    while value.is_multiple_of(base) {
+        value /= base;
+    }
+    value == 1
+}
+
+/// Sort ranges and merge the ones that touch or overlap
+fn normalize(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.retain(|range| !range.is_empty());
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// `ranges` with `holes` cut out; both sorted and disjoint
+fn subtract(ranges: &[Range<u64>], holes: &[Range<u64>]) -> Vec<Range<u64>> {
+    let mut result = Vec::new();
+    let mut holes = holes.iter().peekable();
+    for range in ranges {
+        let mut start = range.start;
+        while let Some(hole) = holes.peek() {
+            if hole.start >= range.end {
+                break;
+            }
+            if hole.start > start {
+                result.push(start..hole.start);
+            }
+            start = start.max(hole.end);
+            // A hole reaching past this range may cut into the next one too
+            if hole.end > range.end {
+                break;
+            }
+            holes.next();
+        }
+        if start < range.end {
+            result.push(start..range.end);
+        }
+    }
+    result
+}
+
+fn read_at(image: &mut (impl Read + Seek), offset: u64, buffer: &mut [u8]) -> std::io::Result<()> {
+    image.seek(SeekFrom::Start(offset))?;
+    image.read_exact(buffer)
+}
+
+fn u16_le(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn u64_le(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes([
+        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const MIB: u64 = 1024 * 1024;
+    const BLOCK: u64 = 4096;
+    const BLOCKS_PER_GROUP: u64 = 1024;
+    const GROUPS: u64 = 4;
+    /// 256 inodes of 256 bytes
+    const TABLE_BLOCKS: u64 = 16;
+
+    /// A 16 MiB ext4 filesystem: four groups of 4 MiB, flex_bg metadata in group 0
+    ///
+    /// Group 0 uses its metadata and blocks 100..110, group 1 its superblock
+    /// backup and blocks 1024..1030; groups 2 and 3 are BLOCK_UNINIT.
+    struct Ext4Image {
+        bytes: Vec<u8>,
+    }
+
+    impl Ext4Image {
+        fn new() -> Self {
+            let mut image = Self {
+                bytes: vec![0; usize::try_from(GROUPS * BLOCKS_PER_GROUP * BLOCK).unwrap()],
+            };
+            let superblock = &mut image.bytes[1024..2048];
+            superblock[0x04..0x08].copy_from_slice(&4096u32.to_le_bytes()); // blocks
+            superblock[0x18] = 2; // 4 KB blocks
+            superblock[0x20..0x24].copy_from_slice(&1024u32.to_le_bytes()); // blocks per group
+            superblock[0x28..0x2C].copy_from_slice(&256u32.to_le_bytes()); // inodes per group
+            superblock[0x38..0x3A].copy_from_slice(&EXT4_MAGIC.to_le_bytes());
+            superblock[0x3A] = 1; // clean
+            superblock[0x4C] = 1; // dynamic revision
+            superblock[0x58..0x5A].copy_from_slice(&256u16.to_le_bytes()); // inode size
+            let incompat = INCOMPAT_FILETYPE | INCOMPAT_EXTENTS | INCOMPAT_FLEX_BG;
+            superblock[0x60..0x64].copy_from_slice(&incompat.to_le_bytes());
+            let ro_compat = RO_COMPAT_SPARSE_SUPER | RO_COMPAT_GDT_CSUM;
+            superblock[0x64..0x68].copy_from_slice(&ro_compat.to_le_bytes());
+
+            for group in 0..GROUPS {
+                let flags = if group >= 2 { BG_BLOCK_UNINIT } else { 0 };
+                image.set_descriptor(
+                    group,
+                    2 + group,
+                    6 + group,
+                    10 + group * TABLE_BLOCKS,
+                    flags,
+                );
+            }
+            // Boot block, superblock, descriptors, bitmaps, inode tables
+            image.mark_used(0, 0..10 + GROUPS * TABLE_BLOCKS);
+            image.mark_used(0, 100..110);
+            // Superblock backup and descriptor copy, then data
+            image.mark_used(1, 1024..1030);
+            image
+        }
+
+        fn set_descriptor(
+            &mut self,
+            group: u64,
+            block_bitmap: u64,
+            inode_bitmap: u64,
+            inode_table: u64,
+            flags: u16,
+        ) {
+            let at = usize::try_from(BLOCK + group * 32).unwrap();
+            let descriptor = &mut self.bytes[at..at + 32];
+            descriptor[0x00..0x04]
+                .copy_from_slice(&u32::try_from(block_bitmap).unwrap().to_le_bytes());
+            descriptor[0x04..0x08]
+                .copy_from_slice(&u32::try_from(inode_bitmap).unwrap().to_le_bytes());
+            descriptor[0x08..0x0C]
+                .copy_from_slice(&u32::try_from(inode_table).unwrap().to_le_bytes());
+            descriptor[0x12..0x14].copy_from_slice(&flags.to_le_bytes());
+        }
+
+        /// Set the bits of `blocks` in `group`'s block bitmap
+        fn mark_used(&mut self, group: u64, blocks: Range<u64>) {
+            let bitmap = (2 + group) * BLOCK;
+            for block in blocks {
+                let bit = block - group * BLOCKS_PER_GROUP;
+                let byte = usize::try_from(bitmap + bit / 8).unwrap();
+                self.bytes[byte] |= 1 << (bit % 8);
+            }
+        }
+
+        fn superblock(&mut self) -> &mut [u8] {
+            &mut self.bytes[1024..2048]
+        }
+    }
+
+    fn blocks(range: Range<u64>) -> Range<u64> {
+        range.start * BLOCK..range.end * BLOCK
+    }
+
+    fn shifted(ranges: &[Range<u64>], by: u64) -> Vec<Range<u64>> {
+        ranges.iter().map(|r| r.start + by..r.end + by).collect()
+    }
+
+    fn extents_of(bytes: &[u8]) -> Option<Vec<Range<u64>>> {
+        used_extents_of(&mut Cursor::new(bytes), bytes.len() as u64)
+    }
+
+    /// `filesystem` in an MBR partition at 1 MiB, followed by 1 MiB of something else
+    fn mbr_image(filesystem: &[u8]) -> Vec<u8> {
+        let mut image = vec![0u8; 512];
+        image[510] = 0x55;
+        image[511] = 0xAA;
+        let entry = &mut image[446..462];
+        entry[4] = 0x83;
+        entry[8..12].copy_from_slice(&2048u32.to_le_bytes());
+        let sectors = u32::try_from(filesystem.len() / 512).unwrap();
+        entry[12..16].copy_from_slice(&sectors.to_le_bytes());
+        image.resize(usize::try_from(MIB).unwrap(), 0);
+        image.extend_from_slice(filesystem);
+        image.resize(image.len() + usize::try_from(MIB).unwrap(), 0xA5);
+        image
+    }
+
+    /// What `Ext4Image::new` keeps, relative to the filesystem start
+    fn expected_filesystem_extents() -> Vec<Range<u64>> {
+        // 74..100 is free but shorter than MIN_SKIP; 3072..3074 is group 3's superblock backup
+        vec![blocks(0..110), blocks(1024..1030), blocks(3072..3074)]
+    }
+
+    #[test]
+    fn unused_blocks_of_a_bare_filesystem_are_skipped() {
+        let image = Ext4Image::new();
+        assert_eq!(
+            extents_of(&image.bytes),
+            Some(expected_filesystem_extents())
+        );
+    }
+
+    #[test]
+    fn mbr_partition_keeps_table_and_trailing_data() {
+        let image = mbr_image(&Ext4Image::new().bytes);
+        let filesystem_end = MIB + GROUPS * BLOCKS_PER_GROUP * BLOCK;
+
+        let mut expected = shifted(&expected_filesystem_extents(), MIB);
+        expected[0].start = 0;
+        expected.push(filesystem_end..filesystem_end + MIB);
+        assert_eq!(extents_of(&image), Some(expected));
+    }
+
+    #[test]
+    fn gpt_partition_is_found() {
+        let filesystem = Ext4Image::new().bytes;
+        let mut image = vec![0u8; usize::try_from(MIB).unwrap()];
+        image[510] = 0x55;
+        image[511] = 0xAA;
+        image[446 + 4] = 0xEE;
+        let header = &mut image[512..604];
+        header[..8].copy_from_slice(b"EFI PART");
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&128u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        // Second slot, to show unused ones are passed over
+        let entry = &mut image[1024 + 128..1024 + 256];
+        entry[..16].copy_from_slice(&[0xAF; 16]);
+        entry[32..40].copy_from_slice(&2048u64.to_le_bytes());
+        let last = 2048 + filesystem.len() as u64 / 512 - 1;
+        entry[40..48].copy_from_slice(&last.to_le_bytes());
+        image.extend_from_slice(&filesystem);
+
+        let mut expected = shifted(&expected_filesystem_extents(), MIB);
+        expected[0].start = 0;
+        assert_eq!(extents_of(&image), Some(expected));
+    }
+
+    #[test]
+    fn uninit_group_keeps_metadata_placed_in_it() {
+        let mut image = Ext4Image::new();
+        // Group 2 keeps its own inode table, as without flex_bg
+        image.set_descriptor(2, 4, 8, 2100, BG_BLOCK_UNINIT);
+
+        assert_eq!(
+            extents_of(&image.bytes),
+            Some(vec![
+                blocks(0..110),
+                blocks(1024..1030),
+                blocks(2100..2116),
+                blocks(3072..3074),
+            ])
+        );
+    }
+
+    #[test]
+    fn uninit_flag_is_ignored_without_checksums() {
+        let mut image = Ext4Image::new();
+        image.superblock()[0x64..0x68].copy_from_slice(&RO_COMPAT_SPARSE_SUPER.to_le_bytes());
+        // Zeroed bitmaps for groups 2 and 3 now count as read: all free
+        assert_eq!(
+            extents_of(&image.bytes),
+            Some(vec![blocks(0..110), blocks(1024..1030)])
+        );
+    }
+
+    #[test]
+    fn group_bitmap_bits_are_read_per_block() {
+        let bitmap = [0b1111_0001, 0b0000_0000, 0b1000_0000];
+        let runs: Vec<_> = clear_bit_runs(&bitmap, 20).collect();
+        assert_eq!(runs, [1..4, 8..20]);
+        let runs: Vec<_> = clear_bit_runs(&bitmap, 24).collect();
+        assert_eq!(runs, [1..4, 8..23]);
+    }
+
+    #[test]
+    fn backup_superblocks_follow_sparse_super() {
+        let superblock = Superblock::parse(&Ext4Image::new().bytes[1024..2048]).unwrap();
+        let groups: Vec<u64> = (0..50).filter(|&g| superblock.has_super(g)).collect();
+        assert_eq!(groups, [0, 1, 3, 5, 7, 9, 25, 27, 49]);
+    }
+
+    #[test]
+    fn unsupported_filesystems_fall_back_to_a_full_write() {
+        type Corruption = fn(&mut [u8]);
+        let cases: [(&str, Corruption); 6] = [
+            ("needs journal recovery", |sb| sb[0x60] |= 0x4),
+            ("meta_bg", |sb| sb[0x60] |= 0x10),
+            ("bigalloc", |sb| sb[0x65] |= 0x2),
+            ("sparse_super2", |sb| sb[0x5D] |= 0x2),
+            ("not cleanly unmounted", |sb| sb[0x3A] = 0),
+            ("larger than its partition", |sb| sb[0x05] = 0x20),
+        ];
+        for (case, corrupt) in cases {
+            let mut image = Ext4Image::new();
+            corrupt(image.superblock());
+            assert_eq!(extents_of(&mbr_image(&image.bytes)), None, "{case}");
+        }
+    }
+
+    #[test]
+    fn descriptor_pointing_outside_the_filesystem_falls_back() {
+        let mut image = Ext4Image::new();
+        image.set_descriptor(3, 5, 9, 5000, BG_BLOCK_UNINIT);
+        assert_eq!(extents_of(&image.bytes), None);
+    }
+
+    #[test]
+    fn truncated_image_falls_back() {
+        let image = mbr_image(&Ext4Image::new().bytes);
+        let cut = &image[..usize::try_from(2 * MIB).unwrap()];
+        assert_eq!(extents_of(cut), None);
+    }
+
+    #[test]
+    fn overlapping_partitions_fall_back() {
+        let mut image = mbr_image(&Ext4Image::new().bytes);
+        // A hybrid-ISO style entry covering the whole image
+        let entry = &mut image[462..478];
+        entry[4] = 0x17;
+        entry[12..16].copy_from_slice(&(2048u32 * 18).to_le_bytes());
+        assert_eq!(extents_of(&image), None);
+    }
+
+    #[test]
+    fn images_without_ext4_have_no_map() {
+        assert_eq!(extents_of(&mbr_image(&vec![0; 16 * 1024 * 1024])), None);
+        assert_eq!(extents_of(&[0; 4096]), None);
+        assert_eq!(extents_of(&[]), None);
+    }
+
+    #[test]
+    fn full_filesystem_has_no_map() {
+        let mut image = Ext4Image::new();
+        image.superblock()[0x64] &= !(RO_COMPAT_GDT_CSUM as u8);
+        for group in 0..GROUPS {
+            image.mark_used(
+                group,
+                group * BLOCKS_PER_GROUP..(group + 1) * BLOCKS_PER_GROUP,
+            );
+        }
+        assert_eq!(extents_of(&image.bytes), None);
+    }
+
+    #[test]
+    fn subtract_cuts_holes_across_ranges() {
+        let ranges = [0..10, 20..30, 40..50];
+        assert_eq!(
+            subtract(&ranges, &[5..25, 28..29, 45..60]),
+            [0..5, 25..28, 29..30, 40..45]
+        );
+        assert_eq!(subtract(&ranges, &[]), ranges);
+        assert_eq!(normalize(vec![5..8, 0..2, 2..3, 7..9, 4..4]), [0..3, 5..9]);
+    }
+
+    #[test]
+    fn extents_are_walked_from_any_offset() {
+        let extents = [0..10, 20..30];
+        assert_eq!(next_extent(&extents, 0), Some(0..10));
+        assert_eq!(next_extent(&extents, 5), Some(5..10));
+        assert_eq!(next_extent(&extents, 10), Some(20..30));
+        assert_eq!(next_extent(&extents, 30), None);
+        assert_eq!(clip(&extents, 5..25), [5..10, 20..25]);
+        assert_eq!(clip(&extents, 10..20), Vec::<Range<u64>>::new());
+        assert_eq!(extent_bytes(&extents), 20);
+    }
+}
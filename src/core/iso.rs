@@ -0,0 +1,508 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// ISO9660 sectors are always 2048 bytes on disk, regardless of the logical block size
+const SECTOR_SIZE: u64 = 2048;
+
+/// The volume descriptor set starts at sector 16 (offset 0x8000)
+const FIRST_DESCRIPTOR_SECTOR: u64 = 16;
+
+/// Stop scanning for descriptors after this many sectors (real images use a handful)
+const MAX_DESCRIPTORS: u64 = 32;
+
+/// Refuse to read directories larger than this; real root directories are a few KB
+const MAX_DIRECTORY_BYTES: u32 = 1024 * 1024;
+
+/// Bootloader found on the image's filesystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bootloader {
+    Isolinux,
+    Syslinux,
+    Grub,
+    WindowsBootmgr,
+}
+
+impl Bootloader {
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Isolinux => "ISOLINUX",
+            Self::Syslinux => "SYSLINUX",
+            Self::Grub => "GRUB",
+            Self::WindowsBootmgr => "Windows Boot Manager",
+        }
+    }
+}
+
+/// Fields of interest from the ISO9660 primary volume descriptor
+#[derive(Debug, Clone)]
+pub struct PrimaryVolumeDescriptor {
+    pub volume_label: String,
+    pub publisher: String,
+    pub volume_space_blocks: u32,
+    pub logical_block_size: u16,
+    pub root_extent: u32,
+    pub root_length: u32,
+}
+
+impl PrimaryVolumeDescriptor {
+    /// Size of the filesystem as declared by the image itself
+    pub const fn volume_size_bytes(&self) -> u64 {
+        self.volume_space_blocks as u64 * self.logical_block_size as u64
+    }
+}
+
+/// One entry of an ISO9660 directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+    pub extent: u32,
+}
+
+/// What Etch could learn about an image without mounting it
+#[derive(Debug, Clone)]
+pub struct IsoInfo {
+    pub file_size: u64,
+    /// None for raw images without an ISO9660 filesystem (e.g. `.img`)
+    pub pvd: Option<PrimaryVolumeDescriptor>,
+    /// MBR boot signature present, so the image boots when written to a USB stick
+    pub is_hybrid: bool,
+    /// El Torito boot record present (bootable as optical media)
+    pub has_el_torito: bool,
+    pub bootloaders: Vec<Bootloader>,
+    /// `/EFI` directory present, so the image boots on UEFI firmware
+    pub has_efi: bool,
+    /// Top-level directory of the ISO9660 filesystem, in image order
+    pub root_entries: Vec<DirEntry>,
+}
+
+impl IsoInfo {
+    /// Bytes missing from the end of the file, if it is shorter than its filesystem
+    ///
+    /// Copying through FAT32 silently cuts files at 4 GiB; such an image still
+    /// "verifies" because verification compares against the truncated file.
+    pub fn missing_bytes(&self) -> Option<u64> {
+        let declared = self.pvd.as_ref()?.volume_size_bytes();
+        (declared > self.file_size).then(|| declared - self.file_size)
+    }
+}
+
+/// Inspect an image file, reading only its descriptors and root directory
+pub fn inspect(path: &Path) -> Result<IsoInfo> {
+    let mut file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+    let file_size = file
+        .metadata()
+        .context("Failed to get image file size")?
+        .len();
+
+    let mut mbr = [0u8; 512];
+    let is_hybrid = read_at(&mut file, 0, &mut mbr).is_ok() && has_mbr_signature(&mbr);
+
+    let mut pvd = None;
+    let mut has_el_torito = false;
+    let mut sector = vec![0u8; SECTOR_SIZE as usize];
+
+    for index in 0..MAX_DESCRIPTORS {
+        let offset = (FIRST_DESCRIPTOR_SECTOR + index) * SECTOR_SIZE;
+        if read_at(&mut file, offset, &mut sector).is_err() || &sector[1..6] != b"CD001" {
+            break;
+        }
+
+        match sector[0] {
+            0 => has_el_torito |= is_el_torito_record(&sector),
+            1 if pvd.is_none() => pvd = parse_primary_volume_descriptor(&sector),
+            255 => break, // Volume descriptor set terminator
+            _ => {}
+        }
+    }
+
+    let (root_entries, bootloaders, has_efi) = match &pvd {
+        Some(pvd) => {
+            let root = read_directory(&mut file, pvd, pvd.root_extent, pvd.root_length)
+                .unwrap_or_default();
+            let has_efi = find_entry(&root, "EFI").is_some_and(|efi| efi.is_dir);
+            let bootloaders = detect_bootloaders(&mut file, pvd, &root);
+            (root, bootloaders, has_efi)
+        }
+        None => (Vec::new(), Vec::new(), false),
+    };
+
+    Ok(IsoInfo {
+        file_size,
+        pvd,
+        is_hybrid,
+        has_el_torito,
+        bootloaders,
+        has_efi,
+        root_entries,
+    })
+}
+
+/// Parse sector 16 of an image; None if it is not an ISO9660 primary descriptor
+pub fn parse_primary_volume_descriptor(sector: &[u8]) -> Option<PrimaryVolumeDescriptor> {
+    if sector.len() < 882 || sector[0] != 1 || &sector[1..6] != b"CD001" {
+        return None;
+    }
+
+    let logical_block_size = u16::from_le_bytes([sector[128], sector[129]]);
+    if logical_block_size == 0 {
+        return None;
+    }
+
+    // Root directory record lives at offset 156
+    let root = &sector[156..190];
+
+    Some(PrimaryVolumeDescriptor {
+        volume_label: text_field(&sector[40..72]),
+        publisher: text_field(&sector[318..446]),
+        volume_space_blocks: u32_le(&sector[80..84]),
+        logical_block_size,
+        root_extent: u32_le(&root[2..6]),
+        root_length: u32_le(&root[10..14]),
+    })
+}
+
+/// Parse the records of a directory extent
+///
+/// Never panics on malformed input: zero-length records, and records that
+/// would cross a sector boundary or the end of the data, end the current
+/// sector and parsing continues at the next one. The extents of a
+/// multi-extent file (over 4 GiB) are merged into one entry.
+pub fn parse_directory_records(data: &[u8]) -> Vec<DirEntry> {
+    let sector = SECTOR_SIZE as usize;
+    let mut entries: Vec<DirEntry> = Vec::new();
+    let mut offset = 0usize;
+    // The previous record said the file continues in the next one
+    let mut continued = false;
+
+    while offset < data.len() {
+        let record_length = data[offset] as usize;
+        let sector_end = (offset / sector + 1) * sector;
+
+        // Records never span sectors; a zero length means padding until the next one
+        let record = data
+            .get(offset..offset + record_length)
+            .filter(|_| record_length > 0 && offset + record_length <= sector_end);
+        let Some(record) = record else {
+            offset = sector_end;
+            continued = false;
+            continue;
+        };
+        offset += record_length;
+
+        if record.len() < 34 {
+            continue;
+        }
+        let name_length = record[32] as usize;
+        let Some(raw_name) = record.get(33..33 + name_length) else {
+            continue;
+        };
+
+        // Skip the "." and ".." entries
+        if raw_name == [0] || raw_name == [1] {
+            continue;
+        }
+
+        let name = entry_name(raw_name);
+        let size = u64::from(u32_le(&record[10..14]));
+        let flags = record[25];
+        let was_continued = std::mem::replace(&mut continued, flags & 0x80 != 0);
+        if was_continued {
+            if let Some(last) = entries.last_mut().filter(|last| last.name == name) {
+                last.size += size;
+                continue;
+            }
+        }
+
+        entries.push(DirEntry {
+            name,
+            size,
+            is_dir: flags & 0x02 != 0,
+            extent: u32_le(&record[2..6]),
+        });
+    }
+
+    entries
+}
+
+/// Whether a 512-byte boot sector carries the 0x55AA signature
+pub fn has_mbr_signature(sector: &[u8]) -> bool {
+    sector.len() >= 512 && sector[510] == 0x55 && sector[511] == 0xAA
+}
+
+fn is_el_torito_record(sector: &[u8]) -> bool {
+    sector.get(7..30) == Some(b"EL TORITO SPECIFICATION".as_slice())
+}
+
+fn find_entry<'a>(entries: &'a [DirEntry], name: &str) -> Option<&'a DirEntry> {
+    entries.iter().find(|e| e.name.eq_ignore_ascii_case(name))
+}
+
+fn detect_bootloaders(
+    file: &mut File,
+    pvd: &PrimaryVolumeDescriptor,
+    root: &[DirEntry],
+) -> Vec<Bootloader> {
+    let mut found = Vec::new();
+    let has = find_entry;
+
+    if has(root, "ISOLINUX").is_some() {
+        found.push(Bootloader::Isolinux);
+    }
+    if has(root, "SYSLINUX").is_some() {
+        found.push(Bootloader::Syslinux);
+    }
+    if has(root, "BOOTMGR").is_some() {
+        found.push(Bootloader::WindowsBootmgr);
+    }
+
+    // GRUB lives in /boot/grub
+    let grub_in_boot = has(root, "BOOT")
+        .filter(|boot| boot.is_dir)
+        .and_then(|boot| {
+            let length = u32::try_from(boot.size).ok()?;
+            read_directory(file, pvd, boot.extent, length).ok()
+        })
+        .is_some_and(|boot| has(&boot, "GRUB").is_some());
+    if grub_in_boot {
+        found.push(Bootloader::Grub);
+    }
+
+    found
+}
+
+fn read_directory(
+    file: &mut File,
+    pvd: &PrimaryVolumeDescriptor,
+    extent: u32,
+    length: u32,
+) -> Result<Vec<DirEntry>> {
+    if length > MAX_DIRECTORY_BYTES {
+        anyhow::bail!("Directory is implausibly large ({length} bytes)");
+    }
+
+    let mut data = vec![0u8; length as usize];
+    let offset = u64::from(extent) * u64::from(pvd.logical_block_size);
+    read_at(file, offset, &mut data).context("Failed to read directory")?;
+
+    Ok(parse_directory_records(&data))
+}
+
+fn read_at(file: &mut File, offset: u64, buffer: &mut [u8]) -> std::io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buffer)
+}
+
+fn u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Space-padded descriptor text field
+fn text_field(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches([' ', '\0'])
+        .to_string()
+}
+
+/// "BOOTMGR.;1" -> "BOOTMGR", "README.TXT;1" -> "README.TXT"
+fn entry_name(raw: &[u8]) -> String {
+    let name = String::from_utf8_lossy(raw);
+    let name = name.split(';').next().unwrap_or_default();
+    name.strip_suffix('.').unwrap_or(name).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const SECTOR: usize = SECTOR_SIZE as usize;
+
+    /// An image with a primary volume descriptor declaring `blocks` sectors
+    fn iso_image(label: &str, blocks: u32) -> Vec<u8> {
+        let mut image = vec![0u8; 18 * SECTOR];
+
+        let pvd = &mut image[16 * SECTOR..17 * SECTOR];
+        pvd[0] = 1;
+        pvd[1..6].copy_from_slice(b"CD001");
+        pvd[6] = 1;
+        pvd[40..72].fill(b' ');
+        pvd[40..40 + label.len()].copy_from_slice(label.as_bytes());
+        pvd[80..84].copy_from_slice(&blocks.to_le_bytes());
+        pvd[128..130].copy_from_slice(&2048u16.to_le_bytes());
+        pvd[156] = 34;
+        pvd[158..162].copy_from_slice(&18u32.to_le_bytes());
+        pvd[166..170].copy_from_slice(&0u32.to_le_bytes());
+        pvd[181] = 0x02;
+
+        let terminator = &mut image[17 * SECTOR..18 * SECTOR];
+        terminator[0] = 255;
+        terminator[1..6].copy_from_slice(b"CD001");
+        image
+    }
+
+    fn inspect_bytes(bytes: &[u8]) -> IsoInfo {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(bytes).unwrap();
+        inspect(file.path()).unwrap()
+    }
+
+    /// A directory record for `name` with the given size and flags
+    fn record(name: &[u8], extent: u32, size: u32, flags: u8) -> Vec<u8> {
+        let mut record = vec![0u8; 33 + name.len() + (name.len() + 1) % 2];
+        record[0] = u8::try_from(record.len()).unwrap();
+        record[2..6].copy_from_slice(&extent.to_le_bytes());
+        record[10..14].copy_from_slice(&size.to_le_bytes());
+        record[25] = flags;
+        record[32] = u8::try_from(name.len()).unwrap();
+        record[33..33 + name.len()].copy_from_slice(name);
+        record
+    }
+
+    #[test]
+    fn directory_records_are_listed_without_dot_entries() {
+        let mut data = Vec::new();
+        data.extend(record(&[0], 18, 2048, 0x02));
+        data.extend(record(&[1], 18, 2048, 0x02));
+        data.extend(record(b"EFI", 20, 2048, 0x02));
+        data.extend(record(b"README.TXT;1", 21, 42, 0));
+        data.resize(SECTOR, 0);
+
+        let entries = parse_directory_records(&data);
+        assert_eq!(
+            entries,
+            [
+                DirEntry {
+                    name: "EFI".to_string(),
+                    size: 2048,
+                    is_dir: true,
+                    extent: 20,
+                },
+                DirEntry {
+                    name: "README.TXT".to_string(),
+                    size: 42,
+                    is_dir: false,
+                    extent: 21,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_length_record_skips_to_the_next_sector() {
+        let mut data = record(b"FIRST.;1", 20, 1, 0);
+        data.resize(SECTOR, 0);
+        data.extend(record(b"SECOND.;1", 21, 1, 0));
+
+        let names: Vec<String> = parse_directory_records(&data)
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["FIRST", "SECOND"]);
+    }
+
+    #[test]
+    fn name_past_the_record_end_is_skipped() {
+        let mut broken = record(b"BROKEN.;1", 20, 1, 0);
+        broken[32] = 200;
+        let mut data = broken;
+        data.extend(record(b"GOOD.;1", 21, 1, 0));
+
+        let names: Vec<String> = parse_directory_records(&data)
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["GOOD"]);
+    }
+
+    #[test]
+    fn record_crossing_a_sector_is_skipped() {
+        // Eight 255-byte records fill the sector up to byte 2040
+        let mut filler = record(b"FILLER.;1", 20, 1, 0);
+        filler.resize(255, 0);
+        filler[0] = 255;
+        let mut data = filler.repeat(8);
+        // A record starting there would end past the sector boundary
+        data.extend(&record(b"STRADDLE.;1", 21, 1, 0)[..8]);
+        data.extend(record(b"NEXT.;1", 22, 1, 0));
+
+        let names: Vec<String> = parse_directory_records(&data)
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names.len(), 9);
+        assert_eq!(names.last().map(String::as_str), Some("NEXT"));
+    }
+
+    #[test]
+    fn truncated_and_garbage_data_never_panics() {
+        let full = record(b"FILE.;1", 20, 1, 0);
+        for cut in 0..full.len() {
+            parse_directory_records(&full[..cut]);
+        }
+        let garbage: Vec<u8> = (0..=255u8).cycle().take(3 * SECTOR).collect();
+        parse_directory_records(&garbage);
+    }
+
+    #[test]
+    fn multi_extent_file_is_one_entry() {
+        let mut data = Vec::new();
+        data.extend(record(b"BIG.ISO;1", 100, 0xFFFF_F800, 0x80));
+        data.extend(record(b"BIG.ISO;1", 100 + 0x1F_FFFF, 0x1000, 0));
+        data.extend(record(b"SMALL.;1", 200, 7, 0));
+        data.resize(SECTOR, 0);
+
+        let entries = parse_directory_records(&data);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "BIG.ISO");
+        assert_eq!(entries[0].size, 0xFFFF_F800 + 0x1000);
+        assert_eq!(entries[0].extent, 100);
+        assert_eq!(entries[1].size, 7);
+    }
+
+    #[test]
+    fn empty_file_has_no_structure() {
+        let info = inspect_bytes(&[]);
+        assert_eq!(info.file_size, 0);
+        assert!(info.pvd.is_none());
+        assert!(!info.is_hybrid);
+    }
+
+    #[test]
+    fn file_ending_before_sector_16_has_no_descriptor() {
+        let mut image = iso_image("CUT", 18);
+        image.truncate(16 * SECTOR + 100);
+        let info = inspect_bytes(&image);
+        assert!(info.pvd.is_none());
+        assert_eq!(info.missing_bytes(), None);
+    }
+
+    #[test]
+    fn bare_mbr_signature_is_hybrid_without_filesystem() {
+        let mut image = vec![0u8; 512];
+        image[510] = 0x55;
+        image[511] = 0xAA;
+        let info = inspect_bytes(&image);
+        assert!(info.is_hybrid);
+        assert!(info.pvd.is_none());
+    }
+
+    #[test]
+    fn valid_descriptor_is_parsed() {
+        let info = inspect_bytes(&iso_image("ETCH_TEST", 18));
+        let pvd = info.pvd.as_ref().unwrap();
+        assert_eq!(pvd.volume_label, "ETCH_TEST");
+        assert_eq!(pvd.volume_size_bytes(), 18 * SECTOR_SIZE);
+        assert_eq!(info.missing_bytes(), None);
+    }
+
+    #[test]
+    fn file_shorter_than_its_volume_reports_missing_bytes() {
+        // A 4 GiB FAT32 cut leaves the descriptor claiming more than the file holds
+        let info = inspect_bytes(&iso_image("CUT", 20));
+        assert_eq!(info.missing_bytes(), Some(2 * SECTOR_SIZE));
+    }
+}
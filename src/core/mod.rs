@@ -1,3 +1,10 @@
 /// Core domain types and business logic
+pub mod diagnostics;
+pub mod error;
+pub mod extents;
+pub mod iso;
 pub mod models;
+pub mod safety;
+pub mod span;
+pub mod support_bundle;
 pub mod verification;
@@ -9,6 +9,10 @@ pub struct BlockDevice {
     pub vendor: String,
     pub capacity_bytes: u64,
     pub is_removable: bool,
+    /// A partition of a removable disk rather than the whole disk
+    pub is_partition: bool,
+    /// Laid out by Ventoy, so images can be added without erasing it
+    pub is_ventoy: bool,
 }
 
 #[allow(dead_code)]
@@ -0,0 +1,314 @@
+use crate::core::error::EtchError;
+use crate::core::iso::IsoInfo;
+use anyhow::{Context, Result};
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// Device-mapper and md stacks are shallow; this only guards against cycles
+const MAX_STACK_DEPTH: usize = 8;
+
+/// Recognised images smaller than this are worth a warning; installers are larger
+pub const SMALL_IMAGE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Reject anything that is not a regular file as the source image
+///
+/// The file chooser accepts typed paths, so `/dev/sdb` or a fifo can end up
+/// selected as the "ISO".
+pub fn validate_iso_selection(path: &Path) -> Result<()> {
+    let metadata = fs::metadata(path).context(format!("{} does not exist", path.display()))?;
+    let file_type = metadata.file_type();
+
+    let kind = if file_type.is_file() {
+        return Ok(());
+    } else if file_type.is_dir() {
+        "a directory"
+    } else if file_type.is_block_device() {
+        "a block device"
+    } else if file_type.is_char_device() {
+        "a character device"
+    } else if file_type.is_fifo() {
+        "a named pipe"
+    } else if file_type.is_socket() {
+        "a socket"
+    } else {
+        "not a regular file"
+    };
+
+    Err(EtchError::NotAnImage {
+        path: path.to_path_buf(),
+        kind,
+    }
+    .into())
+}
+
+/// Refuse a source with no recognisable image structure
+///
+/// Bootable images carry an ISO9660 volume descriptor or an MBR boot
+/// signature (GPT disks keep a protective MBR), so a file with neither is a
+/// failed download or a text file renamed to `.iso`.
+pub fn ensure_recognized_image(path: &Path, info: &IsoInfo) -> Result<()> {
+    if info.file_size == 0 || (info.pvd.is_none() && !info.is_hybrid) {
+        return Err(EtchError::UnrecognizedImage {
+            path: path.to_path_buf(),
+            size: info.file_size,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Refuse a target that is smaller than the image
+///
+/// Checked before confirming; the writer checks again before writing.
+pub fn ensure_fits(source_iso: &Path, target_device: &Path) -> Result<()> {
+    let image_bytes = fs::metadata(source_iso)
+        .context(format!("Failed to stat {}", source_iso.display()))?
+        .len();
+    let mut device = fs::File::open(target_device)
+        .context(format!("Failed to open {}", target_device.display()))?;
+    let device_bytes = crate::io::devices::block_device_size(&mut device).context(format!(
+        "Failed to get the size of {}",
+        target_device.display()
+    ))?;
+
+    if image_bytes > device_bytes {
+        return Err(EtchError::DeviceTooSmall {
+            device: target_device.to_path_buf(),
+            device_bytes,
+            image_bytes,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Refuse a target that holds the filesystem the image is stored on
+///
+/// Both sides are resolved to their whole disks (following partitions and
+/// device-mapper/md slaves), so an ISO on `/dev/sdb1` or on an LVM volume
+/// backed by `/dev/sdb` blocks a write to `/dev/sdb`. Filesystems without a
+/// backing block device (tmpfs, network mounts) never match.
+pub fn ensure_not_hosting(source_iso: &Path, target_device: &Path) -> Result<()> {
+    let iso_dev = fs::metadata(source_iso)
+        .context(format!("Failed to stat {}", source_iso.display()))?
+        .dev();
+    let target_dev = fs::metadata(target_device)
+        .context(format!("Failed to stat {}", target_device.display()))?
+        .rdev();
+
+    let iso_disks = backing_disks(iso_dev);
+    let target_disks = backing_disks(target_dev);
+
+    if iso_disks.iter().any(|disk| target_disks.contains(disk)) {
+        return Err(EtchError::ImageOnTarget {
+            image: source_iso.to_path_buf(),
+            device: target_device.to_path_buf(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Whole-disk sysfs directories underlying a device number
+fn backing_disks(dev: u64) -> Vec<PathBuf> {
+    backing_disks_in(Path::new("/sys/dev/block"), dev)
+}
+
+/// `backing_disks` below a given `/sys/dev/block`
+fn backing_disks_in(dev_block: &Path, dev: u64) -> Vec<PathBuf> {
+    let node = dev_block.join(format!("{}:{}", libc::major(dev), libc::minor(dev)));
+
+    let mut disks = Vec::new();
+    if let Ok(node) = fs::canonicalize(node) {
+        collect_disks(&node, 0, &mut disks);
+    }
+    disks
+}
+
+fn collect_disks(node: &Path, depth: usize, disks: &mut Vec<PathBuf>) {
+    if depth > MAX_STACK_DEPTH {
+        return;
+    }
+
+    // Stacked devices (dm-crypt, LVM, md) point at what they are built from
+    if let Ok(slaves) = fs::read_dir(node.join("slaves")) {
+        let slaves: Vec<PathBuf> = slaves
+            .flatten()
+            .filter_map(|entry| fs::canonicalize(entry.path()).ok())
+            .collect();
+        if !slaves.is_empty() {
+            for slave in slaves {
+                collect_disks(&slave, depth + 1, disks);
+            }
+            return;
+        }
+    }
+
+    // A partition's sysfs directory sits inside its disk's directory
+    let disk = if node.join("partition").exists() {
+        node.parent().unwrap_or(node)
+    } else {
+        node
+    };
+
+    if !disks.iter().any(|known| known == disk) {
+        disks.push(disk.to_path_buf());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn image_of(bytes: u64) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        file.as_file().set_len(bytes).unwrap();
+        file
+    }
+
+    fn not_an_image_kind(path: &Path) -> &'static str {
+        let error = validate_iso_selection(path).unwrap_err();
+        match error.downcast_ref::<EtchError>() {
+            Some(EtchError::NotAnImage { kind, .. }) => kind,
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn regular_file_is_a_valid_selection() {
+        let image = image_of(4096);
+        assert!(validate_iso_selection(image.path()).is_ok());
+    }
+
+    #[test]
+    fn directories_and_fifos_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(not_an_image_kind(dir.path()), "a directory");
+
+        let fifo = dir.path().join("fifo");
+        let name = std::ffi::CString::new(fifo.as_os_str().as_encoded_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o600) }, 0);
+        assert_eq!(not_an_image_kind(&fifo), "a named pipe");
+    }
+
+    #[test]
+    fn missing_file_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(validate_iso_selection(&dir.path().join("missing.iso")).is_err());
+    }
+
+    fn info(file_size: u64, has_pvd: bool, is_hybrid: bool) -> IsoInfo {
+        let pvd = has_pvd.then(|| crate::core::iso::PrimaryVolumeDescriptor {
+            volume_label: "ETCH_TEST".to_string(),
+            publisher: String::new(),
+            volume_space_blocks: 18,
+            logical_block_size: 2048,
+            root_extent: 18,
+            root_length: 2048,
+        });
+        IsoInfo {
+            file_size,
+            pvd,
+            is_hybrid,
+            has_el_torito: false,
+            bootloaders: Vec::new(),
+            has_efi: false,
+            root_entries: Vec::new(),
+        }
+    }
+
+    fn unrecognized_size(info: &IsoInfo) -> Option<u64> {
+        let error = ensure_recognized_image(Path::new("image.iso"), info).err()?;
+        match error.downcast_ref::<EtchError>() {
+            Some(EtchError::UnrecognizedImage { size, .. }) => Some(*size),
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn empty_image_is_unrecognized() {
+        assert_eq!(unrecognized_size(&info(0, false, false)), Some(0));
+        assert_eq!(unrecognized_size(&info(0, true, true)), Some(0));
+    }
+
+    #[test]
+    fn image_without_descriptor_or_signature_is_unrecognized() {
+        assert_eq!(unrecognized_size(&info(32_868, false, false)), Some(32_868));
+    }
+
+    #[test]
+    fn boot_signature_or_descriptor_is_enough() {
+        assert_eq!(unrecognized_size(&info(512, false, true)), None);
+        assert_eq!(unrecognized_size(&info(36_864, true, false)), None);
+    }
+
+    #[test]
+    fn image_fits_on_a_target_of_equal_size() {
+        let (image, target) = (image_of(1 << 20), image_of(1 << 20));
+        assert!(ensure_fits(image.path(), target.path()).is_ok());
+    }
+
+    #[test]
+    fn image_larger_than_target_is_refused() {
+        let (image, target) = (image_of((1 << 20) + 1), image_of(1 << 20));
+        let error = ensure_fits(image.path(), target.path()).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<EtchError>(),
+            Some(EtchError::DeviceTooSmall {
+                device_bytes: 1_048_576,
+                image_bytes: 1_048_577,
+                ..
+            })
+        ));
+    }
+
+    /// A sysfs-like tree: sda with partition sda1, and dm-0 built on sda1
+    fn fake_sysfs() -> (tempfile::TempDir, PathBuf) {
+        let root = tempfile::tempdir().unwrap();
+        let base = fs::canonicalize(root.path()).unwrap();
+        let sda = base.join("devices/pci/block/sda");
+        fs::create_dir_all(sda.join("sda1")).unwrap();
+        fs::write(sda.join("sda1/partition"), "1\n").unwrap();
+        let dm = base.join("devices/virtual/block/dm-0");
+        fs::create_dir_all(dm.join("slaves")).unwrap();
+        symlink(sda.join("sda1"), dm.join("slaves/sda1")).unwrap();
+
+        let dev_block = base.join("dev/block");
+        fs::create_dir_all(&dev_block).unwrap();
+        symlink(&sda, dev_block.join("8:0")).unwrap();
+        symlink(sda.join("sda1"), dev_block.join("8:1")).unwrap();
+        symlink(&dm, dev_block.join("253:0")).unwrap();
+        (root, sda)
+    }
+
+    #[test]
+    fn partition_resolves_to_its_disk() {
+        let (root, sda) = fake_sysfs();
+        let dev_block = root.path().join("dev/block");
+        for minor in [0, 1] {
+            assert_eq!(
+                backing_disks_in(&dev_block, libc::makedev(8, minor)),
+                [sda.as_path()]
+            );
+        }
+    }
+
+    #[test]
+    fn stacked_device_resolves_through_its_slaves() {
+        let (root, sda) = fake_sysfs();
+        let dev_block = root.path().join("dev/block");
+        assert_eq!(backing_disks_in(&dev_block, libc::makedev(253, 0)), [sda]);
+    }
+
+    #[test]
+    fn unknown_device_has_no_backing_disk() {
+        let (root, _) = fake_sysfs();
+        let dev_block = root.path().join("dev/block");
+        assert!(backing_disks_in(&dev_block, libc::makedev(0, 42)).is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+use crate::core::error::EtchError;
+use sha2::{Digest, Sha256};
+use std::ops::Range;
+use std::path::Path;
+
+/// Each device starts with one header block; the part's data follows it
+pub const HEADER_SIZE: u64 = 4096;
+
+/// Parts are cut on whole MiB
+const PART_ALIGNMENT: u64 = 1024 * 1024;
+
+/// More devices than anyone would swap by hand
+pub const MAX_PARTS: u64 = 64;
+
+const MAGIC: &[u8; 8] = b"ETCHSPAN";
+const VERSION: u32 = 1;
+
+/// Bytes of the header covered by its own check value
+const CHECKED_BYTES: usize = 88;
+
+/// What a spanned-write device says about the part it holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartHeader {
+    /// Shared by every part of one spanned write
+    pub set_id: u64,
+    /// Counting from 0
+    pub index: u32,
+    pub total: u32,
+    pub image_size: u64,
+    /// Where the part's data belongs in the image
+    pub offset: u64,
+    pub length: u64,
+    /// SHA-256 of the part's data
+    pub sha256: [u8; 32],
+}
+
+impl PartHeader {
+    /// The `HEADER_SIZE` block written in front of the part's data
+    ///
+    /// Magic, version, index, total, set id, image size, offset, length and
+    /// SHA-256, little-endian, followed by the first 8 bytes of the SHA-256
+    /// of all that; the rest is zero.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut block = Vec::with_capacity(HEADER_SIZE as usize);
+        block.extend_from_slice(MAGIC);
+        block.extend_from_slice(&VERSION.to_le_bytes());
+        block.extend_from_slice(&self.index.to_le_bytes());
+        block.extend_from_slice(&self.total.to_le_bytes());
+        block.extend_from_slice(&[0; 4]);
+        block.extend_from_slice(&self.set_id.to_le_bytes());
+        block.extend_from_slice(&self.image_size.to_le_bytes());
+        block.extend_from_slice(&self.offset.to_le_bytes());
+        block.extend_from_slice(&self.length.to_le_bytes());
+        block.extend_from_slice(&self.sha256);
+        let check = Sha256::digest(&block[..CHECKED_BYTES]);
+        block.extend_from_slice(&check[..8]);
+        block.resize(HEADER_SIZE as usize, 0);
+        block
+    }
+
+    /// Parse a header block, or `None` if it is not a complete, consistent one
+    pub fn decode(block: &[u8]) -> Option<Self> {
+        if block.len() < CHECKED_BYTES + 8
+            || &block[..8] != MAGIC
+            || u32_le(&block[8..]) != VERSION
+            || Sha256::digest(&block[..CHECKED_BYTES])[..8]
+                != block[CHECKED_BYTES..CHECKED_BYTES + 8]
+        {
+            return None;
+        }
+
+        let header = Self {
+            index: u32_le(&block[12..]),
+            total: u32_le(&block[16..]),
+            set_id: u64_le(&block[24..]),
+            image_size: u64_le(&block[32..]),
+            offset: u64_le(&block[40..]),
+            length: u64_le(&block[48..]),
+            sha256: block[56..88].try_into().ok()?,
+        };
+        let fits = header
+            .offset
+            .checked_add(header.length)
+            .is_some_and(|end| end <= header.image_size);
+        (header.index < header.total && header.length > 0 && fits).then_some(header)
+    }
+
+    pub fn sha256_hex(&self) -> String {
+        self.sha256
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// Check that this is part `index` of the set `first` belongs to
+    ///
+    /// Parts must come in order, so each one is checked against where the
+    /// previous one ended.
+    pub fn check_sequence(
+        &self,
+        path: &Path,
+        first: Option<&Self>,
+        index: u32,
+        offset: u64,
+    ) -> Result<(), EtchError> {
+        if let Some(first) = first {
+            if (self.set_id, self.total, self.image_size)
+                != (first.set_id, first.total, first.image_size)
+            {
+                return Err(EtchError::PartFromOtherSet(path.to_path_buf()));
+            }
+        }
+        if self.index != index {
+            return Err(EtchError::PartOutOfOrder {
+                path: path.to_path_buf(),
+                expected: index + 1,
+                found: self.index + 1,
+            });
+        }
+        if self.offset != offset {
+            return Err(EtchError::NotSpannedPart(path.to_path_buf()));
+        }
+        Ok(())
+    }
+}
+
+/// The image range each device holds when every device has `device_bytes`
+///
+/// `None` if the device cannot hold a part, or the image would need more
+/// than `MAX_PARTS` of them. Later devices may be larger, but not smaller.
+pub fn plan_parts(image_size: u64, device_bytes: u64) -> Option<Vec<Range<u64>>> {
+    let capacity = device_bytes.checked_sub(HEADER_SIZE)? / PART_ALIGNMENT * PART_ALIGNMENT;
+    if capacity == 0 || image_size == 0 {
+        return None;
+    }
+    let parts = image_size.div_ceil(capacity);
+    if parts > MAX_PARTS {
+        return None;
+    }
+    Some(
+        (0..parts)
+            .map(|part| part * capacity..((part + 1) * capacity).min(image_size))
+            .collect(),
+    )
+}
+
+fn u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn u64_le(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes([
+        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn header() -> PartHeader {
+        PartHeader {
+            set_id: 0x0123_4567_89ab_cdef,
+            index: 1,
+            total: 3,
+            image_size: 70 * MIB,
+            offset: 31 * MIB,
+            length: 31 * MIB,
+            sha256: [0xAB; 32],
+        }
+    }
+
+    #[test]
+    fn header_round_trips_through_one_block() {
+        let block = header().encode();
+        assert_eq!(block.len() as u64, HEADER_SIZE);
+        assert_eq!(&block[..8], b"ETCHSPAN");
+        assert_eq!(PartHeader::decode(&block), Some(header()));
+        assert_eq!(header().sha256_hex(), "ab".repeat(32));
+    }
+
+    #[test]
+    fn damaged_or_foreign_blocks_are_not_headers() {
+        let block = header().encode();
+        for position in [0, 8, 12, 40, 60, 90] {
+            let mut damaged = block.clone();
+            damaged[position] ^= 0x01;
+            assert_eq!(PartHeader::decode(&damaged), None, "byte {position}");
+        }
+        assert_eq!(PartHeader::decode(&block[..64]), None);
+        assert_eq!(PartHeader::decode(&[0; HEADER_SIZE as usize]), None);
+    }
+
+    #[test]
+    fn inconsistent_headers_are_rejected() {
+        let index_past_total = PartHeader {
+            index: 3,
+            ..header()
+        };
+        let past_the_image = PartHeader {
+            length: 40 * MIB,
+            ..header()
+        };
+        let empty = PartHeader {
+            length: 0,
+            ..header()
+        };
+        for bad in [index_past_total, past_the_image, empty] {
+            assert_eq!(PartHeader::decode(&bad.encode()), None, "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn parts_fill_each_device_on_whole_mib() {
+        // 32 MiB devices hold 31 MiB each: the header takes part of the last MiB
+        let parts = plan_parts(70 * MIB, 32 * MIB).unwrap();
+        assert_eq!(parts, [0..31 * MIB, 31 * MIB..62 * MIB, 62 * MIB..70 * MIB]);
+
+        assert_eq!(plan_parts(31 * MIB, 32 * MIB).unwrap().len(), 1);
+        assert_eq!(plan_parts(31 * MIB + 1, 32 * MIB).unwrap().len(), 2);
+        assert_eq!(
+            plan_parts(MIB + 1, MIB + HEADER_SIZE).unwrap(),
+            [0..MIB, MIB..MIB + 1]
+        );
+    }
+
+    #[test]
+    fn unusable_plans_are_refused() {
+        assert_eq!(plan_parts(10 * MIB, MIB), None);
+        assert_eq!(plan_parts(10 * MIB, HEADER_SIZE - 1), None);
+        assert_eq!(plan_parts(0, 32 * MIB), None);
+        assert_eq!(plan_parts(MAX_PARTS * MIB + 1, MIB + HEADER_SIZE), None);
+        assert_eq!(
+            plan_parts(MAX_PARTS * MIB, MIB + HEADER_SIZE).map(|parts| parts.len()),
+            Some(MAX_PARTS as usize)
+        );
+    }
+
+    #[test]
+    fn parts_must_come_in_order_from_one_set() {
+        let path = Path::new("/dev/sdz");
+        let first = PartHeader {
+            index: 0,
+            offset: 0,
+            ..header()
+        };
+        assert!(first.check_sequence(path, None, 0, 0).is_ok());
+        assert!(header()
+            .check_sequence(path, Some(&first), 1, 31 * MIB)
+            .is_ok());
+
+        assert!(matches!(
+            header().check_sequence(path, Some(&first), 2, 62 * MIB),
+            Err(EtchError::PartOutOfOrder {
+                expected: 3,
+                found: 2,
+                ..
+            })
+        ));
+        let other_set = PartHeader {
+            set_id: 7,
+            ..header()
+        };
+        assert!(matches!(
+            other_set.check_sequence(path, Some(&first), 1, 31 * MIB),
+            Err(EtchError::PartFromOtherSet(_))
+        ));
+        assert!(matches!(
+            header().check_sequence(path, Some(&first), 1, 30 * MIB),
+            Err(EtchError::NotSpannedPart(_))
+        ));
+    }
+}
@@ -0,0 +1,249 @@
+use crate::core::models::BlockDevice;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What the window knows about the session, for a support report
+pub struct SupportContext<'a> {
+    /// Runtime GTK version, e.g. "4.14.2"
+    pub gtk_version: String,
+    pub iso: Option<&'a Path>,
+    pub device: Option<&'a BlockDevice>,
+    /// Log of the last write or image operation
+    pub operation_log: Option<&'a Path>,
+}
+
+/// Plain-text report to attach to a bug report
+///
+/// Collects versions, the selected image and device, the environment checks
+/// and the last operation log. Unless `full_paths` is set, home directories
+/// are replaced with `~` so user names don't end up in public issues.
+pub fn build(context: &SupportContext, full_paths: bool) -> String {
+    let checks = crate::core::diagnostics::format_report(&crate::core::diagnostics::run_checks());
+    let report = render(context, &checks);
+
+    if full_paths {
+        report
+    } else {
+        redact_homes(&report, &home_dirs())
+    }
+}
+
+/// The report with the environment checks already formatted
+fn render(context: &SupportContext, checks: &str) -> String {
+    let mut report = String::new();
+
+    let _ = writeln!(report, "Etch support report");
+    let _ = writeln!(
+        report,
+        "Etch:   {} ({} on {})",
+        env!("CARGO_PKG_VERSION"),
+        env!("ETCH_GIT_HASH"),
+        env!("ETCH_GIT_BRANCH")
+    );
+    let kernel = fs::read_to_string("/proc/sys/kernel/osrelease").map_or_else(
+        |_| "unknown".to_string(),
+        |release| release.trim().to_string(),
+    );
+    let _ = writeln!(report, "Kernel: {kernel}");
+    let _ = writeln!(report, "GTK:    {}", context.gtk_version);
+
+    match context.iso {
+        Some(iso) => {
+            let size = fs::metadata(iso).map_or_else(
+                |e| format!("unreadable: {e}"),
+                |metadata| format!("{} bytes", metadata.len()),
+            );
+            let _ = writeln!(report, "Image:  {} ({size})", iso.display());
+        }
+        None => {
+            let _ = writeln!(report, "Image:  none selected");
+        }
+    }
+
+    match context.device {
+        Some(device) => {
+            let _ = writeln!(
+                report,
+                "Device: {} ({} {}, {} bytes{}, identity {})",
+                device.path.display(),
+                device.vendor,
+                device.model,
+                device.capacity_bytes,
+                if device.is_partition {
+                    ", partition"
+                } else {
+                    ""
+                },
+                crate::io::devices::device_identity(&device.path)
+                    .as_deref()
+                    .unwrap_or("unknown")
+            );
+        }
+        None => {
+            let _ = writeln!(report, "Device: none selected");
+        }
+    }
+
+    let _ = writeln!(report, "\nEnvironment checks:");
+    report.push_str(checks);
+
+    match context.operation_log {
+        Some(log) => {
+            let _ = writeln!(report, "\nOperation log {}:", log.display());
+            match fs::read_to_string(log) {
+                Ok(contents) => report.push_str(&contents),
+                Err(e) => {
+                    let _ = writeln!(report, "(unreadable: {e})");
+                }
+            }
+        }
+        None => {
+            let _ = writeln!(report, "\nNo operation this session.");
+        }
+    }
+
+    report
+}
+
+/// Home directories to redact: root's and the sudo user's
+fn home_dirs() -> Vec<PathBuf> {
+    let mut homes: Vec<PathBuf> = vec![glib::home_dir()];
+    if let Ok(user) = std::env::var("SUDO_USER") {
+        homes.push(Path::new("/home").join(user));
+    }
+    homes
+}
+
+/// Replace each of `homes` with `~` where it is a whole path prefix
+///
+/// `/root` is replaced in `/root/x.iso` but not in `/rootfs`.
+fn redact_homes(text: &str, homes: &[PathBuf]) -> String {
+    homes
+        .iter()
+        .filter_map(|home| home.to_str())
+        .filter(|home| home.len() > 1)
+        .fold(text.to_string(), |text, home| redact_home(&text, home))
+}
+
+fn redact_home(text: &str, home: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(position) = rest.find(home) {
+        let after = &rest[position + home.len()..];
+        let continues_name = after
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphanumeric() || matches!(c, '_' | '-'));
+        redacted.push_str(&rest[..position]);
+        redacted.push_str(if continues_name { home } else { "~" });
+        rest = after;
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn stick() -> BlockDevice {
+        BlockDevice {
+            path: PathBuf::from("/dev/etchtest1"),
+            model: "Flash".to_string(),
+            vendor: "Acme".to_string(),
+            capacity_bytes: 16_000_000_000,
+            is_removable: true,
+            is_partition: true,
+            is_ventoy: false,
+        }
+    }
+
+    #[test]
+    fn report_lists_image_device_checks_and_log() {
+        let mut image = tempfile::NamedTempFile::new().unwrap();
+        image.write_all(&[0u8; 4096]).unwrap();
+        let mut log = tempfile::NamedTempFile::new().unwrap();
+        log.write_all(b"write: complete in 1.0s\n").unwrap();
+        let device = stick();
+
+        let report = render(
+            &SupportContext {
+                gtk_version: "4.14.2".to_string(),
+                iso: Some(image.path()),
+                device: Some(&device),
+                operation_log: Some(log.path()),
+            },
+            "[ OK ] Privileges: root\n",
+        );
+
+        assert!(report.starts_with("Etch support report\n"));
+        assert!(report.contains("GTK:    4.14.2\n"));
+        assert!(report.contains(&format!("Image:  {} (4096 bytes)", image.path().display())));
+        assert!(report.contains(
+            "Device: /dev/etchtest1 (Acme Flash, 16000000000 bytes, partition, identity unknown)"
+        ));
+        assert!(report.contains("Environment checks:\n[ OK ] Privileges: root\n"));
+        assert!(report.ends_with("write: complete in 1.0s\n"));
+    }
+
+    #[test]
+    fn report_without_selection_or_operation_says_so() {
+        let report = render(
+            &SupportContext {
+                gtk_version: "4.14.2".to_string(),
+                iso: None,
+                device: None,
+                operation_log: None,
+            },
+            "",
+        );
+
+        assert!(report.contains("Image:  none selected\n"));
+        assert!(report.contains("Device: none selected\n"));
+        assert!(report.ends_with("No operation this session.\n"));
+    }
+
+    #[test]
+    fn unreadable_log_is_reported_not_fatal() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("gone.log");
+        let report = render(
+            &SupportContext {
+                gtk_version: String::new(),
+                iso: None,
+                device: None,
+                operation_log: Some(&missing),
+            },
+            "",
+        );
+        assert!(report.contains("(unreadable: "));
+    }
+
+    #[test]
+    fn home_directories_become_tilde() {
+        let homes = [PathBuf::from("/root"), PathBuf::from("/home/alex")];
+        let text = "Image:  /home/alex/Downloads/x.iso\nlog /root/.local/state/etch/logs/a.log\n";
+        assert_eq!(
+            redact_homes(text, &homes),
+            "Image:  ~/Downloads/x.iso\nlog ~/.local/state/etch/logs/a.log\n"
+        );
+    }
+
+    #[test]
+    fn only_whole_path_components_are_redacted() {
+        let homes = [PathBuf::from("/root"), PathBuf::from("/home/al")];
+        let text = "/rootfs/x.iso /home/alex/y.iso /home/al";
+        assert_eq!(
+            redact_homes(text, &homes),
+            "/rootfs/x.iso /home/alex/y.iso ~"
+        );
+    }
+
+    #[test]
+    fn filesystem_root_as_home_is_not_redacted() {
+        let homes = [PathBuf::from("/")];
+        assert_eq!(redact_homes("/dev/sdb", &homes), "/dev/sdb");
+    }
+}
@@ -1,17 +1,121 @@
+use crate::core::error::EtchError;
+use crate::core::extents::clip;
 use anyhow::{Context, Result};
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
 use std::path::Path;
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-const CHUNK_SIZE: usize = 1024 * 1024; // 1 MB chunks
+pub const CHUNK_SIZE: usize = 1024 * 1024; // 1 MB chunks
+
+/// Device chunks read ahead of the comparison during a full verification
+pub const READ_AHEAD: usize = 4;
+
+/// Size of each region compared by a quick verification
+const SAMPLE_SIZE: u64 = 1024 * 1024;
+
+/// Evenly spaced regions checked between the first and last MB
+const INTERIOR_SAMPLES: u64 = 8;
+
+/// Attempts at reading a device region before a read error is final
+const READ_ATTEMPTS: u32 = 3;
+const READ_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// How thoroughly a write is checked afterwards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// Read back and compare every byte
+    Full,
+    /// Compare a fixed set of 1 MB regions; catches wrong-device writes,
+    /// truncation and gross corruption in seconds
+    Quick,
+    /// Trust the write
+    Skip,
+}
+
+/// Bytes shown around the first difference in a mismatch report
+const DIFF_WINDOW: usize = 16;
+
+/// Verification found different data on the device than in the source
+///
+/// Carries the bytes surrounding the first difference so a bad sector (one
+/// garbled region) can be told apart from a systematic write error.
+#[derive(Debug)]
+pub struct Mismatch {
+    /// Absolute offset of the first differing byte
+    pub offset: u64,
+    /// Absolute offset of the first byte in `source` / `target`
+    pub window_start: u64,
+    pub source: Vec<u8>,
+    pub target: Vec<u8>,
+}
+
+impl Mismatch {
+    /// `cmp -l`-style hex comparison of the bytes around the difference
+    pub fn hex_diff(&self) -> String {
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let markers = self
+            .source
+            .iter()
+            .zip(&self.target)
+            .map(|(s, t)| if s == t { "  " } else { "^^" })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        format!(
+            "offset  0x{:08x} (first difference at 0x{:08x})\nsource  {}\ndevice  {}\n        {}",
+            self.window_start,
+            self.offset,
+            hex(&self.source),
+            hex(&self.target),
+            markers.trim_end()
+        )
+    }
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let index = usize::try_from(self.offset - self.window_start).unwrap_or_default();
+        write!(
+            f,
+            "Verification failed: data mismatch at byte offset {}. Source: 0x{:02x}, Target: 0x{:02x}",
+            self.offset,
+            self.source.get(index).copied().unwrap_or_default(),
+            self.target.get(index).copied().unwrap_or_default()
+        )
+    }
+}
+
+impl std::error::Error for Mismatch {}
 
 /// Verify written data matches source ISO
+///
+/// Exactly the first `source length` bytes of the device are compared. The
+/// rest of the device is ignored by design: the writer never touches it, so
+/// it still holds whatever was there before, and the image's own partition
+/// table ends within the image, so nothing reads it at boot. Asserting it is
+/// zero would fail on every previously used drive.
+///
+/// Given `extents`, only those ranges were written, so only they are compared.
 #[allow(dead_code)]
 pub fn verify_write(
     source_iso: &Path,
     target_device: &Path,
+    extents: Option<&[Range<u64>]>,
+    cancel: &AtomicBool,
     progress_callback: impl Fn(u64, u64, u64), // (bytes_verified, total_bytes, bytes_per_second)
+    on_retry: impl Fn(&ReadRetry) + Sync,
 ) -> Result<()> {
     // Open source ISO for reading
     let mut source = File::open(source_iso).context(format!(
@@ -25,65 +129,497 @@ pub fn verify_write(
         .len();
 
     // Open target device for reading
-    let mut target = File::open(target_device).context(format!(
+    let target = File::open(target_device).context(format!(
         "Failed to open target device for reading: {}",
         target_device.display()
     ))?;
 
-    // Allocate buffers once outside loop for memory efficiency
+    // Bounded by the size taken up front rather than by source EOF, so the
+    // compared region is fixed before the first read
+    let whole_image = 0..total_size;
+    let chunks = chunk_plan(extents.unwrap_or(std::slice::from_ref(&whole_image)));
+    let compare_total: u64 = chunks.iter().map(|&(_, length)| length as u64).sum();
+
     let mut source_buffer = vec![0u8; CHUNK_SIZE];
-    let mut target_buffer = vec![0u8; CHUNK_SIZE];
     let mut total_verified: u64 = 0;
     let start_time = Instant::now();
     let mut last_progress_time = start_time;
 
+    // The device is read on its own thread, up to READ_AHEAD chunks ahead,
+    // so device reads overlap with source reads and comparing. Chunks arrive
+    // in order, so offsets stay exact and progress only moves forward.
+    let compared = thread::scope(|scope| {
+        let (chunk_tx, chunk_rx) = mpsc::sync_channel(READ_AHEAD);
+        let (recycle_tx, recycle_rx) = mpsc::channel::<Vec<u8>>();
+        let on_retry = &on_retry;
+        let plan = &chunks;
+        scope.spawn(move || read_ahead(target, plan, &chunk_tx, &recycle_rx, on_retry));
+
+        for &(offset, want) in &chunks {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(EtchError::Cancelled {
+                    bytes_done: total_verified,
+                }
+                .into());
+            }
+
+            // Read chunk from source, never past the end of the image
+            let source_chunk = &mut source_buffer[..want];
+            source
+                .seek(SeekFrom::Start(offset))
+                .and_then(|_| source.read_exact(source_chunk))
+                .map_err(|e| {
+                    if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                        anyhow::anyhow!(
+                            "Source ISO shrank during verification: ended before {} of {total_size} bytes",
+                            offset + want as u64
+                        )
+                    } else {
+                        anyhow::Error::new(e).context("Failed to read from source ISO")
+                    }
+                })?;
+
+            // Same range from the device
+            let target_chunk: Vec<u8> = chunk_rx
+                .recv()
+                .context("Device reader stopped unexpectedly")?
+                .context("Failed to read from target device")?;
+
+            // Verify we read the same amount
+            if target_chunk.len() != want {
+                anyhow::bail!(
+                    "Verification failed: size mismatch at offset {offset}. Expected {want} bytes, got {} bytes.",
+                    target_chunk.len()
+                );
+            }
+
+            // Whole-chunk comparison first; only a mismatch is searched byte by byte
+            if *source_chunk != *target_chunk {
+                let i = source_chunk
+                    .iter()
+                    .zip(&target_chunk)
+                    .position(|(s, t)| s != t)
+                    .unwrap_or_default();
+                // Keep the report within this chunk; the difference sits mid-window when possible
+                let start = i
+                    .saturating_sub(DIFF_WINDOW / 2)
+                    .min(want.saturating_sub(DIFF_WINDOW));
+                let end = (start + DIFF_WINDOW).min(want);
+                return Err(Mismatch {
+                    offset: offset + i as u64,
+                    window_start: offset + start as u64,
+                    source: source_chunk[start..end].to_vec(),
+                    target: target_chunk[start..end].to_vec(),
+                }
+                .into());
+            }
+            let _ = recycle_tx.send(target_chunk);
+
+            total_verified += want as u64;
+
+            // Report progress (throttle to avoid overwhelming UI)
+            let now = Instant::now();
+            if now.duration_since(last_progress_time).as_millis() >= 100
+                || total_verified == compare_total
+            {
+                let elapsed = now.duration_since(start_time).as_secs_f64();
+                #[allow(
+                    clippy::cast_possible_truncation,
+                    clippy::cast_sign_loss,
+                    clippy::cast_precision_loss
+                )]
+                let bytes_per_second = if elapsed > 0.0 {
+                    (total_verified as f64 / elapsed) as u64
+                } else {
+                    0
+                };
+                progress_callback(total_verified, compare_total, bytes_per_second);
+                last_progress_time = now;
+            }
+        }
+
+        // Dropping the receiver on any early return stops the reader thread
+        Ok(())
+    });
+    compared?;
+
+    // Ensure final progress update is sent
+    if total_verified > 0 {
+        let elapsed = Instant::now().duration_since(start_time).as_secs_f64();
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let bytes_per_second = if elapsed > 0.0 {
+            (total_verified as f64 / elapsed) as u64
+        } else {
+            0
+        };
+        progress_callback(total_verified, compare_total, bytes_per_second);
+    }
+
+    Ok(())
+}
+
+/// The reads of a full verification: `extents` cut into chunks of at most `CHUNK_SIZE`
+fn chunk_plan(extents: &[Range<u64>]) -> Vec<(u64, usize)> {
+    extents
+        .iter()
+        .flat_map(|extent| {
+            (extent.start..extent.end)
+                .step_by(CHUNK_SIZE)
+                .map(move |offset| (offset, chunk_length(extent.end - offset)))
+        })
+        .collect()
+}
+
+/// Bytes to read for the chunk starting `remaining` bytes before the end
+fn chunk_length(remaining: u64) -> usize {
+    usize::try_from(remaining).map_or(CHUNK_SIZE, |remaining| remaining.min(CHUNK_SIZE))
+}
+
+/// Read the planned `(offset, length)` chunks of the device, handing each to the comparer
+///
+/// Stops after the first error or once the comparer hangs up.
+fn read_ahead(
+    mut target: impl Read + Seek,
+    plan: &[(u64, usize)],
+    chunks: &mpsc::SyncSender<std::io::Result<Vec<u8>>>,
+    recycled: &mpsc::Receiver<Vec<u8>>,
+    on_retry: &impl Fn(&ReadRetry),
+) {
+    for &(offset, want) in plan {
+        let mut buffer = recycled.try_recv().unwrap_or_default();
+        buffer.resize(want, 0);
+
+        let result = with_read_retries(offset, on_retry, || {
+            target.seek(SeekFrom::Start(offset))?;
+            read_full(&mut target, &mut buffer)
+        })
+        .map(|bytes_read| {
+            buffer.truncate(bytes_read);
+            buffer
+        });
+        let complete = result.as_ref().is_ok_and(|chunk| chunk.len() == want);
+        if chunks.send(result).is_err() || !complete {
+            return;
+        }
+    }
+}
+
+/// Read until `buffer` is full or the input ends, returning the bytes read
+///
+/// A device may hand back less than asked for in one read; only the end of
+/// the device makes a chunk short.
+fn read_full(input: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// A device read that failed and is about to be repeated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadRetry {
+    pub offset: u64,
+    /// The attempt that failed, counting from 1
+    pub attempt: u32,
+    pub error: String,
+}
+
+impl std::fmt::Display for ReadRetry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "read error at offset {} (attempt {} of {READ_ATTEMPTS}): {}; retrying",
+            self.offset, self.attempt, self.error
+        )
+    }
+}
+
+/// Run a device read, repeating it after a pause if it fails with an I/O error
+///
+/// Flaky USB links drop the odd read on media that is fine. `read` must seek
+/// to its own start, since a failed read leaves the position undefined. Only
+/// read errors are retried; a data mismatch is final. Each retry is passed to
+/// `on_retry` so the caller can log it.
+fn with_read_retries<T>(
+    offset: u64,
+    on_retry: &impl Fn(&ReadRetry),
+    mut read: impl FnMut() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let mut attempt = 1;
     loop {
-        // Read chunk from source
-        let source_bytes_read = source
-            .read(&mut source_buffer)
-            .context("Failed to read from source ISO")?;
+        match read() {
+            Err(e) if attempt < READ_ATTEMPTS => {
+                let retry = ReadRetry {
+                    offset,
+                    attempt,
+                    error: e.to_string(),
+                };
+                eprintln!("WARNING: {retry}");
+                on_retry(&retry);
+                thread::sleep(READ_RETRY_DELAY);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
 
-        if source_bytes_read == 0 {
-            break; // EOF
+/// Start offsets of the regions a quick verification compares
+///
+/// Deterministic for a given size: the first MB, the last MB and
+/// `INTERIOR_SAMPLES` windows evenly spaced between them.
+pub fn sample_offsets(total_size: u64) -> Vec<u64> {
+    if total_size <= SAMPLE_SIZE {
+        return vec![0];
+    }
+
+    let last = total_size - SAMPLE_SIZE;
+    let mut offsets: Vec<u64> = (0..=INTERIOR_SAMPLES)
+        .map(|index| last / (INTERIOR_SAMPLES + 1) * index)
+        .collect();
+    offsets.push(last);
+    offsets.sort_unstable();
+    offsets.dedup();
+    offsets
+}
+
+/// Compare SHA-256 hashes of sampled regions of the source and the device
+///
+/// Given `extents`, the parts of each region outside them were not written
+/// and are left out.
+pub fn verify_samples(
+    source_iso: &Path,
+    target_device: &Path,
+    extents: Option<&[Range<u64>]>,
+    cancel: &AtomicBool,
+    progress_callback: impl Fn(u64, u64, u64), // (bytes_verified, total_bytes, bytes_per_second)
+    on_retry: impl Fn(&ReadRetry) + Sync,
+) -> Result<()> {
+    let mut source = File::open(source_iso).context(format!(
+        "Failed to open source ISO: {}",
+        source_iso.display()
+    ))?;
+    let total_size = source
+        .metadata()
+        .context("Failed to get source file size")?
+        .len();
+    let mut target = File::open(target_device).context(format!(
+        "Failed to open target device for reading: {}",
+        target_device.display()
+    ))?;
+
+    let regions: Vec<Range<u64>> = sample_offsets(total_size)
+        .into_iter()
+        .flat_map(|offset| {
+            let region = offset..offset + SAMPLE_SIZE.min(total_size - offset);
+            match extents {
+                Some(extents) => clip(extents, region),
+                None => vec![region],
+            }
+        })
+        .collect();
+    let sample_total: u64 = regions.iter().map(|region| region.end - region.start).sum();
+
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut verified: u64 = 0;
+    let start_time = Instant::now();
+
+    for region in regions {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(EtchError::Cancelled {
+                bytes_done: verified,
+            }
+            .into());
         }
 
-        // Read same amount from target
-        let target_bytes_read = target
-            .read(&mut target_buffer[..source_bytes_read])
-            .context("Failed to read from target device")?;
+        let (offset, length) = (region.start, region.end - region.start);
+        let source_hash = hash_region(&mut source, offset, length, &mut buffer)
+            .context("Failed to read from source ISO")?;
+        let target_hash = with_read_retries(offset, &on_retry, || {
+            hash_region(&mut target, offset, length, &mut buffer)
+        })
+        .context("Failed to read from target device")?;
 
-        // Verify we read the same amount
-        if source_bytes_read != target_bytes_read {
+        if source_hash != target_hash {
             anyhow::bail!(
-                "Verification failed: size mismatch at offset {total_verified}. Expected {source_bytes_read} bytes, got {target_bytes_read} bytes."
+                "Quick verification failed: the {length} bytes at offset {offset} differ from the source"
             );
         }
 
-        // Compare buffers byte-by-byte
-        if source_buffer[..source_bytes_read] != target_buffer[..target_bytes_read] {
-            // Find the first differing byte for detailed error message
-            for (i, (s, t)) in source_buffer[..source_bytes_read]
-                .iter()
-                .zip(target_buffer[..target_bytes_read].iter())
-                .enumerate()
-            {
-                if s != t {
-                    anyhow::bail!(
-                        "Verification failed: data mismatch at byte offset {}. Source: 0x{:02x}, Target: 0x{:02x}",
-                        total_verified + i as u64,
-                        s,
-                        t
-                    );
-                }
+        verified += length;
+        let elapsed = start_time.elapsed().as_secs_f64();
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let bytes_per_second = if elapsed > 0.0 {
+            (verified as f64 / elapsed) as u64
+        } else {
+            0
+        };
+        progress_callback(verified, sample_total, bytes_per_second);
+    }
+
+    Ok(())
+}
+
+/// SHA-256 of `length` bytes at `offset`
+fn hash_region(
+    file: &mut File,
+    offset: u64,
+    length: u64,
+    buffer: &mut [u8],
+) -> std::io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut hasher = Sha256::new();
+    let mut remaining = length;
+    while remaining > 0 {
+        let want = usize::try_from(remaining).map_or(buffer.len(), |r| r.min(buffer.len()));
+        file.read_exact(&mut buffer[..want])?;
+        hasher.update(&buffer[..want]);
+        remaining -= want as u64;
+    }
+
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Check the source ISO against a known SHA-256 before anything is written
+///
+/// Device verification only proves the device matches the source, so a source
+/// that is already corrupt on disk would otherwise pass unnoticed.
+pub fn verify_source_checksum(
+    source_iso: &Path,
+    expected_sha256: &str,
+    cancel: &AtomicBool,
+    progress_callback: impl Fn(u64, u64, u64), // (bytes_hashed, total_bytes, bytes_per_second)
+) -> Result<()> {
+    let actual = file_digest(
+        source_iso,
+        ChecksumAlgorithm::Sha256,
+        cancel,
+        progress_callback,
+    )?;
+    let expected = expected_sha256.trim().to_ascii_lowercase();
+
+    if actual != expected {
+        return Err(EtchError::ChecksumMismatch { expected, actual }.into());
+    }
+
+    Ok(())
+}
+
+/// Whether a string looks like a hex-encoded SHA-256 digest
+pub fn is_sha256_hex(value: &str) -> bool {
+    let value = value.trim();
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Digest algorithms a published checksum can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    pub const ALL: [Self; 4] = [Self::Sha224, Self::Sha256, Self::Sha384, Self::Sha512];
+
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Sha224 => "SHA-224",
+            Self::Sha256 => "SHA-256",
+            Self::Sha384 => "SHA-384",
+            Self::Sha512 => "SHA-512",
+        }
+    }
+
+    /// Length of the hex-encoded digest
+    pub const fn hex_length(self) -> usize {
+        match self {
+            Self::Sha224 => 56,
+            Self::Sha256 => 64,
+            Self::Sha384 => 96,
+            Self::Sha512 => 128,
+        }
+    }
+
+    /// The algorithm whose hex digests are as long as `value`, if it is hex at all
+    pub fn detect(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if !value.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.hex_length() == value.len())
+    }
+}
+
+/// Hash a file, returning the lowercase hex digest
+pub fn file_digest(
+    path: &Path,
+    algorithm: ChecksumAlgorithm,
+    cancel: &AtomicBool,
+    progress_callback: impl Fn(u64, u64, u64), // (bytes_hashed, total_bytes, bytes_per_second)
+) -> Result<String> {
+    match algorithm {
+        ChecksumAlgorithm::Sha224 => hash_file::<Sha224>(path, cancel, progress_callback),
+        ChecksumAlgorithm::Sha256 => hash_file::<Sha256>(path, cancel, progress_callback),
+        ChecksumAlgorithm::Sha384 => hash_file::<Sha384>(path, cancel, progress_callback),
+        ChecksumAlgorithm::Sha512 => hash_file::<Sha512>(path, cancel, progress_callback),
+    }
+}
+
+fn hash_file<D: Digest>(
+    path: &Path,
+    cancel: &AtomicBool,
+    progress_callback: impl Fn(u64, u64, u64),
+) -> Result<String> {
+    let mut file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+    let total_size = file.metadata().context("Failed to get file size")?.len();
+
+    let mut hasher = D::new();
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut total_hashed: u64 = 0;
+    let start_time = Instant::now();
+    let mut last_progress_time = start_time;
+
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(EtchError::Cancelled {
+                bytes_done: total_hashed,
             }
+            .into());
+        }
+
+        let bytes_read = file
+            .read(&mut buffer)
+            .context(format!("Failed to read {}", path.display()))?;
+
+        if bytes_read == 0 {
+            break; // EOF
         }
 
-        total_verified += source_bytes_read as u64;
+        hasher.update(&buffer[..bytes_read]);
+        total_hashed += bytes_read as u64;
 
         // Report progress (throttle to avoid overwhelming UI)
         let now = Instant::now();
-        if now.duration_since(last_progress_time).as_millis() >= 100 || total_verified == total_size
-        {
+        if now.duration_since(last_progress_time).as_millis() >= 100 || total_hashed == total_size {
             let elapsed = now.duration_since(start_time).as_secs_f64();
             #[allow(
                 clippy::cast_possible_truncation,
@@ -91,30 +627,307 @@ pub fn verify_write(
                 clippy::cast_precision_loss
             )]
             let bytes_per_second = if elapsed > 0.0 {
-                (total_verified as f64 / elapsed) as u64
+                (total_hashed as f64 / elapsed) as u64
             } else {
                 0
             };
-            progress_callback(total_verified, total_size, bytes_per_second);
+            progress_callback(total_hashed, total_size, bytes_per_second);
             last_progress_time = now;
         }
     }
 
-    // Ensure final progress update is sent
-    if total_verified > 0 {
-        let elapsed = Instant::now().duration_since(start_time).as_secs_f64();
-        #[allow(
-            clippy::cast_possible_truncation,
-            clippy::cast_sign_loss,
-            clippy::cast_precision_loss
-        )]
-        let bytes_per_second = if elapsed > 0.0 {
-            (total_verified as f64 / elapsed) as u64
-        } else {
-            0
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::io::Write;
+
+    /// Not a multiple of the chunk size, so the last chunk is partial
+    const IMAGE_SIZE: usize = CHUNK_SIZE + 1000;
+
+    fn file_with(bytes: &[u8]) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(bytes).unwrap();
+        file
+    }
+
+    fn image() -> Vec<u8> {
+        (0..IMAGE_SIZE).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn bytes_past_the_image_end_are_ignored() {
+        let image = image();
+        let mut device = image.clone();
+        device.resize(IMAGE_SIZE + 3 * CHUNK_SIZE, 0xA5);
+        let (source, target) = (file_with(&image), file_with(&device));
+
+        let verified = Cell::new(0);
+        verify_write(
+            source.path(),
+            target.path(),
+            None,
+            &AtomicBool::new(false),
+            |bytes, total, _| {
+                assert_eq!(total, IMAGE_SIZE as u64);
+                verified.set(bytes);
+            },
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(verified.get(), IMAGE_SIZE as u64);
+    }
+
+    #[test]
+    fn difference_in_the_last_image_byte_is_found() {
+        let image = image();
+        let mut device = image.clone();
+        device[IMAGE_SIZE - 1] ^= 0xFF;
+        device.extend([0u8; 4096]);
+        let (source, target) = (file_with(&image), file_with(&device));
+
+        let error = verify_write(
+            source.path(),
+            target.path(),
+            None,
+            &AtomicBool::new(false),
+            |_, _, _| {},
+            |_| {},
+        )
+        .unwrap_err();
+        let mismatch = error.downcast_ref::<Mismatch>().unwrap();
+        assert_eq!(mismatch.offset, IMAGE_SIZE as u64 - 1);
+    }
+
+    #[test]
+    fn device_shorter_than_the_image_fails() {
+        let image = image();
+        let (source, target) = (file_with(&image), file_with(&image[..IMAGE_SIZE - 1]));
+
+        let result = verify_write(
+            source.path(),
+            target.path(),
+            None,
+            &AtomicBool::new(false),
+            |_, _, _| {},
+            |_| {},
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn cancelled_verification_stops() {
+        let image = image();
+        let (source, target) = (file_with(&image), file_with(&image));
+
+        let error = verify_write(
+            source.path(),
+            target.path(),
+            None,
+            &AtomicBool::new(true),
+            |_, _, _| {},
+            |_| {},
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<EtchError>(),
+            Some(EtchError::Cancelled { bytes_done: 0 })
+        ));
+    }
+
+    #[test]
+    fn only_written_extents_are_compared() {
+        let image = image();
+        let mut device = image.clone();
+        // Never written: stale data the image's filesystem does not use
+        device[100..200].fill(0xA5);
+        device[CHUNK_SIZE + 10] ^= 0xFF;
+        let (source, target) = (file_with(&image), file_with(&device));
+        let extents = [0..100, 200..CHUNK_SIZE as u64 + 10];
+
+        let verified = Cell::new(0);
+        verify_write(
+            source.path(),
+            target.path(),
+            Some(&extents),
+            &AtomicBool::new(false),
+            |bytes, total, _| {
+                assert_eq!(total, CHUNK_SIZE as u64 - 90);
+                verified.set(bytes);
+            },
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(verified.get(), CHUNK_SIZE as u64 - 90);
+        verify_samples(
+            source.path(),
+            target.path(),
+            Some(&extents),
+            &AtomicBool::new(false),
+            |_, _, _| {},
+            |_| {},
+        )
+        .unwrap();
+
+        // The same device fails once the skipped bytes count
+        let extents = [0..100, 100..150];
+        let error = verify_write(
+            source.path(),
+            target.path(),
+            Some(&extents),
+            &AtomicBool::new(false),
+            |_, _, _| {},
+            |_| {},
+        )
+        .unwrap_err();
+        assert_eq!(error.downcast_ref::<Mismatch>().unwrap().offset, 100);
+        assert!(verify_samples(
+            source.path(),
+            target.path(),
+            Some(&extents),
+            &AtomicBool::new(false),
+            |_, _, _| {},
+            |_| {},
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn chunk_plan_splits_extents_at_chunk_size() {
+        let size = CHUNK_SIZE as u64;
+        assert_eq!(
+            chunk_plan(&[0..10, 3 * size..5 * size + 1]),
+            [
+                (0, 10),
+                (3 * size, CHUNK_SIZE),
+                (4 * size, CHUNK_SIZE),
+                (5 * size, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn samples_cover_the_first_and_last_mb() {
+        let total = 100 * SAMPLE_SIZE + 12_345;
+        let offsets = sample_offsets(total);
+        assert_eq!(offsets.first(), Some(&0));
+        assert_eq!(offsets.last(), Some(&(total - SAMPLE_SIZE)));
+    }
+
+    #[test]
+    fn interior_samples_are_evenly_spaced() {
+        let total = 100 * SAMPLE_SIZE;
+        let offsets = sample_offsets(total);
+        assert_eq!(offsets.len(), INTERIOR_SAMPLES as usize + 2);
+
+        let step = (total - SAMPLE_SIZE) / (INTERIOR_SAMPLES + 1);
+        let interior = &offsets[1..offsets.len() - 1];
+        for (index, &offset) in (1..).zip(interior) {
+            assert_eq!(offset, step * index);
+        }
+        // Whole windows, none running past the end
+        assert!(offsets.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(offsets.iter().all(|&offset| offset + SAMPLE_SIZE <= total));
+    }
+
+    #[test]
+    fn images_of_one_mb_or_less_are_a_single_sample() {
+        assert_eq!(sample_offsets(0), [0]);
+        assert_eq!(sample_offsets(4096), [0]);
+        assert_eq!(sample_offsets(SAMPLE_SIZE), [0]);
+    }
+
+    #[test]
+    fn samples_of_small_images_are_not_repeated() {
+        // Too little room for the interior windows to land apart
+        assert_eq!(sample_offsets(SAMPLE_SIZE + 5), [0, 5]);
+
+        let offsets = sample_offsets(SAMPLE_SIZE + 100);
+        let mut unique = offsets.clone();
+        unique.dedup();
+        assert_eq!(offsets, unique);
+        assert_eq!(offsets.last(), Some(&100));
+    }
+
+    /// Hands back at most `limit` bytes per read, like a device mid-transfer
+    struct ShortReads {
+        inner: std::io::Cursor<Vec<u8>>,
+        limit: usize,
+    }
+
+    impl Read for ShortReads {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            let limit = buffer.len().min(self.limit);
+            self.inner.read(&mut buffer[..limit])
+        }
+    }
+
+    impl Seek for ShortReads {
+        fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(position)
+        }
+    }
+
+    #[test]
+    fn read_ahead_fills_chunks_across_short_reads() {
+        let image = image();
+        let device = ShortReads {
+            inner: std::io::Cursor::new(image.clone()),
+            limit: 4096 + 3,
         };
-        progress_callback(total_verified, total_size, bytes_per_second);
+        // The last chunk asks for more than the device holds
+        let plan = [(0, CHUNK_SIZE), (CHUNK_SIZE as u64, CHUNK_SIZE)];
+        let (chunk_tx, chunk_rx) = mpsc::sync_channel(plan.len());
+        let (_recycle_tx, recycle_rx) = mpsc::channel();
+        read_ahead(device, &plan, &chunk_tx, &recycle_rx, &|_| {});
+        drop(chunk_tx);
+
+        let chunks: Vec<Vec<u8>> = chunk_rx.iter().map(Result::unwrap).collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0], image[..CHUNK_SIZE]);
+        assert_eq!(chunks[1], image[CHUNK_SIZE..]);
     }
 
-    Ok(())
+    #[test]
+    fn read_retries_are_reported() {
+        let retries = std::sync::Mutex::new(Vec::new());
+        let attempts = Cell::new(0);
+        let result = with_read_retries(
+            4096,
+            &|retry: &ReadRetry| retries.lock().unwrap().push(retry.clone()),
+            || {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < READ_ATTEMPTS {
+                    Err(std::io::Error::other("flaky link"))
+                } else {
+                    Ok(7)
+                }
+            },
+        );
+        assert_eq!(result.unwrap(), 7);
+
+        let retries = retries.into_inner().unwrap();
+        let attempts: Vec<u32> = retries.iter().map(|retry| retry.attempt).collect();
+        assert_eq!(attempts, [1, 2]);
+        assert!(retries.iter().all(|retry| retry.offset == 4096));
+        assert!(retries[0].to_string().contains("flaky link"));
+    }
+
+    #[test]
+    fn last_failed_attempt_is_returned_not_retried() {
+        let retries = Cell::new(0);
+        let result: std::io::Result<()> =
+            with_read_retries(0, &|_: &ReadRetry| retries.set(retries.get() + 1), || {
+                Err(std::io::Error::other("dead"))
+            });
+        assert!(result.is_err());
+        assert_eq!(retries.get(), READ_ATTEMPTS - 1);
+    }
 }
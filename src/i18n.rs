@@ -0,0 +1,42 @@
+/// Translation setup and helpers for user-facing strings
+///
+/// Strings are marked with `gettext()` / `i18n_f()` and extracted into
+/// `po/etch.pot` (see "Translations" in the README). Untranslated strings fall
+/// back to English.
+pub use gettextrs::gettext;
+
+const GETTEXT_PACKAGE: &str = "etch";
+
+/// Directory holding compiled `.mo` catalogs, overridable at build time
+const LOCALEDIR: &str = match option_env!("ETCH_LOCALEDIR") {
+    Some(dir) => dir,
+    None => "/usr/share/locale",
+};
+
+/// Select the user's locale and bind the translation domain
+///
+/// Failures are not fatal: without a catalog every string stays in English.
+pub fn init() {
+    gettextrs::setlocale(gettextrs::LocaleCategory::LcAll, "");
+
+    let bound = gettextrs::bindtextdomain(GETTEXT_PACKAGE, LOCALEDIR)
+        .and_then(|_| gettextrs::bind_textdomain_codeset(GETTEXT_PACKAGE, "UTF-8"))
+        .and_then(|_| gettextrs::textdomain(GETTEXT_PACKAGE));
+
+    if let Err(e) = bound {
+        eprintln!("WARNING: Translations unavailable: {e}");
+    }
+}
+
+/// Translate a message containing `{}` placeholders, then fill them in order
+///
+/// Placeholders are substituted after translation so translators can move them.
+pub fn i18n_f(format: &str, args: &[&str]) -> String {
+    let mut translated = gettext(format);
+    for arg in args {
+        if let Some(position) = translated.find("{}") {
+            translated.replace_range(position..position + 2, arg);
+        }
+    }
+    translated
+}
@@ -1,19 +1,96 @@
+use crate::core::error::EtchError;
 use crate::core::models::BlockDevice;
 use anyhow::{Context, Result};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for a vanished device to come back before giving up
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Unit of `/sys/block/<dev>/size` and of partition `size` files
+///
+/// The kernel always reports these in 512-byte units, whatever the device's
+/// logical block size (`queue/logical_block_size`, e.g. 4096 on some USB
+/// enclosures), so multiplying by the logical block size would overstate
+/// such a device's capacity eightfold.
+pub const SYSFS_SECTOR_SIZE: u64 = 512;
+
+/// `device/type` of CD/DVD drives (TYPE_ROM)
+const OPTICAL_SCSI_TYPE: &str = "5";
+
+/// How long `identify` keeps the activity LED busy
+pub const IDENTIFY_DURATION: Duration = Duration::from_secs(5);
+
+/// Length of each burst of reads and of the pause after it
+const IDENTIFY_BLINK: Duration = Duration::from_millis(250);
+const IDENTIFY_READ_SIZE: u64 = 64 * 1024;
+
+/// Where the device list comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnumerationBackend {
+    Sysfs,
+    /// `lsblk --json`, for containers and minimal systems without /sys/block
+    Lsblk,
+}
+
+impl EnumerationBackend {
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Sysfs => "sysfs",
+            Self::Lsblk => "lsblk",
+        }
+    }
+}
+
+/// Which enumeration backend works on this system, if any
+pub fn probe_enumeration_backend() -> Option<EnumerationBackend> {
+    if fs::read_dir("/sys/block").is_ok() {
+        return Some(EnumerationBackend::Sysfs);
+    }
+    Command::new("lsblk")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+        .then_some(EnumerationBackend::Lsblk)
+}
 
 /// Enumerate all removable block devices on the system
-#[allow(dead_code)]
+///
+/// Reads sysfs, falling back to `lsblk` only when /sys/block is missing or
+/// unreadable; an empty sysfs list means no devices are plugged in. Fails
+/// only when neither source is available, so callers can tell "no devices"
+/// apart from "cannot enumerate devices".
 pub fn list_removable_devices() -> Result<Vec<BlockDevice>> {
-    let mut devices = Vec::new();
-    let sys_block = PathBuf::from("/sys/block");
+    with_lsblk_fallback(
+        list_sysfs_devices(Path::new("/sys/block")),
+        list_lsblk_devices,
+    )
+}
 
-    if !sys_block.exists() {
-        return Ok(devices);
-    }
+/// The sysfs result, or `lsblk`'s if sysfs could not be read
+fn with_lsblk_fallback(
+    sysfs: Result<Vec<BlockDevice>>,
+    lsblk: impl FnOnce() -> Result<Vec<BlockDevice>>,
+) -> Result<Vec<BlockDevice>> {
+    let sysfs_error = match sysfs {
+        Ok(devices) => return Ok(devices),
+        Err(e) => e,
+    };
+    lsblk().map_err(|e| e.context(format!("Cannot enumerate devices: {sysfs_error:#}")))
+}
+
+/// Removable whole disks listed in a directory laid out like `/sys/block`
+fn list_sysfs_devices(sys_block: &Path) -> Result<Vec<BlockDevice>> {
+    let mut devices = Vec::new();
 
-    for entry in fs::read_dir(&sys_block).context("Failed to read /sys/block")? {
+    for entry in
+        fs::read_dir(sys_block).context(format!("Failed to read {}", sys_block.display()))?
+    {
         let entry = entry?;
         let device_name = entry.file_name();
         let device_path = entry.path();
@@ -34,16 +111,21 @@ pub fn list_removable_devices() -> Result<Vec<BlockDevice>> {
             continue;
         }
 
+        // Optical drives (SCSI type 5) take burning, not raw block writes
+        if read_sys_file(&device_path.join("device/type")).as_deref() == Some(OPTICAL_SCSI_TYPE) {
+            continue;
+        }
+
         // Read device information
         let model = read_sys_file(&device_path.join("device/model"))
             .unwrap_or_else(|| "Unknown".to_string());
         let vendor = read_sys_file(&device_path.join("device/vendor"))
             .unwrap_or_else(|| "Unknown".to_string());
 
-        // Read capacity in 512-byte sectors
+        // Read capacity; always in 512-byte units, see SYSFS_SECTOR_SIZE
         let size_str = read_sys_file(&device_path.join("size")).unwrap_or_else(|| "0".to_string());
         let sectors: u64 = size_str.parse().unwrap_or(0);
-        let capacity_bytes = sectors * 512;
+        let capacity_bytes = sectors * SYSFS_SECTOR_SIZE;
 
         // Skip devices with zero capacity
         if capacity_bytes == 0 {
@@ -53,25 +135,307 @@ pub fn list_removable_devices() -> Result<Vec<BlockDevice>> {
         let dev_path = PathBuf::from("/dev").join(&device_name);
 
         devices.push(BlockDevice {
+            is_ventoy: crate::io::ventoy::data_partition(&dev_path).is_some(),
             path: dev_path,
             model: model.trim().to_string(),
             vendor: vendor.trim().to_string(),
             capacity_bytes,
             is_removable: true,
+            is_partition: false,
         });
     }
 
     Ok(devices)
 }
 
+/// Removable whole disks as reported by `lsblk --json`
+fn list_lsblk_devices() -> Result<Vec<BlockDevice>> {
+    let output = Command::new("lsblk")
+        .args(["--json", "--bytes", "--nodeps"])
+        .args(["--output", "NAME,RM,SIZE,VENDOR,MODEL,TYPE"])
+        .output()
+        .context("Failed to run lsblk")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "lsblk failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let parsed: serde_json::Value =
+        serde_json::from_slice(&output.stdout).context("Failed to parse lsblk output")?;
+    let entries = parsed["blockdevices"]
+        .as_array()
+        .context("lsblk output has no blockdevices list")?;
+
+    // Older util-linux prints RM and SIZE as strings, newer as bool and number
+    let flag = |value: &serde_json::Value| {
+        value
+            .as_bool()
+            .unwrap_or_else(|| matches!(value.as_str(), Some("1" | "true")))
+    };
+    let number = |value: &serde_json::Value| {
+        value
+            .as_u64()
+            .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+            .unwrap_or(0)
+    };
+    let text = |value: &serde_json::Value| {
+        value
+            .as_str()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .unwrap_or("Unknown")
+            .to_string()
+    };
+
+    Ok(entries
+        .iter()
+        .filter(|entry| entry["type"].as_str() == Some("disk") && flag(&entry["rm"]))
+        .filter_map(|entry| {
+            let name = entry["name"].as_str()?;
+            let path = PathBuf::from("/dev").join(name);
+            Some(BlockDevice {
+                is_ventoy: crate::io::ventoy::data_partition(&path).is_some(),
+                path,
+                model: text(&entry["model"]),
+                vendor: text(&entry["vendor"]),
+                capacity_bytes: number(&entry["size"]),
+                is_removable: true,
+                is_partition: false,
+            })
+        })
+        .filter(|device| device.capacity_bytes > 0)
+        .collect())
+}
+
+/// Enumerate the partitions of a disk returned by `list_removable_devices`
+pub fn list_partitions(disk: &BlockDevice) -> Vec<BlockDevice> {
+    list_partitions_in(Path::new("/sys/block"), disk)
+}
+
+/// `list_partitions` against any directory laid out like `/sys/block`
+fn list_partitions_in(sys_block: &Path, disk: &BlockDevice) -> Vec<BlockDevice> {
+    let Some(disk_name) = disk.path.file_name() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(sys_block.join(disk_name)) else {
+        return Vec::new();
+    };
+
+    let mut partitions: Vec<BlockDevice> = entries
+        .flatten()
+        .filter(|entry| entry.path().join("partition").exists())
+        .filter_map(|entry| {
+            let sectors: u64 = read_sys_file(&entry.path().join("size"))?.parse().ok()?;
+            Some(BlockDevice {
+                path: PathBuf::from("/dev").join(entry.file_name()),
+                model: disk.model.clone(),
+                vendor: disk.vendor.clone(),
+                capacity_bytes: sectors * SYSFS_SECTOR_SIZE,
+                is_removable: disk.is_removable,
+                is_partition: true,
+                is_ventoy: false,
+            })
+        })
+        .filter(|partition| partition.capacity_bytes > 0)
+        .collect();
+
+    partitions.sort_by(|a, b| a.path.cmp(&b.path));
+    partitions
+}
+
 /// Read and trim a sysfs file, return None if it doesn't exist or can't be read
 fn read_sys_file(path: &PathBuf) -> Option<String> {
     fs::read_to_string(path).ok().map(|s| s.trim().to_string())
 }
 
+/// Stable identity of a device, taken from its `/dev/disk/by-id` link name
+///
+/// The link name embeds the bus, vendor, model and serial, so it survives the
+/// device being re-enumerated under a different `/dev/sdX` node.
+pub fn device_identity(path: &Path) -> Option<String> {
+    let target = fs::canonicalize(path).ok()?;
+
+    let mut identities: Vec<String> = fs::read_dir("/dev/disk/by-id")
+        .ok()?
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| fs::canonicalize(entry.path()).ok().as_ref() == Some(&target))
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+        .collect();
+
+    // Prefer the bus-specific name (usb-*, ata-*) over the generic wwn-* alias
+    identities.sort_by_key(|id| id.starts_with("wwn-"));
+    identities.into_iter().next()
+}
+
+/// Resolve a device identity back to its current `/dev` node, if attached
+pub fn find_device_by_identity(identity: &str) -> Option<PathBuf> {
+    fs::canonicalize(Path::new("/dev/disk/by-id").join(identity)).ok()
+}
+
+/// Temperature input of a disk, if its driver exposes one through hwmon
+///
+/// `drivetemp` (ATA disks, including USB bridges that pass SMART through) and
+/// NVMe register a hwmon node under the disk's `device` directory. Most USB
+/// sticks have none.
+pub fn temperature_sensor(device: &Path) -> Option<PathBuf> {
+    find_temperature_sensor(Path::new("/sys/class/block"), device.file_name()?.to_str()?)
+}
+
+/// `temperature_sensor` against any directory laid out like `/sys/class/block`
+fn find_temperature_sensor(class_block: &Path, device_name: &str) -> Option<PathBuf> {
+    let mut nodes: Vec<PathBuf> = fs::read_dir(class_block.join(device_name).join("device/hwmon"))
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .collect();
+    nodes.sort();
+    nodes
+        .into_iter()
+        .map(|node| node.join("temp1_input"))
+        .find(|input| input.is_file())
+}
+
+/// Current reading of a hwmon temperature input, in whole degrees Celsius
+pub fn read_temperature(sensor: &Path) -> Option<i64> {
+    let millidegrees: i64 = fs::read_to_string(sensor).ok()?.trim().parse().ok()?;
+    Some(millidegrees / 1000)
+}
+
+/// Whether an I/O error means the device itself went away (unplugged, port reset)
+pub fn is_device_gone(error: &std::io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(libc::ENODEV | libc::ENXIO))
+}
+
+/// Poll for a vanished device to reappear and reopen it with `open`
+///
+/// Matches by `/dev/disk/by-id` identity, since the kernel may hand the
+/// reconnected stick a different node name. Without an identity there is no
+/// way to tell the same stick from whatever disk gets `last_path` next, so
+/// that fails at once instead of reopening the old node.
+pub fn wait_for_reconnect<T>(
+    identity: Option<&str>,
+    last_path: &Path,
+    cancel: &AtomicBool,
+    open: impl Fn(&Path) -> std::io::Result<T>,
+) -> Result<(T, PathBuf)> {
+    let Some(identity) = identity else {
+        return Err(EtchError::DeviceDisconnected(last_path.to_path_buf()).into());
+    };
+    let deadline = Instant::now() + RECONNECT_TIMEOUT;
+
+    while Instant::now() < deadline {
+        thread::sleep(RECONNECT_POLL_INTERVAL);
+
+        if cancel.load(Ordering::Relaxed) {
+            anyhow::bail!("Cancelled while waiting for the device to reconnect");
+        }
+
+        if let Some(path) = find_device_by_identity(identity) {
+            if let Ok(handle) = open(&path) {
+                return Ok((handle, path));
+            }
+        }
+    }
+
+    anyhow::bail!(
+        "Device disappeared and did not reconnect within {} seconds",
+        RECONNECT_TIMEOUT.as_secs()
+    )
+}
+
+/// Blink a device's activity LED by reading scattered blocks in bursts
+///
+/// Read-only and harmless; lets the user match the selected entry to a
+/// physical stick before erasing it. Devices without an LED just see reads.
+pub fn identify(device: &BlockDevice) -> Result<()> {
+    use std::os::unix::fs::FileExt;
+    use std::os::unix::io::AsRawFd;
+
+    let file = fs::File::open(&device.path).context(format!(
+        "Cannot read {}. Run with sudo/root privileges.",
+        device.path.display()
+    ))?;
+
+    let blocks = device.capacity_bytes / IDENTIFY_READ_SIZE;
+    if blocks == 0 {
+        anyhow::bail!("{} reports no capacity", device.path.display());
+    }
+
+    #[allow(clippy::cast_possible_truncation)] // 64 KiB
+    let mut buffer = vec![0u8; IDENTIFY_READ_SIZE as usize];
+    let mut seed: u64 = 1;
+    let started = Instant::now();
+
+    while started.elapsed() < IDENTIFY_DURATION {
+        let burst_end = Instant::now() + IDENTIFY_BLINK;
+        while Instant::now() < burst_end {
+            // Jump around the device so reads rarely hit the page cache
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            let offset = (seed >> 33) % blocks * IDENTIFY_READ_SIZE;
+            file.read_at(&mut buffer, offset)
+                .context(format!("Failed to read {}", device.path.display()))?;
+        }
+
+        // Drop what was cached so the next burst reaches the device again
+        // SAFETY: the descriptor is owned by `file` and open for the call
+        unsafe {
+            libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+        thread::sleep(IDENTIFY_BLINK);
+    }
+
+    Ok(())
+}
+
+/// Size of a block device in bytes
+///
+/// Seeking to the end asks the kernel (the same value as the BLKGETSIZE64
+/// ioctl); a block device's metadata length is always zero.
+pub fn block_device_size(device: &mut fs::File) -> std::io::Result<u64> {
+    use std::io::{Seek, SeekFrom};
+
+    let size = device.seek(SeekFrom::End(0))?;
+    device.seek(SeekFrom::Start(0))?;
+    Ok(size)
+}
+
+/// Whether the device's first sector carries an MBR boot signature
+///
+/// This is what BIOS firmware checks before booting from a USB stick.
+pub fn has_boot_signature(path: &Path) -> std::io::Result<bool> {
+    use std::io::Read;
+
+    let mut sector = [0u8; 512];
+    fs::File::open(path)?.read_exact(&mut sector)?;
+    Ok(crate::core::iso::has_mbr_signature(&sector))
+}
+
+// From linux/fs.h; libc does not define it
+const BLKRRPART: libc::Ioctl = 0x125f;
+
+/// Ask the kernel to re-read a whole disk's partition table
+///
+/// Fails with EBUSY while one of the old partitions is still mounted and with
+/// EINVAL on a partition node.
+pub fn reread_partition_table(path: &Path) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let device = fs::File::open(path)?;
+    // SAFETY: BLKRRPART takes no argument and the descriptor is open
+    if unsafe { libc::ioctl(device.as_raw_fd(), BLKRRPART) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
 /// Verify that a device path is valid and safe to write to
 #[allow(dead_code)]
 pub fn validate_device(path: &std::path::Path) -> Result<()> {
+    use std::io::Read;
     use std::os::unix::fs::FileTypeExt;
 
     // Check device exists
@@ -80,7 +444,7 @@ pub fn validate_device(path: &std::path::Path) -> Result<()> {
 
     // Check it's a block device
     if !metadata.file_type().is_block_device() {
-        anyhow::bail!("{} is not a block device", path.display());
+        return Err(EtchError::NotBlockDevice(path.to_path_buf()).into());
     }
 
     // Check if any partition is mounted
@@ -93,18 +457,189 @@ pub fn validate_device(path: &std::path::Path) -> Result<()> {
 
     for line in mounts.lines() {
         if line.starts_with(&format!("/dev/{device_name}")) {
-            anyhow::bail!(
-                "Device or one of its partitions is currently mounted. Unmount it first."
-            );
+            return Err(EtchError::DeviceMounted(path.to_path_buf()).into());
         }
     }
 
+    // Write-protect switches and SD card locks show up here
+    let read_only = fs::read_to_string(format!("/sys/class/block/{device_name}/ro"))
+        .is_ok_and(|value| value.trim() == "1");
+    if read_only {
+        return Err(EtchError::DeviceReadOnly(path.to_path_buf()).into());
+    }
+
     // Try to open device for writing to check permissions
     // We don't actually write anything, just check if we can open it
-    std::fs::OpenOptions::new()
+    let mut device = match std::fs::OpenOptions::new()
+        .read(true)
         .write(true)
         .open(path)
-        .context("Cannot open device for writing. Run with sudo/root privileges.")?;
+    {
+        Ok(device) => device,
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            return Err(EtchError::PermissionDenied(path.to_path_buf()).into());
+        }
+        Err(e) if e.raw_os_error() == Some(libc::EROFS) => {
+            return Err(EtchError::DeviceReadOnly(path.to_path_buf()).into());
+        }
+        Err(e) => {
+            return Err(e).context(format!("Cannot open {} for writing", path.display()));
+        }
+    };
+
+    // Locked self-encrypting drives accept the open but fail every read
+    let mut first_sector = [0u8; SYSFS_SECTOR_SIZE as usize];
+    if device.read_exact(&mut first_sector).is_err() {
+        return Err(EtchError::DeviceReadOnly(path.to_path_buf()).into());
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stick(name: &str) -> BlockDevice {
+        BlockDevice {
+            path: PathBuf::from("/dev").join(name),
+            model: "Flash".to_string(),
+            vendor: "Acme".to_string(),
+            capacity_bytes: 16_000_000_000,
+            is_removable: true,
+            is_partition: false,
+            is_ventoy: false,
+        }
+    }
+
+    #[test]
+    fn empty_sysfs_list_does_not_consult_lsblk() {
+        let devices = with_lsblk_fallback(Ok(Vec::new()), || panic!("lsblk consulted")).unwrap();
+        assert!(devices.is_empty());
+    }
+
+    #[test]
+    fn unreadable_sysfs_falls_back_to_lsblk() {
+        let devices = with_lsblk_fallback(Err(anyhow::anyhow!("no /sys/block")), || {
+            Ok(vec![stick("sdb")])
+        })
+        .unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].path, PathBuf::from("/dev/sdb"));
+    }
+
+    #[test]
+    fn both_sources_failing_names_both_causes() {
+        let error = with_lsblk_fallback(Err(anyhow::anyhow!("no /sys/block")), || {
+            Err(anyhow::anyhow!("no lsblk"))
+        })
+        .unwrap_err();
+        let message = format!("{error:#}");
+        assert!(message.contains("no /sys/block"));
+        assert!(message.contains("no lsblk"));
+    }
+
+    /// Write `contents` to `path`, creating its parent directories
+    fn put(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// A removable disk of `sectors` sysfs sectors on a 4K-sector enclosure
+    fn fake_disk(sys_block: &Path, name: &str, removable: &str, sectors: u64) {
+        let disk = sys_block.join(name);
+        put(&disk.join("removable"), &format!("{removable}\n"));
+        put(&disk.join("size"), &format!("{sectors}\n"));
+        put(&disk.join("queue/logical_block_size"), "4096\n");
+        put(&disk.join("device/model"), "Flash Disk      \n");
+        put(&disk.join("device/vendor"), "Acme    \n");
+    }
+
+    #[test]
+    fn capacity_counts_512_byte_sectors_whatever_the_block_size() {
+        let sys_block = tempfile::tempdir().unwrap();
+        fake_disk(sys_block.path(), "etchtest0", "1", 31_260_672);
+        fake_disk(sys_block.path(), "etchtest1", "0", 1_000_000);
+
+        let devices = list_sysfs_devices(sys_block.path()).unwrap();
+        assert_eq!(devices.len(), 1);
+        let disk = &devices[0];
+        assert_eq!(disk.path, PathBuf::from("/dev/etchtest0"));
+        assert_eq!(disk.capacity_bytes, 31_260_672 * SYSFS_SECTOR_SIZE);
+        assert_eq!(disk.model, "Flash Disk");
+        assert_eq!(disk.vendor, "Acme");
+    }
+
+    #[test]
+    fn optical_and_empty_drives_are_skipped() {
+        let sys_block = tempfile::tempdir().unwrap();
+        fake_disk(sys_block.path(), "etchtest0", "1", 0);
+        fake_disk(sys_block.path(), "etchtest1", "1", 2_000_000);
+        put(&sys_block.path().join("etchtest1/device/type"), "5\n");
+
+        assert!(list_sysfs_devices(sys_block.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn partition_capacity_counts_512_byte_sectors() {
+        let sys_block = tempfile::tempdir().unwrap();
+        fake_disk(sys_block.path(), "etchtest0", "1", 31_260_672);
+        let partition = sys_block.path().join("etchtest0/etchtest0p1");
+        put(&partition.join("partition"), "1\n");
+        put(&partition.join("size"), "2048\n");
+
+        let disk = list_sysfs_devices(sys_block.path()).unwrap().remove(0);
+        let partitions = list_partitions_in(sys_block.path(), &disk);
+        assert_eq!(partitions.len(), 1);
+        assert_eq!(partitions[0].path, PathBuf::from("/dev/etchtest0p1"));
+        assert_eq!(partitions[0].capacity_bytes, 1024 * 1024);
+        assert!(partitions[0].is_partition);
+    }
+
+    #[test]
+    fn missing_sysfs_is_an_error() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(list_sysfs_devices(&root.path().join("block")).is_err());
+    }
+
+    #[test]
+    fn temperature_sensor_is_found_under_hwmon() {
+        let class_block = tempfile::tempdir().unwrap();
+        let hwmon = class_block.path().join("sdb/device/hwmon");
+        put(&hwmon.join("hwmon3/name"), "drivetemp\n");
+        put(&hwmon.join("hwmon3/temp1_input"), "41500\n");
+
+        let sensor = find_temperature_sensor(class_block.path(), "sdb").unwrap();
+        assert_eq!(sensor, hwmon.join("hwmon3/temp1_input"));
+        assert_eq!(read_temperature(&sensor), Some(41));
+    }
+
+    #[test]
+    fn first_hwmon_node_with_an_input_wins() {
+        let class_block = tempfile::tempdir().unwrap();
+        let hwmon = class_block.path().join("nvme0n1/device/hwmon");
+        put(&hwmon.join("hwmon1/name"), "nvme\n");
+        put(&hwmon.join("hwmon2/temp1_input"), "38000\n");
+        put(&hwmon.join("hwmon5/temp1_input"), "50000\n");
+
+        let sensor = find_temperature_sensor(class_block.path(), "nvme0n1").unwrap();
+        assert_eq!(sensor, hwmon.join("hwmon2/temp1_input"));
+    }
+
+    #[test]
+    fn device_without_hwmon_has_no_sensor() {
+        let class_block = tempfile::tempdir().unwrap();
+        put(&class_block.path().join("sdc/device/model"), "Flash\n");
+
+        assert_eq!(find_temperature_sensor(class_block.path(), "sdc"), None);
+        assert_eq!(find_temperature_sensor(class_block.path(), "sdz"), None);
+    }
+
+    #[test]
+    fn unreadable_temperature_is_none() {
+        let class_block = tempfile::tempdir().unwrap();
+        let input = class_block.path().join("temp1_input");
+        put(&input, "not a number\n");
+        assert_eq!(read_temperature(&input), None);
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Environment variable holding the post-write hook command
+pub const HOOK_VARIABLE: &str = "ETCH_POST_WRITE_HOOK";
+
+/// How long a hook may run before it is killed
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// How often a running hook is checked for exit, cancel and the deadline
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What a post-write hook did
+#[derive(Debug)]
+pub struct HookOutcome {
+    /// `None` when the hook was killed on cancel or timeout
+    pub status: Option<ExitStatus>,
+    /// Combined stdout and stderr
+    pub output: String,
+}
+
+/// Command from `ETCH_POST_WRITE_HOOK`, if one is configured
+pub fn configured_command() -> Option<String> {
+    std::env::var(HOOK_VARIABLE)
+        .ok()
+        .filter(|command| !command.trim().is_empty())
+}
+
+/// Run the user's post-write hook through `sh -c`, without root privileges
+///
+/// The hook sees `ETCH_DEVICE`, `ETCH_ISO` and `ETCH_RESULT` (`verified`, or
+/// `unverified` when verification was skipped). When Etch runs as root it
+/// switches to the invoking user from `SUDO_UID`/`PKEXEC_UID` first and
+/// refuses to run the hook if it cannot tell who that is.
+///
+/// The hook gets its own process group. If `cancel` is set or it runs past
+/// `timeout`, the whole group is killed, so commands the hook started in the
+/// background do not outlive it either.
+pub fn run(
+    command: &str,
+    device: &Path,
+    iso: &Path,
+    result: &str,
+    cancel: &AtomicBool,
+    timeout: Duration,
+) -> Result<HookOutcome> {
+    let mut hook = Command::new("/bin/sh");
+    hook.arg("-c")
+        .arg(command)
+        .env("ETCH_DEVICE", device)
+        .env("ETCH_ISO", iso)
+        .env("ETCH_RESULT", result)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0);
+
+    // SAFETY: geteuid has no preconditions and cannot fail
+    if unsafe { libc::geteuid() } == 0 {
+        let (uid, gid) = invoking_user().context(
+            "Refusing to run the post-write hook as root: start Etch through sudo or pkexec \
+             so it knows which user to run the hook as",
+        )?;
+        // std also drops root's supplementary groups when switching uid
+        hook.uid(uid).gid(gid);
+    }
+
+    let mut child = hook
+        .spawn()
+        .context(format!("Failed to start post-write hook: {command}"))?;
+    let (Some(mut stdout), Some(mut stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        anyhow::bail!("Post-write hook started without its output pipes");
+    };
+
+    // Drain both pipes while waiting, so a chatty hook cannot block on a full one
+    let (status, stdout, stderr) = thread::scope(|scope| {
+        let stdout = scope.spawn(move || read_lossy(&mut stdout));
+        let stderr = scope.spawn(move || read_lossy(&mut stderr));
+        let status = wait_or_kill(&mut child, cancel, timeout);
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        (status, stdout, stderr)
+    });
+
+    let mut combined = stdout;
+    combined.push_str(&stderr);
+    Ok(HookOutcome {
+        status: status?,
+        output: combined,
+    })
+}
+
+/// Wait for the hook to exit, or kill its process group on cancel or timeout
+///
+/// Returns `None` when the hook was killed. Whatever the hook left running in
+/// its group is killed either way, since it would hold the output pipes open.
+fn wait_or_kill(
+    child: &mut std::process::Child,
+    cancel: &AtomicBool,
+    timeout: Duration,
+) -> Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .context("Failed to wait for post-write hook")?
+        {
+            break Some(status);
+        }
+        if cancel.load(Ordering::Relaxed) || Instant::now() >= deadline {
+            break None;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    // The group id is the hook's pid, since it was started as the group leader
+    if let Ok(group) = libc::pid_t::try_from(child.id()) {
+        // SAFETY: kill has no memory-safety preconditions; a negative pid names the group
+        unsafe { libc::kill(-group, libc::SIGKILL) };
+    }
+    if status.is_none() {
+        child.wait().context("Failed to wait for post-write hook")?;
+    }
+    Ok(status)
+}
+
+/// Everything readable from `pipe`, with invalid UTF-8 replaced
+fn read_lossy(pipe: &mut impl Read) -> String {
+    let mut bytes = Vec::new();
+    let _ = pipe.read_to_end(&mut bytes);
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// UID and primary GID of the user who started Etch through sudo or pkexec
+fn invoking_user() -> Option<(u32, u32)> {
+    let uid: u32 = std::env::var("SUDO_UID")
+        .or_else(|_| std::env::var("PKEXEC_UID"))
+        .ok()?
+        .parse()
+        .ok()?;
+    if uid == 0 {
+        return None;
+    }
+
+    let gid = match std::env::var("SUDO_GID")
+        .ok()
+        .and_then(|gid| gid.parse().ok())
+    {
+        Some(gid) => gid,
+        None => {
+            // SAFETY: getpwuid returns null or a pointer to static storage that
+            // stays valid until the next getpw* call; it is read immediately
+            let entry = unsafe { libc::getpwuid(uid) };
+            if entry.is_null() {
+                return None;
+            }
+            // SAFETY: checked non-null above
+            unsafe { (*entry).pw_gid }
+        }
+    };
+
+    Some((uid, gid))
+}
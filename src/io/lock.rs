@@ -0,0 +1,270 @@
+use crate::core::error::EtchError;
+use anyhow::{Context, Result};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Distinguishes the temporary files of concurrent attempts within one process
+static ATTEMPT: AtomicU32 = AtomicU32::new(0);
+
+/// Exclusive claim on a target device for the duration of one write
+///
+/// A lock file per whole disk lives in the runtime directory and records the
+/// owning PID and start time. Every Etch process checks it before writing, so
+/// two instances (or a sudo and a non-sudo one) can't write the same disk,
+/// nor one of them the disk and the other its partition. The file is removed
+/// when the lock is dropped.
+#[derive(Debug)]
+pub struct DeviceLock {
+    path: PathBuf,
+}
+
+impl DeviceLock {
+    /// Take the lock for `device`, cleaning up locks left by dead processes
+    pub fn acquire(device: &Path) -> Result<Self> {
+        let dir = lock_dir();
+        fs::create_dir_all(&dir).context(format!("Failed to create {}", dir.display()))?;
+        Self::acquire_in(&dir, Path::new("/sys/class/block"), device)
+    }
+
+    /// `acquire` with the lock directory and sysfs given
+    fn acquire_in(dir: &Path, class_block: &Path, device: &Path) -> Result<Self> {
+        let path = lock_path(dir, class_block, device)?;
+
+        // The owner is written to a private file that is then linked into
+        // place, so the lock never exists without its PID
+        let owner = write_owner_file(&path)?;
+        let result = Self::link_owner(&owner, &path, device);
+        if let Err(e) = fs::remove_file(&owner) {
+            eprintln!("WARNING: Failed to remove {}: {e}", owner.display());
+        }
+        result
+    }
+
+    fn link_owner(owner: &Path, path: &Path, device: &Path) -> Result<Self> {
+        // Second attempt only happens after removing a stale lock
+        for _ in 0..2 {
+            match fs::hard_link(owner, path) {
+                Ok(()) => {
+                    return Ok(Self {
+                        path: path.to_path_buf(),
+                    })
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    if let Some(pid) = live_owner(path) {
+                        return Err(EtchError::DeviceBusy {
+                            device: device.to_path_buf(),
+                            pid,
+                        }
+                        .into());
+                    }
+                    eprintln!("INFO: Removing stale lock {}", path.display());
+                    fs::remove_file(path)
+                        .context(format!("Failed to remove stale lock {}", path.display()))?;
+                }
+                Err(e) => {
+                    return Err(e).context(format!("Failed to create lock file {}", path.display()))
+                }
+            }
+        }
+
+        anyhow::bail!("Could not lock {}", device.display())
+    }
+}
+
+impl Drop for DeviceLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            eprintln!(
+                "WARNING: Failed to remove lock {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// Write "<pid> <start time>" to a file next to `lock`, named for this attempt
+fn write_owner_file(lock: &Path) -> Result<PathBuf> {
+    let pid = std::process::id();
+    let attempt = ATTEMPT.fetch_add(1, Ordering::Relaxed);
+    let mut name = OsString::from(".");
+    name.push(lock.file_name().unwrap_or_default());
+    name.push(format!(".{pid}.{attempt}"));
+    let path = lock.with_file_name(name);
+
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let mut file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .context(format!("Failed to create lock file {}", path.display()))?;
+    writeln!(file, "{pid} {started}")
+        .context(format!("Failed to write lock file {}", path.display()))?;
+    Ok(path)
+}
+
+/// `<dir>/<whole disk>.lock`, so a disk and its partitions share one lock
+fn lock_path(dir: &Path, class_block: &Path, device: &Path) -> Result<PathBuf> {
+    // by-id and other links name the same disk as its /dev node
+    let device = fs::canonicalize(device).unwrap_or_else(|_| device.to_path_buf());
+    let name = device
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid device path"))?;
+
+    let mut file_name = whole_disk_name(class_block, name);
+    file_name.push(".lock");
+    Ok(dir.join(file_name))
+}
+
+/// Name of the disk a partition belongs to (`sdb1` -> `sdb`); other names unchanged
+///
+/// A partition's sysfs directory sits inside its disk's directory.
+fn whole_disk_name(class_block: &Path, name: &OsStr) -> OsString {
+    let node = class_block.join(name);
+    if !node.join("partition").exists() {
+        return name.to_os_string();
+    }
+    fs::canonicalize(&node)
+        .ok()
+        .and_then(|node| node.parent()?.file_name().map(OsStr::to_os_string))
+        .unwrap_or_else(|| name.to_os_string())
+}
+
+/// `/run/etch` for root, `$XDG_RUNTIME_DIR/etch` otherwise
+///
+/// Only root can write devices, and whether sudo keeps `XDG_RUNTIME_DIR`
+/// depends on its configuration, so root always uses /run so that every
+/// writing instance agrees on the location.
+pub fn lock_dir() -> PathBuf {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let is_root = unsafe { libc::geteuid() } == 0;
+    let runtime_dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !is_root => PathBuf::from(dir),
+        _ => PathBuf::from("/run"),
+    };
+    runtime_dir.join("etch")
+}
+
+/// PID recorded in a lock file, if that process is still an Etch instance
+fn live_owner(path: &Path) -> Option<u32> {
+    let contents = fs::read_to_string(path).ok()?;
+    let pid: u32 = contents.split_whitespace().next()?.parse().ok()?;
+
+    // Guard against PID reuse by comparing the command name with our own
+    let owner = fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+    let ours = fs::read_to_string("/proc/self/comm").ok()?;
+    (owner == ours).then_some(pid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    /// A `/sys/class/block` with disk `sdb` and its partition `sdb1`
+    fn fake_class_block() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let sdb = root.path().join("devices/usb/block/sdb");
+        fs::create_dir_all(sdb.join("sdb1")).unwrap();
+        fs::write(sdb.join("sdb1/partition"), "1\n").unwrap();
+
+        let class_block = root.path().join("class/block");
+        fs::create_dir_all(&class_block).unwrap();
+        symlink(&sdb, class_block.join("sdb")).unwrap();
+        symlink(sdb.join("sdb1"), class_block.join("sdb1")).unwrap();
+        root
+    }
+
+    fn busy_pid(result: Result<DeviceLock>) -> u32 {
+        match result.unwrap_err().downcast_ref::<EtchError>() {
+            Some(EtchError::DeviceBusy { pid, .. }) => *pid,
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn partition_shares_the_lock_of_its_disk() {
+        let sysfs = fake_class_block();
+        let class_block = sysfs.path().join("class/block");
+        assert_eq!(whole_disk_name(&class_block, OsStr::new("sdb1")), "sdb");
+        assert_eq!(whole_disk_name(&class_block, OsStr::new("sdb")), "sdb");
+        assert_eq!(whole_disk_name(&class_block, OsStr::new("sdc")), "sdc");
+
+        let dir = tempfile::tempdir().unwrap();
+        let _disk =
+            DeviceLock::acquire_in(dir.path(), &class_block, Path::new("/dev/sdb")).unwrap();
+        let partition = DeviceLock::acquire_in(dir.path(), &class_block, Path::new("/dev/sdb1"));
+        assert_eq!(busy_pid(partition), std::process::id());
+    }
+
+    #[test]
+    fn lock_is_released_on_drop() {
+        let sysfs = fake_class_block();
+        let class_block = sysfs.path().join("class/block");
+        let dir = tempfile::tempdir().unwrap();
+        let device = Path::new("/dev/sdb");
+
+        let lock = DeviceLock::acquire_in(dir.path(), &class_block, device).unwrap();
+        let contents = fs::read_to_string(dir.path().join("sdb.lock")).unwrap();
+        assert!(contents.starts_with(&format!("{} ", std::process::id())));
+        drop(lock);
+
+        assert!(!dir.path().join("sdb.lock").exists());
+        assert!(DeviceLock::acquire_in(dir.path(), &class_block, device).is_ok());
+    }
+
+    #[test]
+    fn stale_and_empty_locks_are_replaced() {
+        let sysfs = fake_class_block();
+        let class_block = sysfs.path().join("class/block");
+        let dir = tempfile::tempdir().unwrap();
+        let device = Path::new("/dev/sdb");
+
+        // PIDs never reach u32::MAX, so no process owns this one
+        fs::write(dir.path().join("sdb.lock"), format!("{} 0\n", u32::MAX)).unwrap();
+        drop(DeviceLock::acquire_in(dir.path(), &class_block, device).unwrap());
+
+        fs::write(dir.path().join("sdb.lock"), "").unwrap();
+        assert!(DeviceLock::acquire_in(dir.path(), &class_block, device).is_ok());
+    }
+
+    #[test]
+    fn concurrent_attempts_yield_one_owner_and_no_leftovers() {
+        let sysfs = fake_class_block();
+        let class_block = sysfs.path().join("class/block");
+        let dir = tempfile::tempdir().unwrap();
+        let barrier = std::sync::Barrier::new(8);
+
+        let results: Vec<Result<DeviceLock>> = std::thread::scope(|scope| {
+            let attempts: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        DeviceLock::acquire_in(dir.path(), &class_block, Path::new("/dev/sdb1"))
+                    })
+                })
+                .collect();
+            attempts
+                .into_iter()
+                .map(|attempt| attempt.join().unwrap())
+                .collect()
+        });
+
+        let (owners, busy): (Vec<_>, Vec<_>) = results.into_iter().partition(Result::is_ok);
+        assert_eq!(owners.len(), 1);
+        for result in busy {
+            assert_eq!(busy_pid(result), std::process::id());
+        }
+        let files: Vec<OsString> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, ["sdb.lock"]);
+    }
+}
@@ -1,3 +1,11 @@
 /// Disk I/O operations for writing ISO images to block devices
 pub mod devices;
+pub mod hook;
+pub mod lock;
+pub mod oplog;
+pub mod priority;
+pub mod qemu;
+pub mod reader;
+pub mod span;
+pub mod ventoy;
 pub mod writer;
@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Per-operation diagnostic log
+///
+/// One file per write under `$XDG_STATE_HOME/etch/logs`, named after the start
+/// time and device. Every line is timestamped relative to the start; errors
+/// are synced to disk immediately so the log survives a hung device or crash.
+#[derive(Debug)]
+pub struct OperationLog {
+    file: File,
+    path: PathBuf,
+    started: Instant,
+}
+
+impl OperationLog {
+    /// Create a new log for a write to `device`
+    pub fn create(device: &Path) -> Result<Self> {
+        let dir = log_dir();
+        fs::create_dir_all(&dir).context(format!("Failed to create {}", dir.display()))?;
+
+        let timestamp = glib::DateTime::now_local()
+            .and_then(|now| now.format("%Y%m%d-%H%M%S"))
+            .map_or_else(|_| "unknown".to_string(), |formatted| formatted.to_string());
+        let device_name = device
+            .file_name()
+            .map_or_else(|| "device".into(), |name| name.to_string_lossy());
+        let path = dir.join(format!("{timestamp}-{device_name}.log"));
+
+        // create_new refuses to follow a symlink planted at the log path
+        let file = File::options()
+            .append(true)
+            .create_new(true)
+            .open(&path)
+            .context(format!("Failed to create log {}", path.display()))?;
+
+        Ok(Self {
+            file,
+            path,
+            started: Instant::now(),
+        })
+    }
+
+    /// Reopen an existing log to add to it, e.g. for a follow-up test boot
+    pub fn append_to(path: &Path) -> Result<Self> {
+        let file = File::options()
+            .append(true)
+            .open(path)
+            .context(format!("Failed to open log {}", path.display()))?;
+
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one line; logging failures never interrupt the operation
+    pub fn log(&self, line: impl Display) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if let Err(e) = writeln!(&self.file, "[{elapsed:10.3}s] {line}") {
+            eprintln!("WARNING: Failed to write {}: {e}", self.path.display());
+        }
+    }
+
+    /// Append a failure and make sure it reaches the disk
+    pub fn error(&self, line: impl Display) {
+        self.log(format_args!("ERROR {line}"));
+        if let Err(e) = self.file.sync_all() {
+            eprintln!("WARNING: Failed to sync {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// `$XDG_STATE_HOME/etch/logs`, defaulting to `~/.local/state/etch/logs`
+pub fn log_dir() -> PathBuf {
+    let state_home = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => glib::home_dir().join(".local/state"),
+    };
+    state_home.join("etch").join("logs")
+}
@@ -0,0 +1,32 @@
+use std::io;
+
+// From linux/ioprio.h; libc has the syscall number but no wrapper
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_BE: libc::c_int = 2;
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+/// Lowest level of the best-effort class; the idle class could starve the write
+const BACKGROUND_IO_LEVEL: libc::c_int = 7;
+const BACKGROUND_NICE: libc::c_int = 10;
+
+/// Lower the calling thread's I/O and CPU priority, like `ionice -c2 -n7 nice -n10`
+///
+/// Only the calling thread is affected, so the UI stays at normal priority.
+/// Reads and the flushes the writer issues are deprioritised; writeback the
+/// kernel starts on its own is not, so the effect is best effort.
+pub fn lower_current_thread() -> io::Result<()> {
+    let ioprio = (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | BACKGROUND_IO_LEVEL;
+    // SAFETY: ioprio_set takes no pointers; who = 0 means the calling thread
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: gettid has no preconditions and cannot fail
+    let tid = unsafe { libc::gettid() };
+    // SAFETY: setpriority takes no pointers; on Linux a thread ID selects just that thread
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid.unsigned_abs(), BACKGROUND_NICE) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
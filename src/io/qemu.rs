@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a test boot may run before QEMU is closed
+pub const BOOT_TEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Combined (code + vars) OVMF images usable with `-bios`, by distribution
+const UEFI_FIRMWARE: &[&str] = &[
+    "/usr/share/ovmf/OVMF.fd",
+    "/usr/share/edk2/x64/OVMF.fd",
+    "/usr/share/edk2-ovmf/x64/OVMF.fd",
+    "/usr/share/qemu/ovmf-x86_64.bin",
+];
+
+/// Serial console output that shows the firmware handed over to a bootloader
+const BOOT_MARKERS: &[(&str, &str)] = &[
+    ("GNU GRUB", "GRUB"),
+    ("ISOLINUX", "ISOLINUX"),
+    ("SYSLINUX", "SYSLINUX"),
+    ("Linux version", "Linux kernel"),
+    ("systemd-boot", "systemd-boot"),
+];
+
+/// What a test boot showed
+#[derive(Debug)]
+pub struct BootTestResult {
+    /// None when QEMU was still running at the timeout and was closed
+    pub exit_status: Option<ExitStatus>,
+    /// First bootloader seen on the serial console, if any
+    pub bootloader_seen: Option<&'static str>,
+    pub used_uefi: bool,
+}
+
+/// `qemu-system-x86_64` from PATH, if installed
+pub fn find_qemu() -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join("qemu-system-x86_64"))
+        .find(|candidate| candidate.is_file())
+}
+
+/// An OVMF image for UEFI boots, if one is installed
+pub fn find_uefi_firmware() -> Option<PathBuf> {
+    UEFI_FIRMWARE
+        .iter()
+        .map(PathBuf::from)
+        .find(|candidate| candidate.is_file())
+}
+
+/// Boot `device` read-only in a QEMU window and watch the serial console
+///
+/// The device is attached as a USB stick, as on real hardware. Blocks until
+/// QEMU exits or `BOOT_TEST_TIMEOUT` passes, whichever comes first.
+pub fn test_boot(
+    qemu: &Path,
+    device: &Path,
+    uefi_firmware: Option<&Path>,
+) -> Result<BootTestResult> {
+    let mut drive = std::ffi::OsString::from("if=none,id=stick,format=raw,readonly=on,file=");
+    drive.push(device);
+
+    let mut command = Command::new(qemu);
+    command
+        .args(["-machine", "accel=kvm:tcg", "-m", "2048"])
+        .args(["-device", "qemu-xhci", "-device", "usb-storage,drive=stick"])
+        .arg("-drive")
+        .arg(drive)
+        .args(["-serial", "stdio", "-name", "Etch test boot"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    if let Some(firmware) = uefi_firmware {
+        command.arg("-bios").arg(firmware);
+    }
+
+    let mut child = command
+        .spawn()
+        .context(format!("Failed to start {}", qemu.display()))?;
+
+    // The reader ends when QEMU exits or is killed and its stdout closes
+    let stdout = child.stdout.take().context("QEMU stdout unavailable")?;
+    let reader = thread::spawn(move || scan_serial(BufReader::new(stdout)));
+
+    let started = Instant::now();
+    let exit_status = loop {
+        if let Some(status) = child.try_wait().context("Failed to wait for QEMU")? {
+            break Some(status);
+        }
+        if started.elapsed() >= BOOT_TEST_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    Ok(BootTestResult {
+        exit_status,
+        bootloader_seen: reader.join().unwrap_or(None),
+        used_uefi: uefi_firmware.is_some(),
+    })
+}
+
+fn scan_serial(serial: impl BufRead) -> Option<&'static str> {
+    let mut seen = None;
+    // Keep draining after a match so QEMU never blocks on a full pipe
+    for line in serial.split(b'\n').map_while(Result::ok) {
+        if seen.is_some() {
+            continue;
+        }
+        let line = String::from_utf8_lossy(&line);
+        seen = BOOT_MARKERS
+            .iter()
+            .find(|(marker, _)| line.contains(marker))
+            .map(|&(_, name)| name);
+    }
+    seen
+}
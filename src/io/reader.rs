@@ -0,0 +1,272 @@
+use crate::core::error::EtchError;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use crate::io::writer::CHUNK_SIZE;
+
+/// Trimmed images end on a whole sector
+const SECTOR_SIZE: usize = 512;
+
+/// What a device read produced
+#[derive(Debug, Clone)]
+pub struct ImageResult {
+    /// Length of the image file
+    pub image_size: u64,
+    /// SHA-256 of the image file, lowercase hex
+    pub sha256: String,
+}
+
+/// Read a whole device back into an image file
+///
+/// Zero-filled chunks are skipped over rather than written, so the image is
+/// sparse on filesystems that support it. With `trim_zeros` the trailing run
+/// of zeros is left out entirely, down to the last non-zero sector. Setting
+/// `cancel` stops at the next chunk boundary and removes the partial image.
+/// The image's SHA-256 is also written next to it as `<image>.sha256`.
+pub fn read_device(
+    source_device: &Path,
+    output: &Path,
+    trim_zeros: bool,
+    cancel: &AtomicBool,
+    progress_callback: impl Fn(u64, u64, u64), // (bytes_read, total_bytes, bytes_per_second)
+) -> Result<ImageResult> {
+    let mut source = File::open(source_device).context(format!(
+        "Failed to open device for reading: {}. Are you running with sudo?",
+        source_device.display()
+    ))?;
+    // Block devices report a zero length in their metadata
+    let total_size = source
+        .seek(SeekFrom::End(0))
+        .and_then(|size| source.seek(SeekFrom::Start(0)).map(|_| size))
+        .context("Failed to get device size")?;
+
+    let mut image =
+        File::create(output).context(format!("Failed to create image {}", output.display()))?;
+
+    let result = copy_to_image(
+        &mut source,
+        &mut image,
+        total_size,
+        trim_zeros,
+        cancel,
+        progress_callback,
+    );
+    if result.is_err() {
+        drop(image);
+        let _ = std::fs::remove_file(output);
+    }
+    let result = result?;
+
+    write_checksum_file(output, &result.sha256)?;
+    Ok(result)
+}
+
+/// Write `<image>.sha256` in `sha256sum -c` format, so the archive can be checked without Etch
+pub fn write_checksum_file(image: &Path, sha256: &str) -> Result<()> {
+    let mut checksum_path = image.as_os_str().to_os_string();
+    checksum_path.push(".sha256");
+    let name = image
+        .file_name()
+        .map_or_else(|| image.to_string_lossy(), |name| name.to_string_lossy());
+    std::fs::write(&checksum_path, format!("{sha256}  {name}\n")).context(format!(
+        "Failed to write {}",
+        Path::new(&checksum_path).display()
+    ))
+}
+
+fn copy_to_image(
+    source: &mut File,
+    image: &mut File,
+    total_size: u64,
+    trim_zeros: bool,
+    cancel: &AtomicBool,
+    progress_callback: impl Fn(u64, u64, u64),
+) -> Result<ImageResult> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let zeros = vec![0u8; CHUNK_SIZE];
+    let mut hasher = Sha256::new();
+    let mut total_read: u64 = 0;
+    // Zeros seen but not yet hashed or written; dropped if they turn out to trail
+    let mut pending_zeros: u64 = 0;
+    let start_time = Instant::now();
+    let mut last_progress_time = start_time;
+
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(EtchError::Cancelled {
+                bytes_done: total_read,
+            }
+            .into());
+        }
+
+        let bytes_read = source
+            .read(&mut buffer)
+            .context(format!("Failed to read device at offset {total_read}"))?;
+        if bytes_read == 0 {
+            break;
+        }
+        let chunk = &buffer[..bytes_read];
+        total_read += bytes_read as u64;
+
+        match chunk.iter().rposition(|&byte| byte != 0) {
+            None => pending_zeros += bytes_read as u64,
+            Some(last_data) => {
+                hash_zeros(&mut hasher, &zeros, pending_zeros);
+                image
+                    .seek(SeekFrom::Current(
+                        i64::try_from(pending_zeros).context("Image too large")?,
+                    ))
+                    .context("Failed to extend image")?;
+                pending_zeros = 0;
+
+                let keep = (last_data + 1)
+                    .next_multiple_of(SECTOR_SIZE)
+                    .min(bytes_read);
+                hasher.update(&chunk[..keep]);
+                image
+                    .write_all(&chunk[..keep])
+                    .context("Failed to write image")?;
+                pending_zeros += (bytes_read - keep) as u64;
+            }
+        }
+
+        let now = Instant::now();
+        if now.duration_since(last_progress_time).as_millis() >= 100 || total_read == total_size {
+            let elapsed = now.duration_since(start_time).as_secs_f64();
+            #[allow(
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss,
+                clippy::cast_precision_loss
+            )]
+            let bytes_per_second = if elapsed > 0.0 {
+                (total_read as f64 / elapsed) as u64
+            } else {
+                0
+            };
+            progress_callback(total_read, total_size, bytes_per_second);
+            last_progress_time = now;
+        }
+    }
+
+    if !trim_zeros {
+        hash_zeros(&mut hasher, &zeros, pending_zeros);
+    }
+    let image_size = if trim_zeros {
+        total_read - pending_zeros
+    } else {
+        total_read
+    };
+    // Sets the length of a trailing hole that was only seeked over
+    image.set_len(image_size).context("Failed to size image")?;
+    image.sync_all().context("Failed to sync image")?;
+
+    Ok(ImageResult {
+        image_size,
+        sha256: format!("{:x}", hasher.finalize()),
+    })
+}
+
+fn hash_zeros(hasher: &mut Sha256, zeros: &[u8], mut count: u64) {
+    while count > 0 {
+        let step = usize::try_from(count).map_or(zeros.len(), |count| count.min(zeros.len()));
+        hasher.update(&zeros[..step]);
+        count -= step as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Read `device` into an image in a fresh directory, returning the result and the image
+    fn read(device: &[u8], trim_zeros: bool) -> (ImageResult, Vec<u8>, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let device_path = dir.path().join("device");
+        std::fs::write(&device_path, device).unwrap();
+        let output = dir.path().join("backup.img");
+
+        let result = read_device(
+            &device_path,
+            &output,
+            trim_zeros,
+            &AtomicBool::new(false),
+            |_, _, _| {},
+        )
+        .unwrap();
+        let image = std::fs::read(&output).unwrap();
+        (result, image, dir)
+    }
+
+    fn sha256_hex(bytes: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(bytes))
+    }
+
+    /// 700 bytes of data, then two chunks of zeros
+    fn trailing_zeros() -> Vec<u8> {
+        let mut device = vec![0x5A; 700];
+        device.resize(700 + 2 * CHUNK_SIZE, 0);
+        device
+    }
+
+    #[test]
+    fn interior_zero_run_is_kept_and_hashed() {
+        let mut device = vec![0xAA; 1000];
+        device.resize(1000 + 2 * CHUNK_SIZE + 17, 0);
+        device.extend([0xBB; 1000]);
+
+        let (result, image, dir) = read(&device, true);
+        assert_eq!(image, device);
+        assert_eq!(result.image_size, device.len() as u64);
+        assert_eq!(result.sha256, sha256_hex(&device));
+        let checksum = std::fs::read_to_string(dir.path().join("backup.img.sha256")).unwrap();
+        assert_eq!(checksum, format!("{}  backup.img\n", result.sha256));
+    }
+
+    #[test]
+    fn trailing_zeros_are_trimmed_to_a_sector() {
+        let device = trailing_zeros();
+
+        let (result, image, _dir) = read(&device, true);
+        assert_eq!(result.image_size, 2 * SECTOR_SIZE as u64);
+        assert_eq!(image, device[..2 * SECTOR_SIZE]);
+        assert_eq!(result.sha256, sha256_hex(&image));
+    }
+
+    #[test]
+    fn untrimmed_image_keeps_the_full_length() {
+        let device = trailing_zeros();
+
+        let (result, image, _dir) = read(&device, false);
+        assert_eq!(result.image_size, device.len() as u64);
+        assert_eq!(image, device);
+        assert_eq!(result.sha256, sha256_hex(&device));
+    }
+
+    #[test]
+    fn cancelling_removes_the_partial_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let device_path = dir.path().join("device");
+        std::fs::write(&device_path, trailing_zeros()).unwrap();
+        let output = dir.path().join("backup.img");
+
+        let error = read_device(
+            &device_path,
+            &output,
+            true,
+            &AtomicBool::new(true),
+            |_, _, _| {},
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<EtchError>(),
+            Some(EtchError::Cancelled { bytes_done: 0 })
+        ));
+        assert!(!output.exists());
+        assert!(!dir.path().join("backup.img.sha256").exists());
+    }
+}
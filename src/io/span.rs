@@ -0,0 +1,651 @@
+use crate::core::error::EtchError;
+use crate::core::span::{plan_parts, PartHeader, HEADER_SIZE, MAX_PARTS};
+use crate::io::writer::CHUNK_SIZE;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// What a part is going through, for progress reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanPhase {
+    /// Writing the part's data to its device
+    Writing,
+    /// Reading the written part back before its header is added
+    Checking,
+    /// Copying a part from its device into the restored image
+    Reading,
+}
+
+/// The device for the next part is needed
+#[derive(Debug)]
+pub struct DeviceRequest<'a> {
+    /// Counting from 0
+    pub index: u32,
+    pub total: u32,
+    /// Smallest device that can hold the part; 0 when reading parts back
+    pub min_bytes: u64,
+    /// Why the device given last time for this part was refused
+    pub rejected: Option<&'a anyhow::Error>,
+}
+
+/// Outcome of restoring a spanned write into an image file
+#[derive(Debug, Clone)]
+pub struct RestoredImage {
+    pub image_size: u64,
+    pub parts: u32,
+    /// SHA-256 of the restored image, lowercase hex
+    pub sha256: String,
+}
+
+/// Split `source_image` across several devices, one part per device
+///
+/// Parts are planned from the first device's size; each later device must be
+/// at least that large. The header block of each device is zeroed first and
+/// written last, after the part's data has been read back and matches, so an
+/// interrupted part never passes for a finished one. The result is raw data
+/// for `restore_spanned`, not a bootable device.
+///
+/// Between parts the write pauses in `next_device`, which blocks until the
+/// user has swapped devices and returns the one for the requested part, or
+/// `None` to stop. No device is open while it waits. A device that cannot
+/// take the part (too small, or already holding a part of this write) is
+/// refused before anything is written and requested again.
+pub fn write_spanned(
+    source_image: &Path,
+    first_device: &Path,
+    cancel: &AtomicBool,
+    mut next_device: impl FnMut(DeviceRequest<'_>) -> Option<PathBuf>,
+    progress_callback: impl Fn(u32, SpanPhase, u64, u64, u64), // (part, phase, bytes_done, part_bytes, bytes_per_second)
+) -> Result<Vec<PartHeader>> {
+    let mut source = File::open(source_image).context(format!(
+        "Failed to open source image: {}",
+        source_image.display()
+    ))?;
+    let image_size = source
+        .metadata()
+        .context("Failed to get source file size")?
+        .len();
+
+    let device_bytes = File::open(first_device)
+        .and_then(|mut device| crate::io::devices::block_device_size(&mut device))
+        .context(format!(
+            "Failed to get the size of {}",
+            first_device.display()
+        ))?;
+    let parts = plan_parts(image_size, device_bytes).with_context(|| {
+        format!(
+            "{} cannot hold a part of a {image_size} byte image split across at most {MAX_PARTS} devices",
+            first_device.display()
+        )
+    })?;
+    let total = u32::try_from(parts.len()).context("Too many parts")?;
+
+    // Tells this write's parts apart from those of any other
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    #[allow(clippy::cast_possible_truncation)]
+    let set_id = (nanos as u64) ^ (u64::from(std::process::id()) << 32);
+
+    let mut headers = Vec::with_capacity(parts.len());
+    let mut device = first_device.to_path_buf();
+    for (index, part) in (0..total).zip(&parts) {
+        let header = PartHeader {
+            set_id,
+            index,
+            total,
+            image_size,
+            offset: part.start,
+            length: part.end - part.start,
+            sha256: [0; 32],
+        };
+
+        if index > 0 {
+            let mut rejected = None;
+            device = loop {
+                let request = DeviceRequest {
+                    index,
+                    total,
+                    min_bytes: HEADER_SIZE + header.length,
+                    rejected: rejected.as_ref(),
+                };
+                let Some(device) = next_device(request) else {
+                    return Err(EtchError::Cancelled {
+                        bytes_done: part.start,
+                    }
+                    .into());
+                };
+                match check_target(&device, &header) {
+                    Ok(()) => break device,
+                    Err(e) => rejected = Some(e),
+                }
+            };
+        }
+
+        let progress =
+            |phase, bytes, length, bps| progress_callback(index, phase, bytes, length, bps);
+        headers.push(write_part(&mut source, &device, header, cancel, progress)?);
+    }
+
+    Ok(headers)
+}
+
+/// Refuse a device that is too small for the part or already holds part of this write
+fn check_target(device: &Path, header: &PartHeader) -> Result<()> {
+    let mut target = File::open(device).context(format!("Failed to open {}", device.display()))?;
+    let device_bytes = crate::io::devices::block_device_size(&mut target)
+        .context(format!("Failed to get the size of {}", device.display()))?;
+    ensure_room(device, device_bytes, header)?;
+
+    if let Some(existing) =
+        read_header(&mut target).filter(|existing| existing.set_id == header.set_id)
+    {
+        anyhow::bail!(
+            "{} already holds part {} of this write. Insert the device for part {}.",
+            device.display(),
+            existing.index + 1,
+            header.index + 1
+        );
+    }
+    Ok(())
+}
+
+/// Write one part's data after the header block, read it back, then write the header
+fn write_part(
+    source: &mut File,
+    device: &Path,
+    mut header: PartHeader,
+    cancel: &AtomicBool,
+    progress_callback: impl Fn(SpanPhase, u64, u64, u64),
+) -> Result<PartHeader> {
+    let mut target = File::options()
+        .read(true)
+        .write(true)
+        .open(device)
+        .context(format!(
+            "Failed to open target device for writing: {}. Are you running with sudo?",
+            device.display()
+        ))?;
+    let device_bytes = crate::io::devices::block_device_size(&mut target)
+        .context("Failed to get target device size")?;
+    ensure_room(device, device_bytes, &header)?;
+
+    // Whatever was there before, this is not a finished part until the end
+    target
+        .write_all(&[0; HEADER_SIZE as usize])
+        .context("Failed to clear the part header")?;
+    source
+        .seek(SeekFrom::Start(header.offset))
+        .context("Failed to seek source image")?;
+
+    let sha256 = copy_hashed(
+        source,
+        &mut target,
+        header.length,
+        cancel,
+        header.offset,
+        |bytes, bps| progress_callback(SpanPhase::Writing, bytes, header.length, bps),
+    )
+    .context("Failed to write part")?;
+    target.sync_all().context("Failed to sync data to disk")?;
+
+    let mut written = File::open(device).context(format!(
+        "Failed to open target device for reading: {}",
+        device.display()
+    ))?;
+    written
+        .seek(SeekFrom::Start(HEADER_SIZE))
+        .context("Failed to seek target device")?;
+    let read_back = copy_hashed(
+        &mut written,
+        &mut std::io::sink(),
+        header.length,
+        cancel,
+        header.offset,
+        |bytes, bps| progress_callback(SpanPhase::Checking, bytes, header.length, bps),
+    )
+    .context("Failed to read the part back")?;
+    if read_back != sha256 {
+        return Err(EtchError::PartCorrupt {
+            path: device.to_path_buf(),
+            index: header.index,
+        }
+        .into());
+    }
+
+    header.sha256 = sha256;
+    target
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| target.write_all(&header.encode()))
+        .context("Failed to write the part header")?;
+    target.sync_all().context("Failed to sync data to disk")?;
+    Ok(header)
+}
+
+/// Join the parts of a spanned write back into an image file
+///
+/// Parts are read in order, starting with `first_source`; `next_source`
+/// blocks until the device (or file) with the requested part is available,
+/// or returns `None` to stop. A source holding the wrong part is refused and
+/// requested again. Each part is checked against the SHA-256 in its header.
+/// On failure the partial image is removed. The image's SHA-256 is also
+/// written next to it as `<image>.sha256`.
+pub fn restore_spanned(
+    first_source: &Path,
+    output: &Path,
+    cancel: &AtomicBool,
+    next_source: impl FnMut(DeviceRequest<'_>) -> Option<PathBuf>,
+    progress_callback: impl Fn(u32, SpanPhase, u64, u64, u64), // (part, phase, bytes_done, part_bytes, bytes_per_second)
+) -> Result<RestoredImage> {
+    let mut image =
+        File::create(output).context(format!("Failed to create image {}", output.display()))?;
+    let result = restore_parts(
+        first_source,
+        &mut image,
+        cancel,
+        next_source,
+        progress_callback,
+    );
+    if result.is_err() {
+        drop(image);
+        let _ = std::fs::remove_file(output);
+    }
+    let result = result?;
+
+    crate::io::reader::write_checksum_file(output, &result.sha256)?;
+    Ok(result)
+}
+
+fn restore_parts(
+    first_source: &Path,
+    image: &mut File,
+    cancel: &AtomicBool,
+    mut next_source: impl FnMut(DeviceRequest<'_>) -> Option<PathBuf>,
+    progress_callback: impl Fn(u32, SpanPhase, u64, u64, u64),
+) -> Result<RestoredImage> {
+    let mut image_hasher = Sha256::new();
+    let mut first: Option<PartHeader> = None;
+    let mut offset = 0;
+    let mut index = 0;
+    let mut source_path = first_source.to_path_buf();
+
+    loop {
+        let (mut source, header) = match open_part(&source_path, first.as_ref(), index, offset) {
+            Ok(part) => part,
+            Err(e) if index > 0 => {
+                let total = first.as_ref().map_or(0, |first| first.total);
+                let request = DeviceRequest {
+                    index,
+                    total,
+                    min_bytes: 0,
+                    rejected: Some(&e),
+                };
+                source_path =
+                    next_source(request).ok_or(EtchError::Cancelled { bytes_done: offset })?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut hashing = HashingWriter {
+            inner: &mut *image,
+            hasher: &mut image_hasher,
+        };
+        let sha256 = copy_hashed(
+            &mut source,
+            &mut hashing,
+            header.length,
+            cancel,
+            offset,
+            |bytes, bps| progress_callback(index, SpanPhase::Reading, bytes, header.length, bps),
+        )
+        .context(format!("Failed to restore part {}", index + 1))?;
+        if sha256 != header.sha256 {
+            return Err(EtchError::PartCorrupt {
+                path: source_path,
+                index,
+            }
+            .into());
+        }
+
+        offset += header.length;
+        index += 1;
+        let total = header.total;
+        let image_size = header.image_size;
+        first.get_or_insert(header);
+        if index == total {
+            if offset != image_size {
+                anyhow::bail!("The parts hold {offset} bytes of a {image_size} byte image");
+            }
+            image.sync_all().context("Failed to sync image")?;
+            let sha256 = image_hasher.finalize();
+            return Ok(RestoredImage {
+                image_size,
+                parts: total,
+                sha256: sha256.iter().map(|byte| format!("{byte:02x}")).collect(),
+            });
+        }
+
+        let request = DeviceRequest {
+            index,
+            total,
+            min_bytes: 0,
+            rejected: None,
+        };
+        source_path = next_source(request).ok_or(EtchError::Cancelled { bytes_done: offset })?;
+    }
+}
+
+/// Open a part source and check it is part `index` of the set, positioned at its data
+fn open_part(
+    path: &Path,
+    first: Option<&PartHeader>,
+    index: u32,
+    offset: u64,
+) -> Result<(File, PartHeader)> {
+    let mut source = File::open(path).context(format!(
+        "Failed to open {} for reading. Are you running with sudo?",
+        path.display()
+    ))?;
+    let header =
+        read_header(&mut source).ok_or_else(|| EtchError::NotSpannedPart(path.to_path_buf()))?;
+    header.check_sequence(path, first, index, offset)?;
+    source
+        .seek(SeekFrom::Start(HEADER_SIZE))
+        .context("Failed to seek to the part data")?;
+    Ok((source, header))
+}
+
+/// Refuse a device too small for the part and its header block
+fn ensure_room(device: &Path, device_bytes: u64, header: &PartHeader) -> Result<()> {
+    if HEADER_SIZE + header.length > device_bytes {
+        return Err(EtchError::DeviceTooSmall {
+            device: device.to_path_buf(),
+            device_bytes,
+            image_bytes: HEADER_SIZE + header.length,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// The part header at the start of `device`, if it holds one
+pub fn read_header(device: &mut File) -> Option<PartHeader> {
+    let mut block = [0u8; HEADER_SIZE as usize];
+    device.seek(SeekFrom::Start(0)).ok()?;
+    device.read_exact(&mut block).ok()?;
+    PartHeader::decode(&block)
+}
+
+/// Copy exactly `length` bytes, returning their SHA-256
+///
+/// `base` is where the copy starts in the image, for error messages and the
+/// cancel report. Setting `cancel` stops the copy at the next chunk boundary.
+fn copy_hashed(
+    source: &mut impl Read,
+    target: &mut impl Write,
+    length: u64,
+    cancel: &AtomicBool,
+    base: u64,
+    progress_callback: impl Fn(u64, u64), // (bytes_copied, bytes_per_second)
+) -> Result<[u8; 32]> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut hasher = Sha256::new();
+    let mut copied: u64 = 0;
+    let start_time = Instant::now();
+    let mut last_progress_time = start_time;
+
+    while copied < length {
+        if cancel.load(Ordering::Relaxed) {
+            let _ = target.flush();
+            return Err(EtchError::Cancelled {
+                bytes_done: base + copied,
+            }
+            .into());
+        }
+
+        let want = usize::try_from(length - copied).map_or(CHUNK_SIZE, |r| r.min(CHUNK_SIZE));
+        source
+            .read_exact(&mut buffer[..want])
+            .context(format!("Failed to read at offset {}", base + copied))?;
+        hasher.update(&buffer[..want]);
+        target
+            .write_all(&buffer[..want])
+            .context(format!("Failed to write at offset {}", base + copied))?;
+        copied += want as u64;
+
+        let now = Instant::now();
+        if now.duration_since(last_progress_time).as_millis() >= 100 || copied == length {
+            let elapsed = now.duration_since(start_time).as_secs_f64();
+            #[allow(
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss,
+                clippy::cast_precision_loss
+            )]
+            let bytes_per_second = if elapsed > 0.0 {
+                (copied as f64 / elapsed) as u64
+            } else {
+                0
+            };
+            progress_callback(copied, bytes_per_second);
+            last_progress_time = now;
+        }
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+/// Passes writes through while hashing them into the whole-image digest
+struct HashingWriter<'a, W: Write> {
+    inner: &'a mut W,
+    hasher: &'a mut Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    const MIB: u64 = 1024 * 1024;
+    /// Holds 2 MiB of data, so the image needs three of them
+    const DEVICE_SIZE: u64 = 2 * MIB + HEADER_SIZE;
+    const IMAGE_SIZE: u64 = 5 * MIB + 123;
+
+    struct Fixture {
+        dir: tempfile::TempDir,
+        image: PathBuf,
+        devices: Vec<PathBuf>,
+    }
+
+    impl Fixture {
+        fn new(devices: usize) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let image = dir.path().join("archive.img");
+            let bytes: Vec<u8> = (0..IMAGE_SIZE).map(|i| (i % 251) as u8).collect();
+            std::fs::write(&image, bytes).unwrap();
+            let devices = (0..devices)
+                .map(|n| {
+                    let device = dir.path().join(format!("device{n}"));
+                    File::create(&device).unwrap().set_len(DEVICE_SIZE).unwrap();
+                    device
+                })
+                .collect();
+            Self {
+                dir,
+                image,
+                devices,
+            }
+        }
+
+        /// Write the image, handing out `offers` in turn whenever a device is requested
+        fn write(&self, offers: &[&Path]) -> (Result<Vec<PartHeader>>, Vec<(u32, bool)>) {
+            let requests = RefCell::new(Vec::new());
+            let mut offers = offers.iter();
+            let result = write_spanned(
+                &self.image,
+                &self.devices[0],
+                &AtomicBool::new(false),
+                |request| {
+                    assert_eq!(request.total, 3);
+                    // The last part is shorter and fits a smaller device
+                    let length = if request.index == 2 {
+                        MIB + 123
+                    } else {
+                        2 * MIB
+                    };
+                    assert_eq!(request.min_bytes, HEADER_SIZE + length);
+                    requests
+                        .borrow_mut()
+                        .push((request.index, request.rejected.is_some()));
+                    offers.next().map(|offer| offer.to_path_buf())
+                },
+                |_, _, _, _, _| {},
+            );
+            (result, requests.into_inner())
+        }
+
+        fn restore(&self, first: &Path, offers: &[&Path]) -> (Result<RestoredImage>, PathBuf) {
+            let output = self.dir.path().join("restored.img");
+            let mut offers = offers.iter();
+            let result = restore_spanned(
+                first,
+                &output,
+                &AtomicBool::new(false),
+                |_| offers.next().map(|offer| offer.to_path_buf()),
+                |_, _, _, _, _| {},
+            );
+            (result, output)
+        }
+    }
+
+    #[test]
+    fn spanned_write_restores_to_the_same_image() {
+        let fixture = Fixture::new(3);
+        let [first, second, third] = [0, 1, 2].map(|n| fixture.devices[n].as_path());
+
+        let (headers, requests) = fixture.write(&[second, third]);
+        let headers = headers.unwrap();
+        assert_eq!(requests, [(1, false), (2, false)]);
+        let lengths: Vec<u64> = headers.iter().map(|header| header.length).collect();
+        assert_eq!(lengths, [2 * MIB, 2 * MIB, MIB + 123]);
+
+        let (restored, output) = fixture.restore(first, &[second, third]);
+        let restored = restored.unwrap();
+        assert_eq!(restored.parts, 3);
+        assert_eq!(restored.image_size, IMAGE_SIZE);
+        assert!(std::fs::read(&output).unwrap() == std::fs::read(&fixture.image).unwrap());
+        let checksum = std::fs::read_to_string(output.with_extension("img.sha256")).unwrap();
+        assert_eq!(checksum, format!("{}  restored.img\n", restored.sha256));
+    }
+
+    #[test]
+    fn device_of_an_earlier_part_or_too_small_is_requested_again() {
+        let fixture = Fixture::new(4);
+        let small = fixture.devices[3].as_path();
+        File::options()
+            .write(true)
+            .open(small)
+            .unwrap()
+            .set_len(MIB)
+            .unwrap();
+        let [first, second, third] = [0, 1, 2].map(|n| fixture.devices[n].as_path());
+
+        let (result, requests) = fixture.write(&[first, small, second, third]);
+        result.unwrap();
+        assert_eq!(requests, [(1, false), (1, true), (1, true), (2, false)]);
+        // The refused device still holds part 1
+        let header = read_header(&mut File::open(first).unwrap()).unwrap();
+        assert_eq!(header.index, 0);
+    }
+
+    #[test]
+    fn stopping_between_parts_is_a_cancel() {
+        let fixture = Fixture::new(3);
+        let (result, _) = fixture.write(&[]);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<EtchError>(),
+            Some(EtchError::Cancelled { bytes_done }) if *bytes_done == 2 * MIB
+        ));
+    }
+
+    #[test]
+    fn parts_out_of_order_are_requested_again() {
+        let fixture = Fixture::new(3);
+        let [first, second, third] = [0, 1, 2].map(|n| fixture.devices[n].as_path());
+        fixture.write(&[second, third]).0.unwrap();
+
+        let requests = RefCell::new(Vec::new());
+        let mut offers = [third, second, third].into_iter();
+        let output = fixture.dir.path().join("restored.img");
+        restore_spanned(
+            first,
+            &output,
+            &AtomicBool::new(false),
+            |request| {
+                let rejected = request.rejected.map(|e| {
+                    matches!(
+                        e.downcast_ref::<EtchError>(),
+                        Some(EtchError::PartOutOfOrder {
+                            expected: 2,
+                            found: 3,
+                            ..
+                        })
+                    )
+                });
+                requests.borrow_mut().push((request.index, rejected));
+                offers.next().map(Path::to_path_buf)
+            },
+            |_, _, _, _, _| {},
+        )
+        .unwrap();
+        assert_eq!(
+            requests.into_inner(),
+            [(1, None), (1, Some(true)), (2, None)]
+        );
+    }
+
+    #[test]
+    fn corrupt_part_fails_and_removes_the_partial_image() {
+        let fixture = Fixture::new(3);
+        let [first, second, third] = [0, 1, 2].map(|n| fixture.devices[n].as_path());
+        fixture.write(&[second, third]).0.unwrap();
+
+        use std::os::unix::fs::FileExt;
+        let device = File::options().write(true).open(second).unwrap();
+        device.write_all_at(&[0xFF], HEADER_SIZE + 1000).unwrap();
+
+        let (result, output) = fixture.restore(first, &[second, third]);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<EtchError>(),
+            Some(EtchError::PartCorrupt { index: 1, .. })
+        ));
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn device_without_a_finished_part_is_not_restored() {
+        let fixture = Fixture::new(3);
+        let (result, output) = fixture.restore(&fixture.devices[0], &[]);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<EtchError>(),
+            Some(EtchError::NotSpannedPart(_))
+        ));
+        assert!(!output.exists());
+    }
+}
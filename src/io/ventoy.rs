@@ -0,0 +1,505 @@
+//! Adding images to Ventoy sticks without erasing them
+//!
+//! A Ventoy stick boots any ISO copied onto its first partition, so for those
+//! the image is copied as a file instead of written over the whole device.
+
+use crate::core::error::EtchError;
+use crate::io::writer::{WriteStatus, CHUNK_SIZE};
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Label of the exFAT (or NTFS) partition the images go on
+const DATA_LABEL: &str = "Ventoy";
+/// Label of Ventoy's own boot partition
+const EFI_LABEL: &str = "VTOYEFI";
+/// Ventoy always creates its boot partition with exactly this size
+const EFI_PARTITION_BYTES: u64 = 32 * 1024 * 1024;
+
+/// How often a paused copy checks whether it may continue
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The data partition of a disk laid out by Ventoy, if it is one
+///
+/// Requires partition 1 labelled `Ventoy` and a 32 MiB partition 2 labelled
+/// `VTOYEFI`. Labels come from udev's `/dev/disk/by-label`.
+pub fn data_partition(disk: &Path) -> Option<PathBuf> {
+    data_partition_in(
+        Path::new("/sys/block"),
+        Path::new("/dev/disk/by-label"),
+        disk,
+    )
+}
+
+fn data_partition_in(sys_block: &Path, by_label: &Path, disk: &Path) -> Option<PathBuf> {
+    let disk_name = disk.file_name()?;
+    let labels = partition_labels(by_label);
+
+    let mut data = None;
+    let mut has_efi = false;
+    for entry in fs::read_dir(sys_block.join(disk_name)).ok()?.flatten() {
+        let Ok(number) = fs::read_to_string(entry.path().join("partition")) else {
+            continue;
+        };
+        let name = entry.file_name();
+        match (number.trim(), labels.get(&name).map(String::as_str)) {
+            ("1", Some(DATA_LABEL)) => data = Some(Path::new("/dev").join(name)),
+            ("2", Some(EFI_LABEL)) => {
+                has_efi = fs::read_to_string(entry.path().join("size"))
+                    .ok()
+                    .and_then(|sectors| sectors.trim().parse::<u64>().ok())
+                    .is_some_and(|sectors| {
+                        sectors * crate::io::devices::SYSFS_SECTOR_SIZE == EFI_PARTITION_BYTES
+                    });
+            }
+            _ => {}
+        }
+    }
+
+    data.filter(|_| has_efi)
+}
+
+/// Filesystem labels by device name, from the links in `by_label`
+///
+/// udev points each link straight at the node (`../../sdb1`), so the link's
+/// last component names the partition.
+fn partition_labels(by_label: &Path) -> HashMap<OsString, String> {
+    let Ok(entries) = fs::read_dir(by_label) else {
+        return HashMap::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let node = fs::read_link(entry.path()).ok()?;
+            let label = entry.file_name().to_str()?.to_string();
+            Some((node.file_name()?.to_os_string(), label))
+        })
+        .collect()
+}
+
+/// The data partition, mounted for the duration of a copy
+///
+/// A partition the desktop has already mounted is used where it is and left
+/// mounted; otherwise it is mounted under the runtime directory and unmounted
+/// when this is dropped.
+#[derive(Debug)]
+pub struct DataMount {
+    dir: PathBuf,
+    mounted_here: bool,
+}
+
+impl DataMount {
+    pub fn mount(partition: &Path) -> Result<Self> {
+        if let Some(dir) = existing_mount(partition) {
+            return Ok(Self {
+                dir,
+                mounted_here: false,
+            });
+        }
+
+        let name = partition
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("Invalid partition path"))?;
+        let mut dir_name = std::ffi::OsString::from("ventoy-");
+        dir_name.push(name);
+        let dir = crate::io::lock::lock_dir().join(dir_name);
+        fs::create_dir_all(&dir).context(format!("Failed to create {}", dir.display()))?;
+
+        let output = Command::new("mount")
+            .arg(partition)
+            .arg(&dir)
+            .output()
+            .context("Failed to run mount")?;
+        if !output.status.success() {
+            let _ = fs::remove_dir(&dir);
+            anyhow::bail!(
+                "Failed to mount {}: {}",
+                partition.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(Self {
+            dir,
+            mounted_here: true,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for DataMount {
+    fn drop(&mut self) {
+        if !self.mounted_here {
+            return;
+        }
+        match Command::new("umount").arg(&self.dir).status() {
+            Ok(status) if status.success() => {
+                let _ = fs::remove_dir(&self.dir);
+            }
+            Ok(status) => eprintln!(
+                "WARNING: umount {} exited with {status}",
+                self.dir.display()
+            ),
+            Err(e) => eprintln!("WARNING: Failed to run umount {}: {e}", self.dir.display()),
+        }
+    }
+}
+
+/// Where `partition` is mounted already, from `/proc/mounts`
+fn existing_mount(partition: &Path) -> Option<PathBuf> {
+    let mounts = fs::read_to_string("/proc/mounts").ok()?;
+    mounts.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let source = fields.next()?;
+        let target = fields.next()?;
+        (fs::canonicalize(source).ok()? == partition).then(|| unescape_mount_field(target))
+    })
+}
+
+/// Undo the octal escapes (`\040` for a space) used in `/proc/mounts`
+fn unescape_mount_field(field: &str) -> PathBuf {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 4)
+            .filter(|_| bytes[i] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        if let Some(byte) = escaped {
+            out.push(byte);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    PathBuf::from(std::ffi::OsStr::from_bytes(&out))
+}
+
+/// Free space on the filesystem holding `dir`
+fn available_bytes(dir: &Path) -> std::io::Result<u64> {
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is valid for writes
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: statvfs succeeded, so it filled in `stat`
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)] // c_ulong is 32 bits on some targets
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Copy `source` into the root of a mounted Ventoy partition
+///
+/// Refuses to replace an existing file of the same name. The copy is flushed
+/// before returning, along with the directory entry. Returns the new file and
+/// the SHA-256 of what was written, for `verify_copy`. Pausing and
+/// cancelling work as for `write_iso`; a copy that is cancelled or fails,
+/// including while flushing, is removed.
+pub fn copy_image(
+    source_iso: &Path,
+    mount: &DataMount,
+    cancel: &AtomicBool,
+    pause: &AtomicBool,
+    progress_callback: impl Fn(u64, u64, u64), // (bytes_copied, total_bytes, bytes_per_second)
+    status_callback: impl Fn(WriteStatus),
+) -> Result<(PathBuf, String)> {
+    let file_name = source_iso
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid image path"))?;
+    let destination = mount.path().join(file_name);
+    if destination.exists() {
+        anyhow::bail!(
+            "{} is already on the Ventoy stick. Delete or rename it there first.",
+            Path::new(file_name).display()
+        );
+    }
+
+    let mut source = File::open(source_iso).context(format!(
+        "Failed to open source ISO: {}",
+        source_iso.display()
+    ))?;
+    let total_size = source
+        .metadata()
+        .context("Failed to get source file size")?
+        .len();
+    let available = available_bytes(mount.path()).context(format!(
+        "Failed to get the free space on {}",
+        mount.path().display()
+    ))?;
+    if total_size > available {
+        return Err(EtchError::DeviceTooSmall {
+            device: mount.path().to_path_buf(),
+            device_bytes: available,
+            image_bytes: total_size,
+        }
+        .into());
+    }
+
+    let mut target = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&destination)
+        .context(format!("Failed to create {}", destination.display()))?;
+
+    let result = copy_contents(
+        &mut source,
+        &mut target,
+        total_size,
+        cancel,
+        pause,
+        progress_callback,
+        &status_callback,
+    )
+    .and_then(|digest| {
+        status_callback(WriteStatus::Syncing);
+        let sync_started = Instant::now();
+        target.sync_all().context("Failed to sync data to disk")?;
+        File::open(mount.path())
+            .and_then(|dir| dir.sync_all())
+            .context("Failed to sync the Ventoy directory")?;
+        status_callback(WriteStatus::Synced(sync_started.elapsed()));
+        Ok(digest)
+    });
+    let digest = match result {
+        Ok(digest) => digest,
+        Err(e) => {
+            // Ventoy would list a partial or unflushed image in its menu
+            drop(target);
+            let _ = fs::remove_file(&destination);
+            return Err(e);
+        }
+    };
+
+    Ok((destination, digest))
+}
+
+fn copy_contents(
+    source: &mut File,
+    target: &mut File,
+    total_size: u64,
+    cancel: &AtomicBool,
+    pause: &AtomicBool,
+    progress_callback: impl Fn(u64, u64, u64),
+    status_callback: &impl Fn(WriteStatus),
+) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut total_copied: u64 = 0;
+    let mut start_time = Instant::now();
+    let mut last_progress_time = start_time;
+
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(EtchError::Cancelled {
+                bytes_done: total_copied,
+            }
+            .into());
+        }
+
+        if pause.load(Ordering::Relaxed) {
+            target
+                .sync_data()
+                .context("Failed to flush the copy before pausing")?;
+            status_callback(WriteStatus::Paused(total_copied));
+
+            let paused_at = Instant::now();
+            while pause.load(Ordering::Relaxed) && !cancel.load(Ordering::Relaxed) {
+                thread::sleep(PAUSE_POLL_INTERVAL);
+            }
+            start_time += paused_at.elapsed();
+
+            if !cancel.load(Ordering::Relaxed) {
+                status_callback(WriteStatus::Resumed);
+            }
+            continue;
+        }
+
+        let bytes_read = source
+            .read(&mut buffer)
+            .context("Failed to read from source ISO")?;
+        if bytes_read == 0 {
+            break; // EOF
+        }
+
+        target
+            .write_all(&buffer[..bytes_read])
+            .context("Failed to write to the Ventoy stick")?;
+        hasher.update(&buffer[..bytes_read]);
+        total_copied += bytes_read as u64;
+
+        let now = Instant::now();
+        if now.duration_since(last_progress_time).as_millis() >= 100 || total_copied == total_size {
+            let elapsed = now.duration_since(start_time).as_secs_f64();
+            #[allow(
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss,
+                clippy::cast_precision_loss
+            )]
+            let bytes_per_second = if elapsed > 0.0 {
+                (total_copied as f64 / elapsed) as u64
+            } else {
+                0
+            };
+            progress_callback(total_copied, total_size, bytes_per_second);
+            last_progress_time = now;
+        }
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Re-read the copy from the stick and compare it with the digest of the source
+///
+/// The copy's cached pages are dropped first so the data comes from the
+/// device rather than from memory.
+pub fn verify_copy(
+    copy: &Path,
+    expected_sha256: &str,
+    cancel: &AtomicBool,
+    progress_callback: impl Fn(u64, u64, u64),
+) -> Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let file = File::open(copy).context(format!("Failed to open {}", copy.display()))?;
+    // SAFETY: the descriptor is owned by `file` and open for the call
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+    }
+    drop(file);
+
+    let actual = crate::core::verification::file_digest(
+        copy,
+        crate::core::verification::ChecksumAlgorithm::Sha256,
+        cancel,
+        progress_callback,
+    )?;
+    if actual != expected_sha256 {
+        anyhow::bail!(
+            "The copy of {} on the Ventoy stick does not match the image (SHA-256 {actual}, expected {expected_sha256}). The stick may be failing.",
+            copy.display()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTOR: u64 = crate::io::devices::SYSFS_SECTOR_SIZE;
+
+    fn put(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// A disk `sdx` whose partitions have these labels and sizes in bytes
+    fn fake_disk(partitions: &[(&str, u64)]) -> (tempfile::TempDir, tempfile::TempDir) {
+        let sys_block = tempfile::tempdir().unwrap();
+        let by_label = tempfile::tempdir().unwrap();
+        for (number, (label, bytes)) in (1..).zip(partitions) {
+            let partition = sys_block.path().join(format!("sdx/sdx{number}"));
+            put(&partition.join("partition"), &format!("{number}\n"));
+            put(&partition.join("size"), &format!("{}\n", bytes / SECTOR));
+            std::os::unix::fs::symlink(format!("../../sdx{number}"), by_label.path().join(label))
+                .unwrap();
+        }
+        (sys_block, by_label)
+    }
+
+    fn detect(partitions: &[(&str, u64)]) -> Option<PathBuf> {
+        let (sys_block, by_label) = fake_disk(partitions);
+        data_partition_in(sys_block.path(), by_label.path(), Path::new("/dev/sdx"))
+    }
+
+    #[test]
+    fn ventoy_layout_is_detected_by_label() {
+        assert_eq!(
+            detect(&[(DATA_LABEL, 30 << 30), (EFI_LABEL, EFI_PARTITION_BYTES)]),
+            Some(PathBuf::from("/dev/sdx1"))
+        );
+        assert_eq!(
+            detect(&[("Photos", 30 << 30), (EFI_LABEL, EFI_PARTITION_BYTES)]),
+            None
+        );
+        assert_eq!(detect(&[(DATA_LABEL, 30 << 30)]), None);
+    }
+
+    #[test]
+    fn boot_partition_must_be_32_mib() {
+        assert_eq!(
+            detect(&[(DATA_LABEL, 30 << 30), (EFI_LABEL, EFI_PARTITION_BYTES * 2)]),
+            None
+        );
+        assert_eq!(
+            detect(&[
+                (DATA_LABEL, 30 << 30),
+                (EFI_LABEL, EFI_PARTITION_BYTES - SECTOR)
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn mount_fields_are_unescaped() {
+        assert_eq!(
+            unescape_mount_field(r"/media/user/My\040Stick"),
+            PathBuf::from("/media/user/My Stick")
+        );
+        assert_eq!(
+            unescape_mount_field(r"/mnt/tab\011and\134slash"),
+            PathBuf::from("/mnt/tab\tand\\slash")
+        );
+        // Anything that is not three octal digits is kept as it is
+        assert_eq!(
+            unescape_mount_field(r"/mnt/a\9zz\04"),
+            PathBuf::from(r"/mnt/a\9zz\04")
+        );
+    }
+
+    #[test]
+    fn existing_file_is_not_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("distro.iso");
+        fs::write(&source, b"new image").unwrap();
+        let stick = dir.path().join("stick");
+        put(&stick.join("distro.iso"), "older image");
+        let mount = DataMount {
+            dir: stick.clone(),
+            mounted_here: false,
+        };
+
+        let error = copy_image(
+            &source,
+            &mount,
+            &AtomicBool::new(false),
+            &AtomicBool::new(false),
+            |_, _, _| {},
+            |_| {},
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("already on the Ventoy stick"));
+        assert_eq!(
+            fs::read_to_string(stick.join("distro.iso")).unwrap(),
+            "older image"
+        );
+    }
+}
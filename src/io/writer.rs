@@ -1,18 +1,71 @@
+use crate::core::error::EtchError;
+use crate::core::extents::next_extent;
 use anyhow::{Context, Result};
 use std::fs::File;
-use std::io::{Read, Write};
-use std::path::Path;
-use std::time::Instant;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
-const CHUNK_SIZE: usize = 1024 * 1024; // 1 MB chunks
+pub const CHUNK_SIZE: usize = 1024 * 1024; // 1 MB chunks
+
+/// Flush to the device every 64 MB so a reconnect can resume from a known-durable offset
+pub const SYNC_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// How much is zeroed at each end of the device by `clear_partition_tables`
+///
+/// Covers the primary GPT (first 34 sectors) and the backup GPT (last 33),
+/// with room for 4K-sector devices.
+pub const TABLE_CLEAR_BYTES: u64 = 1024 * 1024;
+
+/// How often a paused write checks whether it may continue
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Out-of-band status changes during a write
+#[derive(Debug, Clone)]
+pub enum WriteStatus {
+    /// Zeroing the old partition tables before the image is written
+    Preparing,
+    /// The device dropped off the bus; waiting for it to reappear
+    Reconnecting,
+    /// The device is back (possibly under a new node) and the write resumed
+    Reconnected(PathBuf),
+    /// Everything up to this offset is flushed and the write is idle
+    Paused(u64),
+    /// The write continues from the offset it paused at
+    Resumed,
+    /// All data is handed to the kernel; waiting for the device to flush it
+    Syncing,
+    /// The final flush finished after this long
+    Synced(Duration),
+}
 
 /// Write ISO image to block device
 /// Must report real progress via callback
-#[allow(dead_code)]
+///
+/// Setting `cancel` stops the write at the next chunk boundary, after flushing
+/// what has been written so far. While `pause` is set the write flushes and
+/// idles at a chunk boundary, leaving the USB bus free; paused time does not
+/// count towards the reported rate.
+///
+/// With `clear_tables` the start and end of the device are zeroed first, so a
+/// backup GPT left past the end of a smaller image cannot resurface.
+///
+/// Given `extents` (see `core::extents::used_extents`), only those ranges of
+/// the image are written and the device keeps its old contents elsewhere.
+/// Progress still counts positions in the image, skipped ranges included.
+#[allow(dead_code, clippy::too_many_arguments)]
 pub fn write_iso(
     source_iso: &Path,
     target_device: &Path,
+    clear_tables: bool,
+    extents: Option<&[Range<u64>]>,
+    cancel: &AtomicBool,
+    pause: &AtomicBool,
     progress_callback: impl Fn(u64, u64, u64), // (bytes_written, total_bytes, bytes_per_second)
+    status_callback: impl Fn(WriteStatus),
 ) -> Result<()> {
     // Open source ISO for reading
     let mut source = File::open(source_iso).context(format!(
@@ -34,27 +87,136 @@ pub fn write_iso(
             target_device.display()
         ))?;
 
+    // Refuse before touching any data rather than failing with ENOSPC at the end
+    let device_size = crate::io::devices::block_device_size(&mut target)
+        .context("Failed to get target device size")?;
+    if total_size > device_size {
+        return Err(EtchError::DeviceTooSmall {
+            device: target_device.to_path_buf(),
+            device_bytes: device_size,
+            image_bytes: total_size,
+        }
+        .into());
+    }
+
+    if clear_tables {
+        status_callback(WriteStatus::Preparing);
+        clear_partition_tables(&mut target, total_size, device_size)
+            .context("Failed to clear the old partition table")?;
+    }
+
+    // Remember who the device is so we can find it again if it drops out
+    let identity = crate::io::devices::device_identity(target_device);
+    let mut target_path = target_device.to_path_buf();
+
     let mut buffer = vec![0u8; CHUNK_SIZE];
     let mut total_written: u64 = 0;
-    let start_time = Instant::now();
+    let mut synced_offset: u64 = 0;
+    let mut start_time = Instant::now();
     let mut last_progress_time = start_time;
 
     loop {
+        if cancel.load(Ordering::Relaxed) {
+            // Best effort: leave the device in a consistent, flushed state
+            let _ = target.sync_all();
+            return Err(EtchError::Cancelled {
+                bytes_done: total_written,
+            }
+            .into());
+        }
+
+        if pause.load(Ordering::Relaxed) {
+            target
+                .sync_data()
+                .context("Failed to flush device before pausing")?;
+            synced_offset = total_written;
+            status_callback(WriteStatus::Paused(total_written));
+
+            let paused_at = Instant::now();
+            while pause.load(Ordering::Relaxed) && !cancel.load(Ordering::Relaxed) {
+                thread::sleep(PAUSE_POLL_INTERVAL);
+            }
+            start_time += paused_at.elapsed();
+
+            if !cancel.load(Ordering::Relaxed) {
+                status_callback(WriteStatus::Resumed);
+            }
+            continue;
+        }
+
+        // Jump over unused space, and stop a chunk short at the end of an extent
+        let mut want = CHUNK_SIZE;
+        if let Some(extents) = extents {
+            let Some(extent) = next_extent(extents, total_written) else {
+                // The rest of the image is unused
+                total_written = total_size;
+                break;
+            };
+            if extent.start > total_written {
+                source
+                    .seek(SeekFrom::Start(extent.start))
+                    .context("Failed to seek source ISO")?;
+                target
+                    .seek(SeekFrom::Start(extent.start))
+                    .context("Failed to seek target device")?;
+                total_written = extent.start;
+            }
+            let remaining = extent.end - extent.start;
+            want = usize::try_from(remaining).map_or(CHUNK_SIZE, |r| r.min(CHUNK_SIZE));
+        }
+
         // Read chunk from source
         let bytes_read = source
-            .read(&mut buffer)
+            .read(&mut buffer[..want])
             .context("Failed to read from source ISO")?;
 
         if bytes_read == 0 {
             break; // EOF
         }
 
-        // Write chunk to target
-        target
-            .write_all(&buffer[..bytes_read])
-            .context("Failed to write to target device")?;
+        // Write chunk to target, flushing periodically to establish a resume point
+        let needs_sync = total_written + bytes_read as u64 - synced_offset >= SYNC_INTERVAL;
+        let write_result = target.write_all(&buffer[..bytes_read]).and_then(|()| {
+            if needs_sync {
+                target.sync_data()
+            } else {
+                Ok(())
+            }
+        });
+
+        match write_result {
+            Ok(()) => {}
+            Err(e) if crate::io::devices::is_device_gone(&e) => {
+                status_callback(WriteStatus::Reconnecting);
+
+                let (reopened, path) = crate::io::devices::wait_for_reconnect(
+                    identity.as_deref(),
+                    &target_path,
+                    cancel,
+                    |path| File::options().write(true).open(path),
+                )?;
+                target = reopened;
+                target_path = path;
+
+                // Anything after the last flush may not have reached the media
+                target
+                    .seek(SeekFrom::Start(synced_offset))
+                    .context("Failed to seek reconnected device")?;
+                source
+                    .seek(SeekFrom::Start(synced_offset))
+                    .context("Failed to seek source ISO")?;
+                total_written = synced_offset;
+
+                status_callback(WriteStatus::Reconnected(target_path.clone()));
+                continue;
+            }
+            Err(e) => return Err(e).context("Failed to write to target device"),
+        }
 
         total_written += bytes_read as u64;
+        if needs_sync {
+            synced_offset = total_written;
+        }
 
         // Report progress (throttle to avoid overwhelming UI)
         let now = Instant::now();
@@ -76,9 +238,6 @@ pub fn write_iso(
         }
     }
 
-    // Sync to ensure all data is written to disk
-    target.sync_all().context("Failed to sync data to disk")?;
-
     // Ensure final progress update is sent
     if total_written > 0 {
         let elapsed = Instant::now().duration_since(start_time).as_secs_f64();
@@ -95,5 +254,37 @@ pub fn write_iso(
         progress_callback(total_written, total_size, bytes_per_second);
     }
 
+    // Sticks with large write caches can take many seconds here; the data is
+    // not safe until this returns
+    status_callback(WriteStatus::Syncing);
+    let sync_started = Instant::now();
+    target.sync_all().context("Failed to sync data to disk")?;
+    status_callback(WriteStatus::Synced(sync_started.elapsed()));
+
+    Ok(())
+}
+
+/// Zero the first and last `TABLE_CLEAR_BYTES` of a device
+///
+/// The tail is only zeroed beyond `image_bytes`; an image as large as the
+/// device leaves nothing to clear there. Leaves the position at the start.
+fn clear_partition_tables(target: &mut File, image_bytes: u64, device_bytes: u64) -> Result<()> {
+    let zeros = vec![0u8; CHUNK_SIZE];
+    let head_end = TABLE_CLEAR_BYTES.min(device_bytes);
+    let tail_start = device_bytes
+        .saturating_sub(TABLE_CLEAR_BYTES)
+        .max(image_bytes);
+
+    for (start, end) in [(0, head_end), (tail_start, device_bytes)] {
+        target.seek(SeekFrom::Start(start))?;
+        let mut offset = start;
+        while offset < end {
+            let len = usize::try_from(end - offset).map_or(CHUNK_SIZE, |len| len.min(CHUNK_SIZE));
+            target.write_all(&zeros[..len])?;
+            offset += len as u64;
+        }
+    }
+
+    target.seek(SeekFrom::Start(0))?;
     Ok(())
 }
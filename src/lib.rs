@@ -0,0 +1,8 @@
+//! Image inspection, safety checks and device I/O behind the Etch window
+//!
+//! The GTK interface lives in the binary (`src/ui`); the rest is a library so
+//! the integration tests under `tests/` can drive the writer directly.
+
+pub mod core;
+pub mod i18n;
+pub mod io;
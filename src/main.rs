@@ -1,7 +1,6 @@
-mod core;
-mod io;
 mod ui;
 
+use etch::{core, i18n, io};
 use gtk4::prelude::*;
 use gtk4::Application;
 
@@ -19,10 +18,47 @@ fn main() -> anyhow::Result<()> {
     // This is NOT a workaround - it's the correct architecture for a stateless utility.
     std::env::set_var("GSETTINGS_BACKEND", "memory");
 
+    i18n::init();
+
+    // Print the environment report without starting the GUI
+    if std::env::args().skip(1).any(|arg| arg == "--doctor") {
+        let checks = core::diagnostics::run_checks();
+        print!("{}", core::diagnostics::format_report(&checks));
+        std::process::exit(i32::from(core::diagnostics::has_failures(&checks)));
+    }
+
     // Root check removed from startup - will be checked when write operation starts
-    let app = Application::builder().application_id(APP_ID).build();
+    let app = Application::builder()
+        .application_id(APP_ID)
+        .flags(gtk4::gio::ApplicationFlags::HANDLES_OPEN)
+        .build();
+
+    // Single instance: launching again activates the running primary instance,
+    // which raises its window (even one hidden during a background write)
+    // instead of opening a second one
+    app.connect_activate(|app| match app.windows().into_iter().next() {
+        Some(window) => window.present(),
+        None => ui::build_ui(app),
+    });
 
-    app.connect_activate(ui::build_ui);
+    // "Open with Etch" and `etch image.iso` land here instead of in activate;
+    // only one image can be selected, so extra files are ignored. Any file is
+    // passed on whatever its name or type: ISOs and raw `.img` images alike
+    // are recognised by their contents when selected
+    app.connect_open(|app, files, _hint| {
+        if app.windows().is_empty() {
+            ui::build_ui(app);
+        }
+        let (Some(window), Some(path)) = (
+            app.windows().into_iter().next(),
+            files.first().and_then(gtk4::gio::prelude::FileExt::path),
+        ) else {
+            return;
+        };
+        window.present();
+        WidgetExt::activate_action(&window, "win.open-iso", Some(&path.to_variant()))
+            .unwrap_or_else(|e| eprintln!("WARNING: Could not open {}: {e}", path.display()));
+    });
 
     let exit_code = app.run();
 
@@ -1,4 +1,8 @@
 /// GTK4 user interface
+mod progress_bus;
+mod sparkline;
+mod state;
 mod window;
+mod worker;
 
 pub use window::build_ui;
@@ -0,0 +1,253 @@
+//! Progress of the running operation on the session bus
+//!
+//! Lets scripts and dashboards follow a write without scraping stderr. The
+//! object lives at `/org/etch/Etch/Progress` under the well-known name
+//! `org.etch.Etch1`; if another process holds that name, it is still reachable
+//! through this connection's unique name. `examples/monitor-progress.sh`
+//! shows how to watch it.
+
+use gtk4::gio;
+use gtk4::glib;
+use gtk4::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+const BUS_NAME: &str = "org.etch.Etch1";
+const OBJECT_PATH: &str = "/org/etch/Etch/Progress";
+const INTERFACE: &str = "org.etch.Etch1.Progress";
+
+const INTROSPECTION: &str = r#"<node>
+  <interface name="org.etch.Etch1.Progress">
+    <property name="Phase" type="s" access="read"/>
+    <property name="Device" type="s" access="read"/>
+    <property name="BytesProcessed" type="t" access="read"/>
+    <property name="TotalBytes" type="t" access="read"/>
+    <property name="BytesPerSecond" type="t" access="read"/>
+    <signal name="Finished">
+      <arg name="outcome" type="s"/>
+    </signal>
+  </interface>
+</node>"#;
+
+/// What the bus object reports
+///
+/// `phase` is one of `idle`, `checking`, `writing`, `verifying` or `reading`.
+#[derive(Debug, Clone)]
+pub struct ProgressSnapshot {
+    pub phase: &'static str,
+    pub device: String,
+    pub bytes_processed: u64,
+    pub total_bytes: u64,
+    pub bytes_per_second: u64,
+}
+
+impl ProgressSnapshot {
+    const fn idle() -> Self {
+        Self {
+            phase: "idle",
+            device: String::new(),
+            bytes_processed: 0,
+            total_bytes: 0,
+            bytes_per_second: 0,
+        }
+    }
+
+    /// D-Bus property names and values, in introspection order
+    pub fn properties(&self) -> [(&'static str, PropertyValue<'_>); 5] {
+        [
+            ("Phase", PropertyValue::Text(self.phase)),
+            ("Device", PropertyValue::Text(&self.device)),
+            ("BytesProcessed", PropertyValue::Count(self.bytes_processed)),
+            ("TotalBytes", PropertyValue::Count(self.total_bytes)),
+            (
+                "BytesPerSecond",
+                PropertyValue::Count(self.bytes_per_second),
+            ),
+        ]
+    }
+}
+
+/// A property value, converted to a `glib::Variant` only when sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyValue<'a> {
+    /// D-Bus type `s`
+    Text(&'a str),
+    /// D-Bus type `t`
+    Count(u64),
+}
+
+impl PropertyValue<'_> {
+    fn to_variant(self) -> glib::Variant {
+        match self {
+            Self::Text(text) => text.to_variant(),
+            Self::Count(count) => count.to_variant(),
+        }
+    }
+}
+
+/// Handle to the exported progress object; clones share it
+#[derive(Clone)]
+pub struct ProgressBus {
+    connection: gio::DBusConnection,
+    snapshot: Rc<RefCell<ProgressSnapshot>>,
+}
+
+impl ProgressBus {
+    /// Export the progress object on `connection` and ask for `BUS_NAME`
+    pub fn export(connection: &gio::DBusConnection) -> Result<Self, glib::Error> {
+        let node = gio::DBusNodeInfo::for_xml(INTROSPECTION)?;
+        let interface = node.lookup_interface(INTERFACE).ok_or_else(|| {
+            glib::Error::new(gio::IOErrorEnum::Failed, "Progress interface missing")
+        })?;
+
+        let snapshot = Rc::new(RefCell::new(ProgressSnapshot::idle()));
+        let reader = snapshot.clone();
+        connection
+            .register_object(OBJECT_PATH, &interface)
+            .property(move |_, _, _, _, name| {
+                reader
+                    .borrow()
+                    .properties()
+                    .into_iter()
+                    .find(|(property, _)| *property == name)
+                    .map_or_else(|| "".to_variant(), |(_, value)| value.to_variant())
+            })
+            .build()?;
+
+        // Without queueing, a second instance keeps only its unique name
+        gio::bus_own_name_on_connection(
+            connection,
+            BUS_NAME,
+            gio::BusNameOwnerFlags::DO_NOT_QUEUE,
+            |_, _| {},
+            |connection, name| {
+                eprintln!(
+                    "INFO: {name} is owned by another process; progress is published on {}",
+                    connection
+                        .unique_name()
+                        .as_deref()
+                        .unwrap_or("this connection")
+                );
+            },
+        );
+
+        Ok(Self {
+            connection: connection.clone(),
+            snapshot,
+        })
+    }
+
+    /// Start reporting an operation on `device`
+    pub fn start(&self, device: &Path) {
+        *self.snapshot.borrow_mut() = ProgressSnapshot {
+            device: device.to_string_lossy().into_owned(),
+            ..ProgressSnapshot::idle()
+        };
+        self.emit_changed();
+    }
+
+    pub fn update(&self, phase: &'static str, bytes: u64, total: u64, bps: u64) {
+        let mut snapshot = self.snapshot.borrow_mut();
+        snapshot.phase = phase;
+        snapshot.bytes_processed = bytes;
+        snapshot.total_bytes = total;
+        snapshot.bytes_per_second = bps;
+        drop(snapshot);
+        self.emit_changed();
+    }
+
+    /// Emit `Finished` with `succeeded` or `failed` and go back to idle
+    pub fn finish(&self, succeeded: bool) {
+        let outcome = if succeeded { "succeeded" } else { "failed" };
+        self.snapshot.borrow_mut().phase = "idle";
+        self.emit_changed();
+        self.emit(INTERFACE, "Finished", &(outcome,).to_variant());
+    }
+
+    fn emit_changed(&self) {
+        let changed: HashMap<String, glib::Variant> = self
+            .snapshot
+            .borrow()
+            .properties()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_variant()))
+            .collect();
+        self.emit(
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            &(INTERFACE, changed, Vec::<String>::new()).to_variant(),
+        );
+    }
+
+    fn emit(&self, interface: &str, signal: &str, parameters: &glib::Variant) {
+        // Monitoring is best effort; a closed bus must not disturb the write
+        let _ = self
+            .connection
+            .emit_signal(None, OBJECT_PATH, interface, signal, Some(parameters));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_snapshot_reports_nothing_in_progress() {
+        let snapshot = ProgressSnapshot::idle();
+        assert_eq!(
+            snapshot.properties(),
+            [
+                ("Phase", PropertyValue::Text("idle")),
+                ("Device", PropertyValue::Text("")),
+                ("BytesProcessed", PropertyValue::Count(0)),
+                ("TotalBytes", PropertyValue::Count(0)),
+                ("BytesPerSecond", PropertyValue::Count(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn running_write_reports_its_progress() {
+        let snapshot = ProgressSnapshot {
+            phase: "writing",
+            device: "/dev/sdb".to_string(),
+            bytes_processed: 1_200_000_000,
+            total_bytes: 2_800_000_000,
+            bytes_per_second: 31_500_000,
+        };
+        assert_eq!(
+            snapshot.properties(),
+            [
+                ("Phase", PropertyValue::Text("writing")),
+                ("Device", PropertyValue::Text("/dev/sdb")),
+                ("BytesProcessed", PropertyValue::Count(1_200_000_000)),
+                ("TotalBytes", PropertyValue::Count(2_800_000_000)),
+                ("BytesPerSecond", PropertyValue::Count(31_500_000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn properties_follow_the_introspection_data() {
+        let declared: Vec<(&str, &str)> = INTROSPECTION
+            .lines()
+            .filter_map(|line| {
+                let rest = line.trim().strip_prefix("<property name=\"")?;
+                let (name, rest) = rest.split_once('"')?;
+                let kind = rest.strip_prefix(" type=\"")?.split('"').next()?;
+                Some((name, kind))
+            })
+            .collect();
+        let reported: Vec<(&str, &str)> = ProgressSnapshot::idle()
+            .properties()
+            .iter()
+            .map(|(name, value)| match value {
+                PropertyValue::Text(_) => (*name, "s"),
+                PropertyValue::Count(_) => (*name, "t"),
+            })
+            .collect();
+        assert_eq!(declared, reported);
+    }
+}
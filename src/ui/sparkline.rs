@@ -0,0 +1,123 @@
+use gtk4::prelude::*;
+use gtk4::DrawingArea;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Samples kept on screen; at `SAMPLE_INTERVAL` this is the last minute
+const MAX_SAMPLES: usize = 120;
+
+/// Minimum time between samples so bursty progress callbacks don't dominate
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Throughput graph for the progress area
+///
+/// The workers report an average rate since the start, which smooths stalls
+/// away; the graph instead derives the instantaneous rate from successive
+/// byte counts. Cheap to clone, all clones draw the same data.
+#[derive(Clone)]
+pub struct Sparkline {
+    area: DrawingArea,
+    samples: Rc<RefCell<VecDeque<f64>>>,
+    last_sample: Rc<Cell<Option<(Instant, u64)>>>,
+}
+
+impl Sparkline {
+    pub fn new() -> Self {
+        let area = DrawingArea::new();
+        area.add_css_class("sparkline");
+        area.set_content_height(28);
+        area.set_hexpand(true);
+
+        let samples: Rc<RefCell<VecDeque<f64>>> =
+            Rc::new(RefCell::new(VecDeque::with_capacity(MAX_SAMPLES)));
+
+        let draw_samples = samples.clone();
+        area.set_draw_func(move |_, cr, width, height| {
+            let samples = draw_samples.borrow();
+            if samples.len() < 2 {
+                return;
+            }
+
+            let peak = samples.iter().copied().fold(0.0_f64, f64::max);
+            if peak <= 0.0 {
+                return;
+            }
+
+            let width = f64::from(width);
+            let height = f64::from(height);
+            #[allow(clippy::cast_precision_loss)] // At most MAX_SAMPLES points
+            let step = width / (MAX_SAMPLES - 1) as f64;
+            #[allow(clippy::cast_precision_loss)]
+            let x_offset = width - step * (samples.len() - 1) as f64;
+
+            let points = samples.iter().enumerate().map(|(index, rate)| {
+                #[allow(clippy::cast_precision_loss)]
+                let x = x_offset + step * index as f64;
+                // Leave a pixel of headroom so the line isn't clipped at the peak
+                let y = height - 1.0 - (rate / peak) * (height - 2.0);
+                (x, y)
+            });
+
+            cr.set_line_width(1.0);
+            cr.set_source_rgba(0.957, 0.957, 0.957, 0.8);
+            for (index, (x, y)) in points.enumerate() {
+                if index == 0 {
+                    cr.move_to(x, y);
+                } else {
+                    cr.line_to(x, y);
+                }
+            }
+            if let Err(e) = cr.stroke() {
+                eprintln!("WARNING: Failed to draw throughput graph: {e}");
+            }
+        });
+
+        Self {
+            area,
+            samples,
+            last_sample: Rc::new(Cell::new(None)),
+        }
+    }
+
+    pub fn widget(&self) -> &DrawingArea {
+        &self.area
+    }
+
+    /// Feed the running byte count of the current phase
+    pub fn record(&self, bytes: u64) {
+        let now = Instant::now();
+
+        let Some((last_time, last_bytes)) = self.last_sample.get() else {
+            self.last_sample.set(Some((now, bytes)));
+            return;
+        };
+
+        let elapsed = now.duration_since(last_time);
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+        self.last_sample.set(Some((now, bytes)));
+
+        // Byte counts go backwards when a reconnect resumes from the last flush
+        #[allow(clippy::cast_precision_loss)] // Acceptable for a graph
+        let rate = bytes.saturating_sub(last_bytes) as f64 / elapsed.as_secs_f64();
+
+        let mut samples = self.samples.borrow_mut();
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(rate);
+        drop(samples);
+
+        self.area.queue_draw();
+    }
+
+    /// Start a fresh graph, e.g. when the operation moves to its next phase
+    pub fn clear(&self) {
+        self.samples.borrow_mut().clear();
+        self.last_sample.set(None);
+        self.area.queue_draw();
+    }
+}
@@ -0,0 +1,1316 @@
+//! UI state and presentation logic that doesn't touch GTK widgets
+//!
+//! `window.rs` wires widgets to these types; keeping them here keeps the
+//! formatting and state rules readable apart from the closures.
+
+use crate::core::error::EtchError;
+use crate::core::verification::VerifyMode;
+use crate::i18n::{gettext, i18n_f};
+use crate::io::span::SpanPhase;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Selections and per-operation handles shared by the window's callbacks
+#[derive(Clone)]
+pub struct AppState {
+    pub selected_iso: Option<PathBuf>,
+    pub selected_device: Option<crate::core::models::BlockDevice>,
+    pub is_working: bool,
+    /// Pause flag of the running write, toggled by the Pause button
+    pub pause_requested: Arc<AtomicBool>,
+    /// Cancel flag of the running operation
+    pub cancel_requested: Arc<AtomicBool>,
+    /// The window was closed mid-operation; quit once the worker finishes
+    pub quit_when_idle: bool,
+    /// Diagnostic log of the last operation, offered when it fails
+    pub operation_log: Option<PathBuf>,
+    /// Device node of the last successful write, for the test boot
+    pub last_target: Option<PathBuf>,
+    /// `qemu-system-x86_64`, detected once at startup
+    pub qemu: Option<PathBuf>,
+}
+
+impl AppState {
+    pub fn new() -> Self {
+        Self {
+            selected_iso: None,
+            selected_device: None,
+            is_working: false,
+            pause_requested: Arc::new(AtomicBool::new(false)),
+            cancel_requested: Arc::new(AtomicBool::new(false)),
+            quit_when_idle: false,
+            operation_log: None,
+            last_target: None,
+            qemu: crate::io::qemu::find_qemu(),
+        }
+    }
+
+    /// Whether the Write button may be pressed
+    pub fn can_write(&self) -> bool {
+        self.selected_iso.is_some() && self.selected_device.is_some() && !self.is_working
+    }
+}
+
+/// What the device dropdown should show once the device list is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceSelection {
+    /// Leave the dropdown alone
+    Keep,
+    /// Show the chosen device, which sits at this position now
+    Select(u32),
+    /// Select the only device for the user
+    AutoSelect,
+    /// Show no selection, so the user has to choose
+    Clear,
+}
+
+/// Decide the dropdown selection for `devices`
+///
+/// The device already chosen stays chosen; if it is gone, the choice is
+/// cleared rather than moved to another device. Nothing changes while an
+/// operation runs.
+pub fn device_selection(
+    selected: Option<&Path>,
+    is_working: bool,
+    devices: &[crate::core::models::BlockDevice],
+    auto_select: bool,
+) -> DeviceSelection {
+    if is_working {
+        return DeviceSelection::Keep;
+    }
+    if let Some(selected) = selected {
+        return devices
+            .iter()
+            .position(|device| device.path == selected)
+            .and_then(|position| u32::try_from(position).ok())
+            .map_or(DeviceSelection::Clear, DeviceSelection::Select);
+    }
+    match devices.len() {
+        0 => DeviceSelection::Keep,
+        1 if auto_select => DeviceSelection::AutoSelect,
+        _ => DeviceSelection::Clear,
+    }
+}
+
+/// Events sent from the worker thread to the UI
+#[derive(Debug, Clone)]
+pub enum WorkMessage {
+    SourceCheckProgress(u64, u64, u64), // bytes, total, bps
+    WriteProgress(u64, u64, u64),       // bytes, total, bps
+    VerifyProgress(u64, u64, u64),      // bytes, total, bps
+    Preparing,                          // zeroing the old partition tables
+    Reconnecting,
+    Reconnected(PathBuf),
+    Paused(u64), // offset flushed to the device
+    Resumed,
+    Syncing,            // final flush to the device started
+    Synced(Duration),   // how long the final flush took
+    BiosBootable(bool), // boot signature found on the written device
+    WriteComplete,
+    RunningHook,
+    HookFailed(String), // post-write hook failed; the write itself succeeded
+    VerifyComplete,
+    VerifyDiff(String), // hex comparison around the first mismatch (ETCH_VERBOSE)
+    Cancelled(u64),     // bytes written and flushed before the write stopped
+    Error(String),
+}
+
+/// Events sent from the spanned write and restore workers to the UI
+#[derive(Debug, Clone)]
+pub enum SpanMessage {
+    Progress {
+        /// Counting from 0
+        part: u32,
+        phase: SpanPhase,
+        bytes: u64,
+        part_bytes: u64,
+        bps: u64,
+    },
+    /// The worker waits for the device holding (or to hold) part `index`
+    NeedDevice {
+        index: u32,
+        total: u32,
+        min_bytes: u64,
+        /// Why the device given for this part was refused
+        rejected: Option<String>,
+    },
+    Complete(String),
+    Error(String),
+}
+
+/// Events sent from the image creation worker to the UI
+#[derive(Debug, Clone)]
+pub enum ImageMessage {
+    Progress(u64, u64, u64), // bytes, total, bps
+    Complete(crate::io::reader::ImageResult),
+    Error(String),
+}
+
+/// Events sent from the file checksum worker to the UI
+#[derive(Debug, Clone)]
+pub enum ChecksumMessage {
+    Progress(u64, u64, u64), // bytes, total, bps
+    Complete(String),        // lowercase hex digest
+    Error(String),
+}
+
+/// What the window title summarises, so progress is visible from the taskbar
+pub enum TitleState<'a> {
+    Idle,
+    Working {
+        phase: &'a str,
+        progress: &'a crate::core::models::Progress,
+    },
+    Done,
+    Error,
+}
+
+/// Stall watchdog state for a running operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchdog {
+    Watching,
+    Prompting,
+    AbortRequested,
+}
+
+/// How often the UI drains worker messages and checks for stalls
+pub const MESSAGE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Pausing longer than this risks the device being autosuspended
+pub const LONG_PAUSE_WARNING: Duration = Duration::from_secs(10 * 60);
+
+/// No-progress interval after which the user is offered to abort
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Minimum interval between title changes, to avoid window-manager churn
+pub const TITLE_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where a running write stands, as far as the UI is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Started, no word from the worker yet
+    Idle,
+    Checking,
+    Preparing,
+    Writing,
+    Paused,
+    Syncing,
+    Verifying,
+    /// Running the post-write hook; the device is already written and checked
+    Hook,
+    /// Joining a spanned part back into the image
+    Reading,
+    /// Between the parts of a spanned operation, until the user chooses the next device
+    WaitingForDevice,
+    /// Cancelled after a stall; the controls stay locked until the worker exits
+    Aborting,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+/// View model of one write, advanced by [`reduce`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteSession {
+    pub phase: Phase,
+    /// Device node being written; changes when the device reconnects
+    pub target: PathBuf,
+    /// Boot signature found after the write, if it was checked
+    pub bios_bootable: Option<bool>,
+    /// The first write progress arrived and the Pause button is showing
+    pub write_started: bool,
+    /// Failure shown once an aborted worker has exited
+    pub abort_reason: Option<String>,
+    /// Set for a spanned write or restore
+    pub span: Option<SpanSession>,
+}
+
+/// The spanned side of a [`WriteSession`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanSession {
+    /// Splitting an image across devices, rather than joining one back
+    pub writing: bool,
+    /// Parts in the set, as far as known
+    pub parts: u32,
+    /// Part and phase of the last progress; each counts from zero again
+    pub current: Option<(u32, SpanPhase)>,
+}
+
+impl WriteSession {
+    pub fn new(target: PathBuf) -> Self {
+        Self {
+            phase: Phase::Idle,
+            target,
+            bios_bootable: None,
+            write_started: false,
+            abort_reason: None,
+            span: None,
+        }
+    }
+
+    /// A spanned write (or restore, unless `writing`) whose first part is on `target`
+    pub fn spanned(target: PathBuf, writing: bool, parts: u32) -> Self {
+        Self {
+            span: Some(SpanSession {
+                writing,
+                parts,
+                current: None,
+            }),
+            ..Self::new(target)
+        }
+    }
+
+    /// Whether the worker is quiet on purpose, so the stall watchdog must not fire
+    pub fn expects_silence(&self) -> bool {
+        matches!(
+            self.phase,
+            Phase::Paused
+                | Phase::Syncing
+                | Phase::Hook
+                | Phase::WaitingForDevice
+                | Phase::Aborting
+        )
+    }
+
+    /// Whether the outcome has been shown and the message loop can stop
+    pub fn is_finished(&self) -> bool {
+        matches!(self.phase, Phase::Done | Phase::Failed | Phase::Cancelled)
+    }
+}
+
+/// Something that happened to a running write
+#[derive(Debug, Clone)]
+pub enum WriteEvent {
+    Worker(WorkMessage),
+    /// From the worker of a spanned write or restore
+    Span(SpanMessage),
+    /// The user chose to abort after no progress for `timeout`
+    StallAbort {
+        timeout: Duration,
+    },
+    /// The worker's channel closed
+    WorkerGone,
+}
+
+/// State of the Pause button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseButton {
+    Pause,
+    Resume,
+    Disabled,
+    Hidden,
+}
+
+/// Widget change requested by [`reduce`], applied by `window.rs`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Show progress below the bar and in the title, and record throughput
+    Progress {
+        label: String,
+        title: String,
+        bus_phase: &'static str,
+        bytes: u64,
+        total: u64,
+        bps: u64,
+    },
+    ClearThroughput,
+    /// Text below the progress bar
+    Status(String),
+    /// Secondary text next to the status
+    Detail(String),
+    Pause(PauseButton),
+    ResetProgressBar,
+    HookWarning(String),
+    VerifyDiff(String),
+    /// Ask the worker to stop
+    Cancel,
+    /// Show success, with a note on BIOS bootability
+    Succeeded {
+        detail: String,
+    },
+    /// Ask the user for the device holding (or to hold) the next spanned part
+    RequestDevice {
+        title: String,
+        text: String,
+        min_bytes: u64,
+    },
+    /// Show the summary of a finished spanned write or restore
+    SpanSucceeded(String),
+    Failed(String),
+}
+
+/// Advance a write session by one event
+///
+/// Pure, so the transitions can be tested without GTK; the returned commands
+/// are applied to the widgets in order.
+pub fn reduce(session: WriteSession, event: WriteEvent) -> (WriteSession, Vec<Command>) {
+    let mut session = session;
+    if session.is_finished() {
+        return (session, Vec::new());
+    }
+
+    let message = match event {
+        // Whatever the worker still reports, the abort is the outcome
+        WriteEvent::Worker(_) | WriteEvent::StallAbort { .. }
+            if session.phase == Phase::Aborting =>
+        {
+            return (session, Vec::new());
+        }
+        // The write already succeeded; a slow hook is no reason to abort it
+        WriteEvent::StallAbort { .. } if session.phase == Phase::Hook => {
+            return (session, Vec::new());
+        }
+        WriteEvent::Worker(message) => message,
+        WriteEvent::Span(message) => return reduce_span(session, message),
+        WriteEvent::StallAbort { timeout } => {
+            session.phase = Phase::Aborting;
+            session.abort_reason = Some(i18n_f(
+                "Aborted after no progress for {}s. Unplug and re-insert the device before retrying.",
+                &[&timeout.as_secs().to_string()],
+            ));
+            let commands = vec![
+                Command::Cancel,
+                Command::Pause(PauseButton::Disabled),
+                Command::Status(gettext("Aborting…")),
+                Command::Detail(gettext("Waiting for the device to stop")),
+            ];
+            return (session, commands);
+        }
+        WriteEvent::WorkerGone if session.phase == Phase::Aborting => {
+            session.phase = Phase::Failed;
+            let message = session.abort_reason.take().unwrap_or_default();
+            return (session, vec![Command::Failed(message)]);
+        }
+        WriteEvent::WorkerGone => {
+            session.phase = Phase::Failed;
+            let message = if session.span.is_some() {
+                gettext("The spanned operation ended unexpectedly")
+            } else {
+                gettext("The write stopped without reporting a result.")
+            };
+            return (session, vec![Command::Failed(message)]);
+        }
+    };
+
+    let progress = |label: &str, title: &str, bus_phase, bytes, total, bps| Command::Progress {
+        label: gettext(label),
+        title: gettext(title),
+        bus_phase,
+        bytes,
+        total,
+        bps,
+    };
+
+    let commands = match message {
+        WorkMessage::SourceCheckProgress(bytes, total, bps) => {
+            session.phase = Phase::Checking;
+            vec![progress(
+                "Checking source",
+                "Checking",
+                "checking",
+                bytes,
+                total,
+                bps,
+            )]
+        }
+        WorkMessage::WriteProgress(bytes, total, bps) => {
+            session.phase = Phase::Writing;
+            let mut commands = Vec::new();
+            if !session.write_started {
+                session.write_started = true;
+                commands.push(Command::ClearThroughput);
+                commands.push(Command::Pause(PauseButton::Pause));
+            }
+            commands.push(progress(
+                "Writing...",
+                "Writing",
+                "writing",
+                bytes,
+                total,
+                bps,
+            ));
+            commands
+        }
+        WorkMessage::Reconnecting => vec![
+            Command::Status(gettext("Device disconnected · waiting for it to return")),
+            Command::Detail(String::new()),
+        ],
+        WorkMessage::Reconnected(path) => {
+            let status = i18n_f(
+                "Reconnected as {} · resuming",
+                &[&path.display().to_string()],
+            );
+            session.target = path;
+            vec![Command::Status(status)]
+        }
+        WorkMessage::Paused(offset) => {
+            session.phase = Phase::Paused;
+            #[allow(clippy::cast_precision_loss)] // Acceptable for UI display
+            let mb_done = offset as f64 / 1_000_000.0;
+            vec![
+                Command::Status(i18n_f(
+                    "Paused at {} MB · device flushed",
+                    &[&format!("{mb_done:.0}")],
+                )),
+                Command::Detail(String::new()),
+                Command::Pause(PauseButton::Resume),
+            ]
+        }
+        WorkMessage::Resumed => {
+            session.phase = Phase::Writing;
+            vec![
+                Command::Status(gettext("Writing...")),
+                Command::Pause(PauseButton::Pause),
+            ]
+        }
+        WorkMessage::Preparing => {
+            session.phase = Phase::Preparing;
+            vec![Command::Status(gettext("Clearing old partition table…"))]
+        }
+        WorkMessage::Syncing => {
+            session.phase = Phase::Syncing;
+            vec![
+                Command::Pause(PauseButton::Disabled),
+                Command::Status(gettext("Flushing to device…")),
+                Command::Detail(gettext("Do not remove the device yet")),
+            ]
+        }
+        WorkMessage::Synced(duration) => {
+            session.phase = Phase::Writing;
+            vec![Command::Detail(i18n_f(
+                "Flushed in {} s",
+                &[&format!("{:.1}", duration.as_secs_f64())],
+            ))]
+        }
+        WorkMessage::BiosBootable(bootable) => {
+            session.bios_bootable = Some(bootable);
+            Vec::new()
+        }
+        WorkMessage::WriteComplete => {
+            session.phase = Phase::Verifying;
+            vec![
+                Command::Pause(PauseButton::Hidden),
+                Command::Status(gettext("Verifying")),
+                Command::ResetProgressBar,
+                Command::ClearThroughput,
+            ]
+        }
+        WorkMessage::VerifyProgress(bytes, total, bps) => {
+            session.phase = Phase::Verifying;
+            vec![progress(
+                "Verifying",
+                "Verifying",
+                "verifying",
+                bytes,
+                total,
+                bps,
+            )]
+        }
+        WorkMessage::VerifyComplete => {
+            session.phase = Phase::Done;
+            let detail = match session.bios_bootable {
+                Some(true) => gettext("BIOS bootable from USB"),
+                Some(false) => gettext("Not BIOS bootable (no MBR boot signature)"),
+                None => String::new(),
+            };
+            vec![Command::Succeeded { detail }]
+        }
+        WorkMessage::RunningHook => {
+            session.phase = Phase::Hook;
+            vec![
+                Command::Status(gettext("Running post-write hook")),
+                Command::Detail(String::new()),
+            ]
+        }
+        WorkMessage::HookFailed(error) => vec![Command::HookWarning(error)],
+        WorkMessage::VerifyDiff(diff) => vec![Command::VerifyDiff(diff)],
+        WorkMessage::Cancelled(bytes_done) => {
+            session.phase = Phase::Cancelled;
+            vec![Command::Failed(i18n_f(
+                "Write stopped after {} bytes. The device holds an incomplete image and must be written again before use.",
+                &[&bytes_done.to_string()],
+            ))]
+        }
+        WorkMessage::Error(error) => {
+            session.phase = Phase::Failed;
+            vec![Command::Failed(error)]
+        }
+    };
+    (session, commands)
+}
+
+/// Advance a spanned write or restore by one message from its worker
+fn reduce_span(session: WriteSession, message: SpanMessage) -> (WriteSession, Vec<Command>) {
+    let mut session = session;
+    let Some(mut span) = session.span else {
+        return (session, Vec::new());
+    };
+
+    let commands = match message {
+        SpanMessage::Progress {
+            part,
+            phase,
+            bytes,
+            part_bytes,
+            bps,
+        } => {
+            session.phase = match phase {
+                SpanPhase::Writing => Phase::Writing,
+                SpanPhase::Checking => Phase::Verifying,
+                SpanPhase::Reading => Phase::Reading,
+            };
+            let mut commands = Vec::new();
+            if span.current != Some((part, phase)) {
+                span.current = Some((part, phase));
+                commands.push(Command::ClearThroughput);
+            }
+            let (label, title, bus_phase) = span_progress_labels(phase, part, span.parts);
+            commands.push(Command::Progress {
+                label,
+                title,
+                bus_phase,
+                bytes,
+                total: part_bytes,
+                bps,
+            });
+            commands
+        }
+        SpanMessage::NeedDevice {
+            index,
+            total,
+            min_bytes,
+            rejected,
+        } => {
+            session.phase = Phase::WaitingForDevice;
+            span.parts = total;
+            let numbers = [(index + 1).to_string(), total.to_string()];
+            let mut text = if span.writing {
+                #[allow(clippy::cast_precision_loss)] // Human-readable display
+                let min_gb = format!("{:.1} GB", min_bytes as f64 / 1_000_000_000.0);
+                i18n_f(
+                    "Part {} is written and checked. Remove that device, insert one of at least {} and choose it below.\n\nALL DATA ON IT WILL BE PERMANENTLY ERASED.",
+                    &[&index.to_string(), &min_gb],
+                )
+            } else {
+                i18n_f(
+                    "Part {} is restored. Insert the device holding part {} and choose it below.",
+                    &[&index.to_string(), &numbers[0]],
+                )
+            };
+            if let Some(reason) = rejected {
+                text.push_str(&i18n_f(
+                    "\n\nThe device chosen last was refused: {}",
+                    &[&reason],
+                ));
+            }
+            vec![
+                Command::Status(i18n_f(
+                    "Waiting for the device with part {} of {}",
+                    &[&numbers[0], &numbers[1]],
+                )),
+                Command::Detail(String::new()),
+                Command::RequestDevice {
+                    title: i18n_f(
+                        "Insert the Device for Part {} of {}",
+                        &[&numbers[0], &numbers[1]],
+                    ),
+                    text,
+                    min_bytes,
+                },
+            ]
+        }
+        SpanMessage::Complete(summary) => {
+            session.phase = Phase::Done;
+            vec![Command::SpanSucceeded(summary)]
+        }
+        SpanMessage::Error(error) => {
+            session.phase = Phase::Failed;
+            vec![Command::Failed(error)]
+        }
+    };
+    session.span = Some(span);
+    (session, commands)
+}
+
+/// Translated explanation of a failure for dialogs and the status line
+///
+/// Known failures get a specific message; anything else shows its full
+/// context chain.
+pub fn describe_error(error: &anyhow::Error) -> String {
+    let Some(known) = error.downcast_ref::<EtchError>() else {
+        return format!("{error:#}");
+    };
+
+    match known {
+        EtchError::NotAnImage { path, .. } => i18n_f(
+            "{} is not a disk image. Select a .iso or .img file.",
+            &[&path.display().to_string()],
+        ),
+        EtchError::ImageOnTarget { image, device } => i18n_f(
+            "{} is stored on {}. Writing it there would destroy the image while it is being read. Copy the image to another drive first.",
+            &[&image.display().to_string(), &device.display().to_string()],
+        ),
+        EtchError::UnrecognizedImage { path, size: 0 } => i18n_f(
+            "{} is empty. The download probably failed; download the image again.",
+            &[&path.display().to_string()],
+        ),
+        EtchError::UnrecognizedImage { path, size } => i18n_f(
+            "{} ({} bytes) does not look like a disk image: it has neither an ISO9660 filesystem nor a boot sector. It may be an incomplete download or a renamed file.",
+            &[&path.display().to_string(), &size.to_string()],
+        ),
+        EtchError::NotBlockDevice(path) => i18n_f(
+            "{} is not a block device.",
+            &[&path.display().to_string()],
+        ),
+        EtchError::DeviceTooSmall {
+            device,
+            device_bytes,
+            image_bytes,
+        } => {
+            #[allow(clippy::cast_precision_loss)] // Acceptable for human-readable display
+            let to_gb = |bytes: u64| format!("{:.1} GB", bytes as f64 / 1_000_000_000.0);
+            i18n_f(
+                "The image is {} ({} bytes) but {} holds only {} ({} bytes). Choose a larger device.",
+                &[
+                    &to_gb(*image_bytes),
+                    &image_bytes.to_string(),
+                    &device.display().to_string(),
+                    &to_gb(*device_bytes),
+                    &device_bytes.to_string(),
+                ],
+            )
+        }
+        EtchError::DeviceMounted(path) => i18n_f(
+            "{} or one of its partitions is mounted. Unmount it first.",
+            &[&path.display().to_string()],
+        ),
+        EtchError::PermissionDenied(path) => i18n_f(
+            "No permission to write {}. Start Etch with sudo.",
+            &[&path.display().to_string()],
+        ),
+        EtchError::DeviceReadOnly(path) => i18n_f(
+            "{} is read-only or locked. Check its write-protect switch, or unlock it if it is a self-encrypting drive.",
+            &[&path.display().to_string()],
+        ),
+        EtchError::DeviceDisconnected(path) => i18n_f(
+            "{} was disconnected. It reports no serial number, so Etch cannot tell whether a device that comes back is the same one. Reconnect it and write the image again.",
+            &[&path.display().to_string()],
+        ),
+        EtchError::DeviceBusy { device, pid } => i18n_f(
+            "{} is being written by another Etch window (process {}).",
+            &[&device.display().to_string(), &pid.to_string()],
+        ),
+        EtchError::ChecksumMismatch { expected, actual } => i18n_f(
+            "The image does not match the expected SHA-256 checksum and is probably corrupt. Download it again.\n\nExpected: {}\nActual: {}",
+            &[expected, actual],
+        ),
+        EtchError::NotSpannedPart(path) => i18n_f(
+            "{} does not hold a part of a spanned write, or the part was not finished.",
+            &[&path.display().to_string()],
+        ),
+        EtchError::PartFromOtherSet(path) => i18n_f(
+            "{} holds a part of a different spanned write. Insert the next device of this set.",
+            &[&path.display().to_string()],
+        ),
+        EtchError::PartOutOfOrder {
+            path,
+            expected,
+            found,
+        } => i18n_f(
+            "{} holds part {}, but part {} is next. Insert the devices in order.",
+            &[
+                &path.display().to_string(),
+                &found.to_string(),
+                &expected.to_string(),
+            ],
+        ),
+        EtchError::PartCorrupt { path, index } => i18n_f(
+            "Part {} on {} does not match its checksum. The device may be failing.",
+            &[&(index + 1).to_string(), &path.display().to_string()],
+        ),
+        EtchError::Cancelled { bytes_done } => i18n_f(
+            "Cancelled after {} bytes.",
+            &[&bytes_done.to_string()],
+        ),
+    }
+}
+
+/// Whether `ETCH_VERBOSE=1` asks for extra diagnostics on failures
+pub fn verbose_diagnostics() -> bool {
+    std::env::var("ETCH_VERBOSE").is_ok_and(|value| value.trim() == "1")
+}
+
+/// Whether a lone device may be selected automatically; `ETCH_AUTO_SELECT=0` turns it off
+pub fn auto_select_enabled() -> bool {
+    std::env::var("ETCH_AUTO_SELECT").map_or(true, |value| value.trim() != "0")
+}
+
+/// Whether `ETCH_COMPLETION_SOUND=1` asks for a sound when an operation ends
+pub fn completion_sound_enabled() -> bool {
+    std::env::var("ETCH_COMPLETION_SOUND").is_ok_and(|value| value.trim() == "1")
+}
+
+/// Whether `ETCH_COMPLETION_ATTENTION=1` asks the window to demand attention when an operation ends
+pub fn completion_attention_enabled() -> bool {
+    std::env::var("ETCH_COMPLETION_ATTENTION").is_ok_and(|value| value.trim() == "1")
+}
+
+/// Verification preselected in the confirmation dialog, from `ETCH_VERIFY_MODE`
+///
+/// Accepts `full`, `quick` or `skip`; anything else falls back to full
+/// verification with a warning on stderr.
+pub fn default_verify_mode() -> VerifyMode {
+    let Ok(value) = std::env::var("ETCH_VERIFY_MODE") else {
+        return VerifyMode::Full;
+    };
+    match value.trim().to_ascii_lowercase().as_str() {
+        "full" | "" => VerifyMode::Full,
+        "quick" => VerifyMode::Quick,
+        "skip" => VerifyMode::Skip,
+        other => {
+            eprintln!(
+                "WARNING: Ignoring unknown ETCH_VERIFY_MODE '{other}'; use full, quick or skip"
+            );
+            VerifyMode::Full
+        }
+    }
+}
+
+/// Whether `ETCH_BACKGROUND_WRITE=1` preselects a lower-priority write
+pub fn background_write_default() -> bool {
+    std::env::var("ETCH_BACKGROUND_WRITE").is_ok_and(|value| value.trim() == "1")
+}
+
+/// Whether `ETCH_ADVANCED_TARGETS=1` unlocks writing to individual partitions
+pub fn advanced_targets_enabled() -> bool {
+    std::env::var("ETCH_ADVANCED_TARGETS").is_ok_and(|value| value.trim() == "1")
+}
+
+/// Whether `ETCH_EXPERIMENTAL_USED_SPACE=1` offers writing only the used blocks of ext4 images
+pub fn used_space_writes_enabled() -> bool {
+    std::env::var("ETCH_EXPERIMENTAL_USED_SPACE").is_ok_and(|value| value.trim() == "1")
+}
+
+/// Stall timeout from `ETCH_STALL_TIMEOUT` (seconds), defaulting to 60
+pub fn stall_timeout() -> Duration {
+    std::env::var("ETCH_STALL_TIMEOUT")
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .filter(|&seconds| seconds > 0)
+        .map_or(DEFAULT_STALL_TIMEOUT, Duration::from_secs)
+}
+
+/// Window title for a given state, e.g. "Etch — Writing 43% · 2:10 left"
+pub fn format_window_title(state: &TitleState) -> String {
+    match state {
+        TitleState::Idle => "Etch".to_string(),
+        TitleState::Working { phase, progress } => {
+            let percent = progress.percentage();
+            match progress.eta_seconds() {
+                Some(eta) => i18n_f(
+                    "Etch — {} {}% · {} left",
+                    &[phase, &percent.to_string(), &format_eta(eta)],
+                ),
+                None => i18n_f("Etch — {} {}%", &[phase, &percent.to_string()]),
+            }
+        }
+        TitleState::Done => gettext("Etch — Done"),
+        TitleState::Error => gettext("⚠ Etch — Failed"),
+    }
+}
+
+/// "m:ss", or "h:mm:ss" for long operations
+pub fn format_eta(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, (seconds % 3600) / 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+/// Status label, title phase and D-Bus phase for progress on one part of a spanned operation
+fn span_progress_labels(phase: SpanPhase, part: u32, parts: u32) -> (String, String, &'static str) {
+    let (part, parts) = ((part + 1).to_string(), parts.to_string());
+    match phase {
+        SpanPhase::Writing => (
+            i18n_f("Writing part {} of {}...", &[&part, &parts]),
+            i18n_f("Writing part {} of {}", &[&part, &parts]),
+            "writing",
+        ),
+        SpanPhase::Checking => (
+            i18n_f("Checking part {} of {}...", &[&part, &parts]),
+            i18n_f("Checking part {} of {}", &[&part, &parts]),
+            "verifying",
+        ),
+        SpanPhase::Reading => (
+            i18n_f("Reading part {} of {}...", &[&part, &parts]),
+            i18n_f("Reading part {} of {}", &[&part, &parts]),
+            "reading",
+        ),
+    }
+}
+
+/// Text for one progress update
+pub struct ProgressText {
+    pub fraction: f64,
+    /// Shown inside the progress bar, e.g. "43%"
+    pub percent: String,
+    /// Shown below it, e.g. "1200/2800 MB · 31.5 MB/s"
+    pub detail: String,
+}
+
+impl ProgressText {
+    pub fn new(bytes: u64, total: u64, bps: u64) -> Self {
+        #[allow(clippy::cast_precision_loss)] // Acceptable for UI display
+        let fraction = bytes as f64 / total as f64;
+
+        #[allow(clippy::cast_precision_loss)] // Acceptable for UI display
+        let mb_per_sec = bps as f64 / 1_000_000.0;
+        #[allow(clippy::cast_precision_loss)]
+        let mb_done = bytes as f64 / 1_000_000.0;
+        #[allow(clippy::cast_precision_loss)]
+        let mb_total = total as f64 / 1_000_000.0;
+
+        Self {
+            fraction,
+            percent: format!("{:.0}%", fraction * 100.0),
+            detail: format!("{mb_done:.0}/{mb_total:.0} MB · {mb_per_sec:.1} MB/s"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::models::BlockDevice;
+
+    fn device(path: &str) -> BlockDevice {
+        BlockDevice {
+            path: PathBuf::from(path),
+            model: "Flash".to_string(),
+            vendor: "Acme".to_string(),
+            capacity_bytes: 16_000_000_000,
+            is_removable: true,
+            is_partition: false,
+            is_ventoy: false,
+        }
+    }
+
+    fn run(events: impl IntoIterator<Item = WriteEvent>) -> (WriteSession, Vec<Command>) {
+        run_from(WriteSession::new(PathBuf::from("/dev/sdb")), events)
+    }
+
+    fn run_from(
+        session: WriteSession,
+        events: impl IntoIterator<Item = WriteEvent>,
+    ) -> (WriteSession, Vec<Command>) {
+        let mut session = session;
+        let mut all = Vec::new();
+        for event in events {
+            let (next, commands) = reduce(session, event);
+            session = next;
+            all.extend(commands);
+        }
+        (session, all)
+    }
+
+    fn worker(message: WorkMessage) -> WriteEvent {
+        WriteEvent::Worker(message)
+    }
+
+    #[test]
+    fn full_write_walks_every_phase_to_done() {
+        let mut session = WriteSession::new(PathBuf::from("/dev/sdb"));
+        let steps = [
+            (WorkMessage::SourceCheckProgress(1, 4, 1), Phase::Checking),
+            (WorkMessage::Preparing, Phase::Preparing),
+            (WorkMessage::WriteProgress(1, 4, 1), Phase::Writing),
+            (WorkMessage::Syncing, Phase::Syncing),
+            (WorkMessage::Synced(Duration::from_secs(1)), Phase::Writing),
+            (WorkMessage::WriteComplete, Phase::Verifying),
+            (WorkMessage::VerifyProgress(4, 4, 1), Phase::Verifying),
+            (WorkMessage::VerifyComplete, Phase::Done),
+        ];
+        for (message, expected) in steps {
+            session = reduce(session, worker(message)).0;
+            assert_eq!(session.phase, expected);
+        }
+        assert!(session.is_finished());
+    }
+
+    #[test]
+    fn pause_button_appears_once_when_writing_starts() {
+        let (_, commands) = run([
+            worker(WorkMessage::WriteProgress(1, 4, 1)),
+            worker(WorkMessage::WriteProgress(2, 4, 1)),
+        ]);
+        let shown = commands
+            .iter()
+            .filter(|command| **command == Command::Pause(PauseButton::Pause))
+            .count();
+        assert_eq!(shown, 1);
+        assert_eq!(commands[0], Command::ClearThroughput);
+    }
+
+    #[test]
+    fn paused_and_syncing_silence_the_watchdog() {
+        let (session, commands) = run([
+            worker(WorkMessage::WriteProgress(1, 4, 1)),
+            worker(WorkMessage::Paused(1_000_000)),
+        ]);
+        assert_eq!(session.phase, Phase::Paused);
+        assert!(session.expects_silence());
+        assert!(commands.contains(&Command::Pause(PauseButton::Resume)));
+
+        let (session, _) = reduce(session, worker(WorkMessage::Resumed));
+        assert!(!session.expects_silence());
+        let (session, commands) = reduce(session, worker(WorkMessage::Syncing));
+        assert!(session.expects_silence());
+        assert!(commands.contains(&Command::Pause(PauseButton::Disabled)));
+    }
+
+    #[test]
+    fn long_hook_never_fails_or_aborts_a_verified_write() {
+        let (session, _) = run([
+            worker(WorkMessage::WriteProgress(4, 4, 1)),
+            worker(WorkMessage::WriteComplete),
+            worker(WorkMessage::VerifyProgress(4, 4, 1)),
+            worker(WorkMessage::RunningHook),
+        ]);
+        assert_eq!(session.phase, Phase::Hook);
+        assert!(session.expects_silence());
+
+        // Even a stall prompt left open from before the hook cannot abort it now
+        let (session, commands) = reduce(
+            session,
+            WriteEvent::StallAbort {
+                timeout: Duration::from_secs(60),
+            },
+        );
+        assert_eq!(session.phase, Phase::Hook);
+        assert!(commands.is_empty());
+
+        let (session, commands) = run_from(
+            session,
+            [
+                worker(WorkMessage::HookFailed(
+                    "The post-write hook was stopped after running for 300 seconds".to_string(),
+                )),
+                worker(WorkMessage::VerifyComplete),
+            ],
+        );
+        assert_eq!(session.phase, Phase::Done);
+        assert!(matches!(commands[0], Command::HookWarning(_)));
+        assert!(matches!(commands.last(), Some(Command::Succeeded { .. })));
+        assert!(!commands
+            .iter()
+            .any(|command| matches!(command, Command::Failed(_) | Command::Cancel)));
+    }
+
+    #[test]
+    fn success_reports_bios_bootability_and_reconnected_target() {
+        let (session, commands) = run([
+            worker(WorkMessage::WriteProgress(4, 4, 1)),
+            worker(WorkMessage::Reconnected(PathBuf::from("/dev/sdc"))),
+            worker(WorkMessage::BiosBootable(true)),
+            worker(WorkMessage::WriteComplete),
+            worker(WorkMessage::VerifyComplete),
+        ]);
+        assert_eq!(session.target, PathBuf::from("/dev/sdc"));
+        assert_eq!(
+            commands.last(),
+            Some(&Command::Succeeded {
+                detail: "BIOS bootable from USB".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn error_fails_with_the_worker_message() {
+        let (session, commands) = run([
+            worker(WorkMessage::WriteProgress(1, 4, 1)),
+            worker(WorkMessage::Error("Write failed: boom".to_string())),
+        ]);
+        assert_eq!(session.phase, Phase::Failed);
+        assert_eq!(
+            commands.last(),
+            Some(&Command::Failed("Write failed: boom".to_string()))
+        );
+    }
+
+    #[test]
+    fn cancel_reports_the_bytes_written() {
+        let (session, commands) = run([
+            worker(WorkMessage::WriteProgress(1, 4, 1)),
+            worker(WorkMessage::Cancelled(4096)),
+        ]);
+        assert_eq!(session.phase, Phase::Cancelled);
+        assert!(
+            matches!(commands.last(), Some(Command::Failed(message)) if message.contains("4096"))
+        );
+    }
+
+    #[test]
+    fn events_after_the_outcome_are_ignored() {
+        let (session, _) = run([worker(WorkMessage::Error("boom".to_string()))]);
+        let (session, commands) = reduce(session, worker(WorkMessage::VerifyComplete));
+        assert_eq!(session.phase, Phase::Failed);
+        assert!(commands.is_empty());
+        assert!(reduce(session, WriteEvent::WorkerGone).1.is_empty());
+    }
+
+    #[test]
+    fn worker_exiting_silently_is_a_failure() {
+        let (session, commands) = run([
+            worker(WorkMessage::WriteProgress(1, 4, 1)),
+            WriteEvent::WorkerGone,
+        ]);
+        assert_eq!(session.phase, Phase::Failed);
+        assert!(matches!(commands.as_slice(), [.., Command::Failed(_)]));
+    }
+
+    #[test]
+    fn stall_abort_waits_for_the_worker_to_exit() {
+        let (session, commands) = run([
+            worker(WorkMessage::WriteProgress(1, 4, 1)),
+            WriteEvent::StallAbort {
+                timeout: Duration::from_secs(60),
+            },
+        ]);
+        assert_eq!(session.phase, Phase::Aborting);
+        assert!(!session.is_finished());
+        assert!(session.expects_silence());
+        assert!(commands.contains(&Command::Cancel));
+        assert!(!commands
+            .iter()
+            .any(|command| matches!(command, Command::Failed(_))));
+
+        // Late reports from the stuck worker change nothing
+        let (session, commands) = reduce(session, worker(WorkMessage::Cancelled(4096)));
+        assert_eq!(session.phase, Phase::Aborting);
+        assert!(commands.is_empty());
+
+        let (session, commands) = reduce(session, WriteEvent::WorkerGone);
+        assert_eq!(session.phase, Phase::Failed);
+        assert!(
+            matches!(commands.as_slice(), [Command::Failed(message)] if message.contains("60s"))
+        );
+    }
+
+    #[test]
+    fn lone_device_is_auto_selected() {
+        let devices = [device("/dev/sdb")];
+        assert_eq!(
+            device_selection(None, false, &devices, true),
+            DeviceSelection::AutoSelect
+        );
+        assert_eq!(
+            device_selection(None, false, &devices, false),
+            DeviceSelection::Clear
+        );
+    }
+
+    #[test]
+    fn several_devices_are_never_auto_selected() {
+        let devices = [device("/dev/sdb"), device("/dev/sdc")];
+        assert_eq!(
+            device_selection(None, false, &devices, true),
+            DeviceSelection::Clear
+        );
+    }
+
+    #[test]
+    fn selection_is_left_alone_while_working() {
+        let devices = [device("/dev/sdc")];
+        let selected = Path::new("/dev/sdb");
+        assert_eq!(
+            device_selection(Some(selected), true, &devices, true),
+            DeviceSelection::Keep
+        );
+        assert_eq!(
+            device_selection(None, true, &devices, true),
+            DeviceSelection::Keep
+        );
+    }
+
+    #[test]
+    fn chosen_device_follows_its_new_position() {
+        let devices = [device("/dev/sda"), device("/dev/sdb")];
+        assert_eq!(
+            device_selection(Some(Path::new("/dev/sdb")), false, &devices, true),
+            DeviceSelection::Select(1)
+        );
+    }
+
+    #[test]
+    fn vanished_device_is_not_replaced_by_the_remaining_one() {
+        let devices = [device("/dev/sdc")];
+        assert_eq!(
+            device_selection(Some(Path::new("/dev/sdb")), false, &devices, true),
+            DeviceSelection::Clear
+        );
+    }
+
+    #[test]
+    fn cleared_selection_may_auto_select_the_lone_device() {
+        // The refresh after a vanished device was cleared
+        let devices = [device("/dev/sdc")];
+        assert_eq!(
+            device_selection(None, false, &devices, true),
+            DeviceSelection::AutoSelect
+        );
+    }
+
+    #[test]
+    fn write_needs_image_device_and_idle() {
+        let mut state = AppState::new();
+        assert!(!state.can_write());
+        state.selected_iso = Some(PathBuf::from("/tmp/image.iso"));
+        assert!(!state.can_write());
+        state.selected_device = Some(device("/dev/sdb"));
+        assert!(state.can_write());
+        state.is_working = true;
+        assert!(!state.can_write());
+    }
+
+    fn span_progress(part: u32, phase: SpanPhase) -> WriteEvent {
+        WriteEvent::Span(SpanMessage::Progress {
+            part,
+            phase,
+            bytes: 1,
+            part_bytes: 4,
+            bps: 1,
+        })
+    }
+
+    fn need_device(rejected: Option<&str>) -> WriteEvent {
+        WriteEvent::Span(SpanMessage::NeedDevice {
+            index: 1,
+            total: 3,
+            min_bytes: 2_000_000_000,
+            rejected: rejected.map(str::to_string),
+        })
+    }
+
+    fn run_spanned(
+        writing: bool,
+        events: impl IntoIterator<Item = WriteEvent>,
+    ) -> (WriteSession, Vec<Command>) {
+        run_from(
+            WriteSession::spanned(PathBuf::from("/dev/sdb"), writing, 3),
+            events,
+        )
+    }
+
+    #[test]
+    fn span_progress_counts_parts_from_one() {
+        let (label, title, bus_phase) = span_progress_labels(SpanPhase::Checking, 0, 3);
+        assert_eq!(label, "Checking part 1 of 3...");
+        assert_eq!(title, "Checking part 1 of 3");
+        assert_eq!(bus_phase, "verifying");
+        assert_eq!(
+            span_progress_labels(SpanPhase::Reading, 2, 3).0,
+            "Reading part 3 of 3..."
+        );
+    }
+
+    #[test]
+    fn throughput_restarts_for_each_part_and_phase() {
+        let (session, commands) = run_spanned(
+            true,
+            [
+                span_progress(0, SpanPhase::Writing),
+                span_progress(0, SpanPhase::Writing),
+                span_progress(0, SpanPhase::Checking),
+                span_progress(1, SpanPhase::Writing),
+            ],
+        );
+        assert_eq!(session.phase, Phase::Writing);
+        let cleared = commands
+            .iter()
+            .filter(|command| **command == Command::ClearThroughput)
+            .count();
+        assert_eq!(cleared, 3);
+        assert!(matches!(
+            commands.last(),
+            Some(Command::Progress { label, total: 4, .. }) if label == "Writing part 2 of 3..."
+        ));
+    }
+
+    #[test]
+    fn waiting_for_the_next_device_silences_the_watchdog() {
+        let (session, commands) = run_spanned(
+            true,
+            [span_progress(0, SpanPhase::Checking), need_device(None)],
+        );
+        assert_eq!(session.phase, Phase::WaitingForDevice);
+        assert!(session.expects_silence());
+        assert!(commands.contains(&Command::Status(
+            "Waiting for the device with part 2 of 3".to_string()
+        )));
+        assert!(matches!(
+            commands.last(),
+            Some(Command::RequestDevice { title, text, min_bytes: 2_000_000_000 })
+                if title == "Insert the Device for Part 2 of 3"
+                    && text.contains("at least 2.0 GB")
+                    && text.contains("ERASED")
+        ));
+
+        // The next part's progress ends the wait
+        let (session, _) = reduce(session, span_progress(1, SpanPhase::Writing));
+        assert!(!session.expects_silence());
+    }
+
+    #[test]
+    fn restore_asks_for_the_part_and_says_why_a_device_was_refused() {
+        let (_, commands) = run_spanned(false, [need_device(Some("wrong set"))]);
+        let Some(Command::RequestDevice { text, .. }) = commands.last() else {
+            panic!("no device requested: {commands:?}");
+        };
+        assert!(text.starts_with("Part 1 is restored. Insert the device holding part 2"));
+        assert!(text.ends_with("The device chosen last was refused: wrong set"));
+        assert!(!text.contains("ERASED"));
+    }
+
+    #[test]
+    fn spanned_outcomes_finish_the_session() {
+        let (session, commands) = run_spanned(
+            true,
+            [
+                span_progress(2, SpanPhase::Checking),
+                WriteEvent::Span(SpanMessage::Complete("split".to_string())),
+            ],
+        );
+        assert_eq!(session.phase, Phase::Done);
+        assert_eq!(
+            commands.last(),
+            Some(&Command::SpanSucceeded("split".to_string()))
+        );
+
+        let (session, commands) = run_spanned(
+            false,
+            [WriteEvent::Span(SpanMessage::Error("bad part".to_string()))],
+        );
+        assert_eq!(session.phase, Phase::Failed);
+        assert_eq!(commands, [Command::Failed("bad part".to_string())]);
+
+        let (session, commands) = run_spanned(true, [WriteEvent::WorkerGone]);
+        assert!(session.is_finished());
+        assert_eq!(
+            commands,
+            [Command::Failed(
+                "The spanned operation ended unexpectedly".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn span_messages_do_not_move_a_plain_write() {
+        let (session, commands) = run([need_device(None)]);
+        assert_eq!(session.phase, Phase::Idle);
+        assert!(commands.is_empty());
+    }
+}
@@ -1,44 +1,74 @@
+use crate::core::verification::VerifyMode;
+use crate::i18n::{gettext, i18n_f};
+use crate::io::lock::DeviceLock;
+use crate::io::oplog::OperationLog;
+use crate::ui::progress_bus::ProgressBus;
+use crate::ui::sparkline::Sparkline;
+use crate::ui::state::{
+    advanced_targets_enabled, auto_select_enabled, background_write_default,
+    completion_attention_enabled, completion_sound_enabled, default_verify_mode, describe_error,
+    device_selection, format_window_title, reduce, stall_timeout, used_space_writes_enabled,
+    AppState, ChecksumMessage, Command, DeviceSelection, ImageMessage, PauseButton, Phase,
+    ProgressText, SpanMessage, TitleState, Watchdog, WorkMessage, WriteEvent, WriteSession,
+    LONG_PAUSE_WARNING, MESSAGE_POLL_INTERVAL, TITLE_UPDATE_INTERVAL,
+};
+use crate::ui::worker::{run_span, run_write, DeviceReply, SpanJob, WriteJob, WriteOptions};
 use gtk4::prelude::*;
 use gtk4::{
     glib, Application, ApplicationWindow, Box as GtkBox, Button, ButtonsType, DropDown,
-    FileChooserAction, FileChooserDialog, Image, Label, MessageDialog, MessageType, Orientation,
-    ProgressBar, ResponseType, StringList,
+    FileChooserAction, FileChooserDialog, Image, Label, MenuButton, MessageDialog, MessageType,
+    Orientation, ProgressBar, ResponseType, StringList,
 };
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::path::PathBuf;
 use std::rc::Rc;
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Clone)]
-struct AppState {
-    selected_iso: Option<PathBuf>,
-    selected_device: Option<crate::core::models::BlockDevice>,
-    is_working: bool,
-}
+/// Below this window width SOURCE is stacked above TARGET
+const NARROW_LAYOUT_WIDTH: i32 = 640;
 
 #[derive(Clone)]
 struct UIComponents {
+    window: ApplicationWindow,
     status_dot: GtkBox,
     progress_label: Label,
     progress_bar: ProgressBar,
     speed_label: Label,
+    /// Device temperature, only shown while a sensor is readable
+    temperature_label: Label,
+    throughput: Sparkline,
     write_button: Button,
+    pause_button: Button,
+    log_button: Button,
+    boot_test_button: Button,
     iso_button: Button,
     device_dropdown: DropDown,
+    identify_button: Button,
+    /// None when there is no session bus, e.g. under plain sudo
+    progress_bus: Option<ProgressBus>,
 }
 
-#[derive(Debug, Clone)]
-enum WorkMessage {
-    WriteProgress(u64, u64, u64),  // bytes, total, bps
-    VerifyProgress(u64, u64, u64), // bytes, total, bps
-    WriteComplete,
-    VerifyComplete,
-    Error(String),
+/// Widgets updated when an ISO is selected
+#[derive(Clone)]
+struct SourceWidgets {
+    iso_label: Label,
+    iso_details: Label,
+    iso_details_expander: gtk4::Expander,
+    iso_contents: Label,
+    iso_contents_expander: gtk4::Expander,
+    write_button: Button,
 }
 
+/// Top-level entries listed in the contents preview; the rest are counted
+const MAX_PREVIEW_ENTRIES: usize = 100;
+
+/// Seconds the confirm button stays locked, so a stray Enter or click cannot start a wipe
+const CONFIRM_DELAY_SECONDS: u32 = 3;
+
 /// Build the main application window
-#[allow(clippy::too_many_lines)] // UI setup requires comprehensive code
 pub fn build_ui(app: &Application) {
     // Load CSS
     let css_provider = gtk4::CssProvider::new();
@@ -51,17 +81,13 @@ pub fn build_ui(app: &Application) {
 
     let window = ApplicationWindow::builder()
         .application(app)
-        .title("Etch")
+        .title(format_window_title(&TitleState::Idle))
         .default_width(760)
         .default_height(480)
-        .resizable(false)
         .build();
+    window.set_size_request(420, 400);
 
-    let state = Rc::new(RefCell::new(AppState {
-        selected_iso: None,
-        selected_device: None,
-        is_working: false,
-    }));
+    let state = Rc::new(RefCell::new(AppState::new()));
 
     let main_box = GtkBox::new(Orientation::Vertical, 0);
     main_box.add_css_class("main-container");
@@ -70,7 +96,88 @@ pub fn build_ui(app: &Application) {
     main_box.set_margin_start(24);
     main_box.set_margin_end(24);
 
-    // Title Section - Horizontal Layout with Status Dot
+    let (title_box, status_dot) = build_title_section();
+    main_box.append(&title_box);
+
+    // Warning - Compact
+    let warning = Label::new(Some(&gettext(
+        "All data on the target will be permanently erased",
+    )));
+    warning.add_css_class("warning-compact");
+    warning.set_halign(gtk4::Align::Start);
+    main_box.append(&warning);
+
+    // Main Content - Horizontal Layout
+    let content_box = GtkBox::new(Orientation::Horizontal, 32);
+    content_box.set_homogeneous(true);
+    content_box.set_hexpand(true);
+    content_box.set_vexpand(true);
+
+    // Enabled once both a source and a target are chosen
+    let write_button = build_icon_button(
+        &gettext("Write"),
+        "media-floppy-symbolic",
+        "write-button-compact",
+    );
+    write_button.set_sensitive(false);
+    write_button.set_size_request(120, -1);
+
+    let (iso_section, iso_button) = build_source_section(&window, &state, &write_button);
+    content_box.append(&iso_section);
+    // Built before TARGET, whose device scan reports an auto-selection on its label
+    let progress = build_progress_section(&write_button);
+    let (device_section, device_dropdown, identify_button) =
+        build_target_section(&state, &write_button, &progress.progress_label);
+    content_box.append(&device_section);
+    main_box.append(&content_box);
+    main_box.append(&progress.container);
+
+    window.set_child(Some(&main_box));
+
+    // Re-check the layout once GTK has applied the new size
+    for property in ["default-width", "maximized", "fullscreened"] {
+        let content_box = content_box.clone();
+        window.connect_notify_local(Some(property), move |window, _| {
+            let window = window.clone();
+            let content_box = content_box.clone();
+            glib::idle_add_local_once(move || update_content_layout(&window, &content_box));
+        });
+    }
+
+    // Widgets driven by write and image operations
+    let ui = UIComponents {
+        window: window.clone(),
+        status_dot,
+        progress_label: progress.progress_label,
+        progress_bar: progress.progress_bar,
+        speed_label: progress.speed_label,
+        temperature_label: progress.temperature_label,
+        throughput: progress.throughput,
+        write_button,
+        pause_button: progress.pause_button,
+        log_button: progress.log_button,
+        boot_test_button: progress.boot_test_button,
+        iso_button,
+        device_dropdown,
+        identify_button,
+        progress_bus: app.dbus_connection().and_then(|connection| {
+            ProgressBus::export(&connection)
+                .map_err(|e| eprintln!("WARNING: Not publishing progress on D-Bus: {e}"))
+                .ok()
+        }),
+    };
+
+    add_menu_actions(&state, &ui);
+    connect_action_buttons(&state, &ui);
+    connect_window_lifecycle(&window, &state);
+
+    window.present();
+}
+
+/// Title row: status dot, name and the application menu
+///
+/// Returns the row and the status dot, which tracks the running operation.
+fn build_title_section() -> (GtkBox, GtkBox) {
     let title_box = GtkBox::new(Orientation::Horizontal, 12);
     title_box.add_css_class("title-section");
     title_box.set_halign(gtk4::Align::Fill);
@@ -86,37 +193,61 @@ pub fn build_ui(app: &Application) {
     title.add_css_class("app-title");
     title_box.append(&title);
 
-    let subtitle = Label::new(Some("ISO to USB Writer"));
+    let subtitle = Label::new(Some(&gettext("ISO to USB Writer")));
     subtitle.add_css_class("app-subtitle");
     subtitle.set_hexpand(true);
     subtitle.set_halign(gtk4::Align::Start);
     subtitle.set_valign(gtk4::Align::Center);
     title_box.append(&subtitle);
 
-    main_box.append(&title_box);
+    // Application Menu, backed by the actions from `add_menu_actions`
+    let menu = gtk4::gio::Menu::new();
+    menu.append(
+        Some(&gettext("Create Image from Device…")),
+        Some("win.create-image"),
+    );
+    menu.append(
+        Some(&gettext("Restore Spanned Image…")),
+        Some("win.restore-image"),
+    );
+    menu.append(Some(&gettext("Verify a File…")), Some("win.verify-file"));
+    menu.append(Some(&gettext("Diagnostics")), Some("win.diagnostics"));
+    menu.append(
+        Some(&gettext("Export Support Report…")),
+        Some("win.support-report"),
+    );
+    menu.append(Some(&gettext("About Etch")), Some("win.about"));
 
-    // Warning - Compact
-    let warning = Label::new(Some("All data on the target will be permanently erased"));
-    warning.add_css_class("warning-compact");
-    warning.set_halign(gtk4::Align::Start);
-    main_box.append(&warning);
+    let menu_button = MenuButton::builder()
+        .icon_name("open-menu-symbolic")
+        .menu_model(&menu)
+        .valign(gtk4::Align::Center)
+        .build();
+    menu_button.add_css_class("menu-button-compact");
+    title_box.append(&menu_button);
 
-    // Main Content - Horizontal Layout
-    let content_box = GtkBox::new(Orientation::Horizontal, 32);
-    content_box.set_homogeneous(true);
-    content_box.set_hexpand(true);
+    (title_box, status_dot)
+}
 
-    // ISO Selection Section
+/// SOURCE section: the chosen image, its details and the file chooser
+///
+/// Also registers the `open-iso` action used by "Open with Etch". Returns the
+/// section and its Choose File button.
+fn build_source_section(
+    window: &ApplicationWindow,
+    state: &Rc<RefCell<AppState>>,
+    write_button: &Button,
+) -> (GtkBox, Button) {
     let iso_section = GtkBox::new(Orientation::Vertical, 8);
     iso_section.add_css_class("section-compact");
     iso_section.set_vexpand(true);
 
-    let iso_section_title = Label::new(Some("SOURCE"));
+    let iso_section_title = Label::new(Some(&gettext("SOURCE")));
     iso_section_title.add_css_class("section-title-compact");
     iso_section_title.set_halign(gtk4::Align::Start);
     iso_section.append(&iso_section_title);
 
-    let iso_label = Label::new(Some("No ISO selected"));
+    let iso_label = Label::new(Some(&gettext("No ISO selected")));
     iso_label.add_css_class("file-label-compact");
     iso_label.set_halign(gtk4::Align::Start);
     iso_label.set_ellipsize(gtk4::pango::EllipsizeMode::Middle);
@@ -125,62 +256,237 @@ pub fn build_ui(app: &Application) {
     iso_label.set_valign(gtk4::Align::Start);
     iso_section.append(&iso_label);
 
-    let iso_button = build_icon_button("Choose File", "document-open-symbolic", "button-compact");
+    // Image details, filled in once an ISO is selected
+    let iso_details = Label::new(None);
+    iso_details.add_css_class("details-label-compact");
+    iso_details.set_halign(gtk4::Align::Start);
+    iso_details.set_xalign(0.0);
+    iso_details.set_selectable(true);
+
+    let iso_details_expander = gtk4::Expander::new(Some(&gettext("Details")));
+    iso_details_expander.add_css_class("details-expander");
+    iso_details_expander.set_child(Some(&iso_details));
+    iso_details_expander.set_visible(false);
+    iso_section.append(&iso_details_expander);
+
+    // Top-level directory of the image, to tell variants apart
+    let iso_contents = Label::new(None);
+    iso_contents.add_css_class("details-label-compact");
+    iso_contents.set_halign(gtk4::Align::Start);
+    iso_contents.set_xalign(0.0);
+    iso_contents.set_selectable(true);
+
+    let iso_contents_scroll = gtk4::ScrolledWindow::new();
+    iso_contents_scroll.set_policy(gtk4::PolicyType::Never, gtk4::PolicyType::Automatic);
+    iso_contents_scroll.set_max_content_height(160);
+    iso_contents_scroll.set_propagate_natural_height(true);
+    iso_contents_scroll.set_child(Some(&iso_contents));
+
+    let iso_contents_expander = gtk4::Expander::new(Some(&gettext("Preview Contents")));
+    iso_contents_expander.add_css_class("details-expander");
+    iso_contents_expander.set_child(Some(&iso_contents_scroll));
+    iso_contents_expander.set_visible(false);
+    iso_section.append(&iso_contents_expander);
+
+    let iso_button = build_icon_button(
+        &gettext("Choose File"),
+        "document-open-symbolic",
+        "button-compact",
+    );
     iso_section.append(&iso_button);
 
-    content_box.append(&iso_section);
+    let source = SourceWidgets {
+        iso_label,
+        iso_details,
+        iso_details_expander,
+        iso_contents,
+        iso_contents_expander,
+        write_button: write_button.clone(),
+    };
+
+    // Files handed over by the desktop ("Open with Etch") or the command line
+    let open_action =
+        gtk4::gio::SimpleAction::new("open-iso", Some(&PathBuf::static_variant_type()));
+    let window_clone = window.clone();
+    let source_clone = source.clone();
+    let state_clone = state.clone();
+    open_action.connect_activate(move |_, parameter| {
+        let Some(path) = parameter.and_then(PathBuf::from_variant) else {
+            return;
+        };
+        if state_clone.borrow().is_working {
+            show_error_dialog(
+                &window_clone,
+                &gettext("A write is in progress. Open the image again once it has finished."),
+            );
+            return;
+        }
+        select_iso(&window_clone, path, &source_clone, &state_clone);
+    });
+    window.add_action(&open_action);
+
+    // Connect ISO button
+    let state_clone = state.clone();
+    iso_button.connect_clicked(move |button| {
+        let window = button.root().and_downcast::<ApplicationWindow>().unwrap();
+
+        let dialog = FileChooserDialog::new(
+            Some(&gettext("Select ISO File")),
+            Some(&window),
+            FileChooserAction::Open,
+            &[
+                (&gettext("Cancel"), ResponseType::Cancel),
+                (&gettext("Open"), ResponseType::Accept),
+            ],
+        );
+
+        let source = source.clone();
+        let state = state_clone.clone();
+
+        dialog.connect_response(move |dialog, response| {
+            if response == ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                    select_iso(&window, path, &source, &state);
+                }
+            }
+            dialog.close();
+        });
+
+        dialog.show();
+    });
+
+    (iso_section, iso_button)
+}
 
-    // Device Selection Section
+/// TARGET section: the device list and the Identify button
+///
+/// Starts the background device scan, which notes an auto-selected device on
+/// `progress_label`. Returns the section, the device dropdown and the Identify button.
+fn build_target_section(
+    state: &Rc<RefCell<AppState>>,
+    write_button: &Button,
+    progress_label: &Label,
+) -> (GtkBox, DropDown, Button) {
     let device_section = GtkBox::new(Orientation::Vertical, 8);
     device_section.add_css_class("section-compact");
     device_section.set_vexpand(true);
 
-    let device_section_title = Label::new(Some("TARGET"));
+    let device_section_title = Label::new(Some(&gettext("TARGET")));
     device_section_title.add_css_class("section-title-compact");
     device_section_title.set_halign(gtk4::Align::Start);
     device_section.append(&device_section_title);
 
-    // Get list of removable devices
-    let devices = crate::io::devices::list_removable_devices().unwrap_or_default();
-    let device_strings = StringList::new(&[]);
-
-    if devices.is_empty() {
-        device_strings.append("No removable devices detected");
-    } else {
-        for device in &devices {
-            let display = format!(
-                "{} · {} {} · {}",
-                device.path.display(),
-                device.vendor,
-                device.model,
-                device.capacity_human()
-            );
-            device_strings.append(&display);
-        }
-    }
-
+    // Devices are listed by a background scan so the window shows at once
+    let devices: Rc<RefCell<Vec<crate::core::models::BlockDevice>>> = Rc::default();
+    let device_strings = StringList::new(&[&gettext("Scanning devices…")]);
     let device_dropdown = DropDown::new(Some(device_strings), None::<gtk4::Expression>);
-    device_dropdown.set_sensitive(!devices.is_empty());
+    device_dropdown.set_sensitive(false);
     device_dropdown.add_css_class("dropdown-compact");
     device_section.append(&device_dropdown);
 
-    content_box.append(&device_section);
-    main_box.append(&content_box);
+    // Blinks the selected stick's LED so it can be told apart from the others
+    let identify_button = build_icon_button(
+        &gettext("Identify"),
+        "find-location-symbolic",
+        "button-compact",
+    );
+    identify_button.set_sensitive(false);
+    identify_button.set_tooltip_text(Some(&gettext(
+        "Read from the selected device for a few seconds so its activity light flashes",
+    )));
+    device_section.append(&identify_button);
 
-    // Action Section - Horizontal
+    // Connect device dropdown
+    let state_clone = state.clone();
+    let write_button_clone = write_button.clone();
+    let devices_clone = devices.clone();
+
+    device_dropdown.connect_selected_notify(move |dropdown| {
+        let selected = dropdown.selected();
+        let device = devices_clone.borrow().get(selected as usize).cloned();
+        state_clone.borrow_mut().selected_device = device;
+        write_button_clone.set_sensitive(state_clone.borrow().can_write());
+    });
+
+    let dropdown_clone = device_dropdown.clone();
+    let identify_clone = identify_button.clone();
+    let progress_label_clone = progress_label.clone();
+    let state_clone = state.clone();
+    glib::spawn_future_local(async move {
+        match gtk4::gio::spawn_blocking(enumerate_targets).await {
+            Ok(scan) => show_devices(
+                &dropdown_clone,
+                &identify_clone,
+                &progress_label_clone,
+                &devices,
+                scan,
+                &state_clone,
+            ),
+            Err(_) => eprintln!("WARNING: Device scan thread panicked"),
+        }
+    });
+
+    (device_section, device_dropdown, identify_button)
+}
+
+/// The action row built by `build_progress_section`
+struct ProgressSection {
+    container: GtkBox,
+    pause_button: Button,
+    log_button: Button,
+    boot_test_button: Button,
+    progress_label: Label,
+    progress_bar: ProgressBar,
+    speed_label: Label,
+    temperature_label: Label,
+    throughput: Sparkline,
+}
+
+/// Action row: the Write button and its companions, with the progress readout beside them
+fn build_progress_section(write_button: &Button) -> ProgressSection {
     let action_box = GtkBox::new(Orientation::Horizontal, 12);
     action_box.set_margin_top(8);
+    action_box.append(write_button);
 
-    let write_button = build_icon_button("Write", "media-floppy-symbolic", "write-button-compact");
-    write_button.set_sensitive(false);
-    write_button.set_size_request(120, -1);
-    action_box.append(&write_button);
+    // Only shown while writing; yields the USB bus without aborting
+    let pause_button = build_icon_button(
+        &gettext("Pause"),
+        "media-playback-pause-symbolic",
+        "button-compact",
+    );
+    pause_button.set_size_request(120, -1);
+    pause_button.set_visible(false);
+    action_box.append(&pause_button);
+
+    // Only shown after a failure that left an operation log behind
+    let log_button = build_icon_button(
+        &gettext("Open Log"),
+        "text-x-generic-symbolic",
+        "button-compact",
+    );
+    log_button.set_size_request(120, -1);
+    log_button.set_visible(false);
+    action_box.append(&log_button);
+
+    // Only shown after a verified write when QEMU is installed
+    let boot_test_button =
+        build_icon_button(&gettext("Test Boot"), "computer-symbolic", "button-compact");
+    boot_test_button.set_size_request(120, -1);
+    boot_test_button.set_visible(false);
+    action_box.append(&boot_test_button);
 
     // Progress Section - Compact
     let progress_box = GtkBox::new(Orientation::Vertical, 4);
     progress_box.set_hexpand(true);
 
-    let progress_label = Label::new(Some("Ready"));
+    // Name a non-default verification up front, so a skipped check is no surprise
+    let default_mode = default_verify_mode();
+    let ready_text = if default_mode == VerifyMode::Full {
+        gettext("Ready")
+    } else {
+        i18n_f("Ready · {}", &[&verify_mode_label(default_mode)])
+    };
+    let progress_label = Label::new(Some(&ready_text));
     progress_label.add_css_class("progress-label-compact");
     progress_label.set_halign(gtk4::Align::Start);
     progress_box.append(&progress_label);
@@ -190,96 +496,119 @@ pub fn build_ui(app: &Application) {
     progress_bar.add_css_class("progress-compact");
     progress_box.append(&progress_bar);
 
+    let speed_row = GtkBox::new(Orientation::Horizontal, 8);
     let speed_label = Label::new(Some(""));
     speed_label.add_css_class("speed-label-compact");
     speed_label.set_halign(gtk4::Align::Start);
-    progress_box.append(&speed_label);
+    speed_row.append(&speed_label);
 
-    action_box.append(&progress_box);
-    main_box.append(&action_box);
+    let temperature_label = Label::new(None);
+    temperature_label.add_css_class("speed-label-compact");
+    temperature_label.set_tooltip_text(Some(&gettext(
+        "Device temperature. Hot drives slow down to protect themselves.",
+    )));
+    temperature_label.set_visible(false);
+    speed_row.append(&temperature_label);
+    progress_box.append(&speed_row);
 
-    window.set_child(Some(&main_box));
+    let throughput = Sparkline::new();
+    progress_box.append(throughput.widget());
 
-    // Connect ISO button
-    let iso_label_clone = iso_label;
-    let state_clone = state.clone();
-    let write_button_clone = write_button.clone();
-    let devices_clone = devices.clone();
-    let device_dropdown_clone = device_dropdown.clone();
+    action_box.append(&progress_box);
 
-    iso_button.connect_clicked(move |button| {
-        let window = button.root().and_downcast::<ApplicationWindow>().unwrap();
+    ProgressSection {
+        container: action_box,
+        pause_button,
+        log_button,
+        boot_test_button,
+        progress_label,
+        progress_bar,
+        speed_label,
+        temperature_label,
+        throughput,
+    }
+}
 
-        let dialog = FileChooserDialog::new(
-            Some("Select ISO File"),
-            Some(&window),
-            FileChooserAction::Open,
-            &[
-                ("Cancel", ResponseType::Cancel),
-                ("Open", ResponseType::Accept),
-            ],
-        );
+/// Window actions behind the application menu entries
+fn add_menu_actions(state: &Rc<RefCell<AppState>>, ui: &UIComponents) {
+    let window = &ui.window;
 
-        let iso_label = iso_label_clone.clone();
-        let state = state_clone.clone();
-        let write_button = write_button_clone.clone();
-        let devices = devices_clone.clone();
-        let device_dropdown = device_dropdown_clone.clone();
+    let about_action = gtk4::gio::SimpleAction::new("about", None);
+    let window_clone = window.clone();
+    about_action.connect_activate(move |_, _| show_about_dialog(&window_clone));
+    window.add_action(&about_action);
 
-        dialog.connect_response(move |dialog, response| {
-            if response == ResponseType::Accept {
-                if let Some(file) = dialog.file() {
-                    if let Some(path) = file.path() {
-                        let filename = path
-                            .file_name()
-                            .and_then(|n| n.to_str())
-                            .unwrap_or("Unknown");
-                        iso_label.set_text(filename);
-                        state.borrow_mut().selected_iso = Some(path);
-
-                        // Enable write button if device also selected
-                        let device_selected = !devices.is_empty()
-                            && device_dropdown.selected() != gtk4::INVALID_LIST_POSITION;
-                        write_button.set_sensitive(device_selected && !state.borrow().is_working);
-                    }
-                }
-            }
-            dialog.close();
-        });
+    let diagnostics_action = gtk4::gio::SimpleAction::new("diagnostics", None);
+    let window_clone = window.clone();
+    diagnostics_action.connect_activate(move |_, _| show_diagnostics_dialog(&window_clone));
+    window.add_action(&diagnostics_action);
 
-        dialog.show();
+    let verify_file_action = gtk4::gio::SimpleAction::new("verify-file", None);
+    let window_clone = window.clone();
+    verify_file_action.connect_activate(move |_, _| show_verify_file_dialog(&window_clone));
+    window.add_action(&verify_file_action);
+
+    let report_action = gtk4::gio::SimpleAction::new("support-report", None);
+    let window_clone = window.clone();
+    let state_clone = state.clone();
+    report_action.connect_activate(move |_, _| {
+        show_support_report_dialog(&window_clone, &state_clone);
     });
+    window.add_action(&report_action);
 
-    // Connect device dropdown
+    let image_action = gtk4::gio::SimpleAction::new("create-image", None);
     let state_clone = state.clone();
-    let write_button_clone = write_button.clone();
-    let devices_clone = devices;
+    let ui_clone = ui.clone();
+    image_action.connect_activate(move |_, _| show_create_image_dialog(&state_clone, &ui_clone));
+    window.add_action(&image_action);
 
-    device_dropdown.connect_selected_notify(move |dropdown| {
-        let selected = dropdown.selected();
-        if selected != gtk4::INVALID_LIST_POSITION && (selected as usize) < devices_clone.len() {
-            state_clone.borrow_mut().selected_device =
-                Some(devices_clone[selected as usize].clone());
+    let restore_action = gtk4::gio::SimpleAction::new("restore-image", None);
+    let state_clone = state.clone();
+    let ui_clone = ui.clone();
+    restore_action.connect_activate(move |_, _| show_restore_image_dialog(&state_clone, &ui_clone));
+    window.add_action(&restore_action);
+}
+
+/// Click handlers for Identify and the action row's buttons
+fn connect_action_buttons(state: &Rc<RefCell<AppState>>, ui: &UIComponents) {
+    // Connect identify button
+    let state_clone = state.clone();
+    let window_clone = ui.window.clone();
+    let progress_label_clone = ui.progress_label.clone();
+    ui.identify_button.connect_clicked(move |button| {
+        start_identify(button, &window_clone, &progress_label_clone, &state_clone);
+    });
 
-            // Enable write button if ISO also selected
-            let state_ref = state_clone.borrow();
-            let iso_selected = state_ref.selected_iso.is_some();
-            write_button_clone.set_sensitive(iso_selected && !state_ref.is_working);
+    // Connect pause button; the label flips once the writer acknowledges
+    let state_clone = state.clone();
+    ui.pause_button.connect_clicked(move |button| {
+        let pause = state_clone.borrow().pause_requested.clone();
+        pause.store(!pause.load(Ordering::Relaxed), Ordering::Relaxed);
+        button.set_sensitive(false);
+    });
+
+    // Connect log button
+    let state_clone = state.clone();
+    let window_clone = ui.window.clone();
+    ui.log_button.connect_clicked(move |_| {
+        if let Some(path) = &state_clone.borrow().operation_log {
+            let uri = gtk4::gio::File::for_path(path).uri();
+            #[allow(deprecated)] // UriLauncher needs GTK 4.10
+            gtk4::show_uri(Some(&window_clone), &uri, gtk4::gdk::CURRENT_TIME);
         }
     });
 
-    // Connect write button
-    let state_clone = state;
-    let window_clone = window.clone();
-    let status_dot_clone = status_dot;
-    let progress_label_clone = progress_label;
-    let progress_bar_clone = progress_bar;
-    let speed_label_clone = speed_label;
-    let write_button_clone = write_button.clone();
-    let iso_button_clone = iso_button;
-    let device_dropdown_clone = device_dropdown;
+    // Connect test boot button
+    let state_clone = state.clone();
+    let progress_label_clone = ui.progress_label.clone();
+    ui.boot_test_button.connect_clicked(move |button| {
+        start_boot_test(button, &progress_label_clone, &state_clone);
+    });
 
-    write_button.connect_clicked(move |_| {
+    // Connect write button
+    let state_clone = state.clone();
+    let ui_clone = ui.clone();
+    ui.write_button.connect_clicked(move |_| {
         let state = state_clone.borrow();
         if let (Some(iso), Some(device)) = (&state.selected_iso, &state.selected_device) {
             let iso = iso.clone();
@@ -287,30 +616,258 @@ pub fn build_ui(app: &Application) {
             drop(state);
 
             show_confirmation_dialog(
-                &window_clone,
+                &ui_clone.window,
                 iso,
                 device,
                 state_clone.clone(),
-                UIComponents {
-                    status_dot: status_dot_clone.clone(),
-                    progress_label: progress_label_clone.clone(),
-                    progress_bar: progress_bar_clone.clone(),
-                    speed_label: speed_label_clone.clone(),
-                    write_button: write_button_clone.clone(),
-                    iso_button: iso_button_clone.clone(),
-                    device_dropdown: device_dropdown_clone.clone(),
-                },
+                ui_clone.clone(),
             );
         }
     });
+}
 
-    window.present();
+/// Closing, termination signals and re-opening while an operation runs
+fn connect_window_lifecycle(window: &ApplicationWindow, state: &Rc<RefCell<AppState>>) {
+    // Closing mid-write must not silently abandon the device
+    let state_clone = state.clone();
+    window.connect_close_request(move |window| {
+        if state_clone.borrow().is_working {
+            show_close_during_write_dialog(window, &state_clone);
+            glib::Propagation::Stop
+        } else {
+            glib::Propagation::Proceed
+        }
+    });
+
+    // Logout, Ctrl+C or kill: let a running operation stop at a chunk boundary
+    // and flush before quitting, instead of dying mid-write
+    for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+        let state_clone = state.clone();
+        let window_clone = window.clone();
+        glib::unix_signal_add_local(signal, move || {
+            handle_termination_signal(&window_clone, &state_clone, signal);
+            glib::ControlFlow::Continue
+        });
+    }
+
+    // Re-opening Etch while a background write runs shows the window again
+    let state_clone = state.clone();
+    window.connect_visible_notify(move |window| {
+        if window.is_visible() {
+            state_clone.borrow_mut().quit_when_idle = false;
+        }
+    });
+}
+
+/// Removable disks (and their partitions with `ETCH_ADVANCED_TARGETS=1`)
+///
+/// Runs off the UI thread. The error is the reason enumeration is impossible,
+/// as opposed to there being no devices.
+fn enumerate_targets() -> (Vec<crate::core::models::BlockDevice>, Option<String>) {
+    match crate::io::devices::probe_enumeration_backend() {
+        Some(backend) => eprintln!("INFO: Enumerating devices via {}", backend.label()),
+        None => eprintln!("WARNING: Neither /sys/block nor lsblk is available"),
+    }
+    let enumeration = crate::io::devices::list_removable_devices();
+    let enumeration_error = enumeration.as_ref().err().map(|e| format!("{e:#}"));
+    let mut devices = enumeration.unwrap_or_default();
+    if advanced_targets_enabled() {
+        devices = devices
+            .into_iter()
+            .flat_map(|disk| {
+                let partitions = crate::io::devices::list_partitions(&disk);
+                std::iter::once(disk).chain(partitions)
+            })
+            .collect();
+    }
+    (devices, enumeration_error)
+}
+
+/// Replace the "Scanning devices…" placeholder with the scan's results
+fn show_devices(
+    dropdown: &DropDown,
+    identify_button: &Button,
+    progress_label: &Label,
+    devices: &Rc<RefCell<Vec<crate::core::models::BlockDevice>>>,
+    (scanned, enumeration_error): (Vec<crate::core::models::BlockDevice>, Option<String>),
+    state: &Rc<RefCell<AppState>>,
+) {
+    let labels: Vec<String> = if let Some(e) = &enumeration_error {
+        eprintln!("WARNING: {e}");
+        vec![gettext("Cannot enumerate devices on this system")]
+    } else if scanned.is_empty() {
+        vec![gettext("No removable devices detected")]
+    } else {
+        scanned
+            .iter()
+            .map(|device| {
+                if device.is_partition {
+                    i18n_f(
+                        "  └ {} · partition · {}",
+                        &[&device.path.display().to_string(), &device.capacity_human()],
+                    )
+                } else {
+                    format!(
+                        "{} · {} {} · {}",
+                        device.path.display(),
+                        device.vendor,
+                        device.model,
+                        device.capacity_human()
+                    )
+                }
+            })
+            .collect()
+    };
+
+    // Swap the entries while the list is still empty, so the selection
+    // change this causes does not pick a device on its own
+    if let Some(strings) = dropdown.model().and_downcast::<StringList>() {
+        let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+        strings.splice(0, strings.n_items(), &labels);
+    }
+    *devices.borrow_mut() = scanned;
+
+    let has_devices = !devices.borrow().is_empty();
+    let working = state.borrow().is_working;
+    dropdown.set_sensitive(has_devices && !working);
+    dropdown.set_tooltip_text(enumeration_error.as_deref());
+    identify_button.set_sensitive(has_devices && !working);
+    sync_device_selection(dropdown, progress_label, &devices.borrow(), state);
+}
+
+/// Make the dropdown and the selected device agree
+///
+/// A DropDown shows its first entry even though nothing was chosen, which
+/// would leave the Write button enabled without a device behind it. The only
+/// device is selected for the user, saying so on `progress_label`; with
+/// several, they must pick one.
+fn sync_device_selection(
+    dropdown: &DropDown,
+    progress_label: &Label,
+    devices: &[crate::core::models::BlockDevice],
+    state: &Rc<RefCell<AppState>>,
+) {
+    let state_ref = state.borrow();
+    let selection = device_selection(
+        state_ref
+            .selected_device
+            .as_ref()
+            .map(|device| device.path.as_path()),
+        state_ref.is_working,
+        devices,
+        auto_select_enabled(),
+    );
+    drop(state_ref);
+
+    match selection {
+        DeviceSelection::Keep => {}
+        DeviceSelection::Select(position) => dropdown.set_selected(position),
+        DeviceSelection::AutoSelect => {
+            let path = devices[0].path.display().to_string();
+            eprintln!("INFO: Auto-selected only available device: {path} — verify this is correct");
+            progress_label.set_text(&i18n_f(
+                "Selected {}, the only device found · check that it is the right one",
+                &[&path],
+            ));
+            // Record it even though the dropdown already shows position 0
+            dropdown.set_selected(gtk4::INVALID_LIST_POSITION);
+            dropdown.set_selected(0);
+        }
+        DeviceSelection::Clear => dropdown.set_selected(gtk4::INVALID_LIST_POSITION),
+    }
+}
+
+/// Stack the SOURCE and TARGET sections when side by side no longer fits
+fn update_content_layout(window: &ApplicationWindow, content_box: &GtkBox) {
+    let width = match window.width() {
+        0 => window.default_width(),
+        width => width,
+    };
+    let (orientation, spacing) = if width < NARROW_LAYOUT_WIDTH {
+        (Orientation::Vertical, 16)
+    } else {
+        (Orientation::Horizontal, 32)
+    };
+
+    if content_box.orientation() != orientation {
+        content_box.set_orientation(orientation);
+        content_box.set_spacing(spacing);
+    }
+}
+
+/// Validate, inspect and select an ISO, from the file chooser or an open request
+fn select_iso(
+    window: &ApplicationWindow,
+    path: PathBuf,
+    source: &SourceWidgets,
+    state: &Rc<RefCell<AppState>>,
+) {
+    if let Err(e) = crate::core::safety::validate_iso_selection(&path) {
+        show_error_dialog(window, &describe_error(&e));
+        return;
+    }
+
+    let info = match crate::core::iso::inspect(&path) {
+        Ok(info) => {
+            if let Err(e) = crate::core::safety::ensure_recognized_image(&path, &info) {
+                show_error_dialog(window, &describe_error(&e));
+                return;
+            }
+            Some(info)
+        }
+        Err(e) => {
+            eprintln!("WARNING: Could not inspect {}: {e}", path.display());
+            None
+        }
+    };
+
+    // Names need not be UTF-8; show them lossily but keep the
+    // real path (an OsStr) for every file operation
+    let filename = path
+        .file_name()
+        .map_or_else(|| path.to_string_lossy(), |n| n.to_string_lossy());
+    source.iso_label.set_text(&filename);
+    source
+        .iso_label
+        .set_tooltip_text(Some(&path.to_string_lossy()));
+
+    match info {
+        Some(info) => {
+            source.iso_details.set_text(&describe_iso(&info));
+            source.iso_details_expander.set_visible(true);
+            source.iso_contents.set_text(&describe_contents(&info));
+            source.iso_contents_expander.set_visible(true);
+
+            if let Some(missing) = info.missing_bytes() {
+                show_truncation_warning(window, &info, missing);
+            } else if info.file_size < crate::core::safety::SMALL_IMAGE_BYTES {
+                show_small_image_warning(window, info.file_size);
+            } else if info.has_el_torito && !info.is_hybrid {
+                show_non_hybrid_warning(window, &info);
+            }
+        }
+        None => {
+            source.iso_details_expander.set_visible(false);
+            source.iso_contents_expander.set_visible(false);
+        }
+    }
+
+    state.borrow_mut().selected_iso = Some(path);
+    window.set_title(Some(&format_window_title(&TitleState::Idle)));
+
+    source
+        .write_button
+        .set_sensitive(state.borrow().can_write());
 }
 
 fn build_icon_button(label: &str, icon_name: &str, class_name: &str) -> Button {
     let button = Button::new();
     button.add_css_class(class_name);
+    set_icon_button_content(&button, label, icon_name);
+    button
+}
 
+fn set_icon_button_content(button: &Button, label: &str, icon_name: &str) {
     let content_box = GtkBox::new(Orientation::Horizontal, 8);
     content_box.set_halign(gtk4::Align::Center);
 
@@ -323,7 +880,6 @@ fn build_icon_button(label: &str, icon_name: &str, class_name: &str) -> Button {
     content_box.append(&text);
 
     button.set_child(Some(&content_box));
-    button
 }
 
 fn show_confirmation_dialog(
@@ -333,7 +889,24 @@ fn show_confirmation_dialog(
     state: Rc<RefCell<AppState>>,
     ui: UIComponents,
 ) {
-    let message = format!(
+    // Too large for a whole disk: the only option left is splitting it. A
+    // Ventoy stick keeps its own layout, so it is never split across.
+    let image_size = std::fs::metadata(&iso).map_or(0, |metadata| metadata.len());
+    if image_size > device.capacity_bytes && !device.is_partition && !device.is_ventoy {
+        show_spanned_confirmation_dialog(window, iso, device, image_size, state, ui);
+        return;
+    }
+
+    let mut message = String::new();
+    if device.is_partition {
+        message.push_str(&gettext(
+            "ADVANCED: PARTITION TARGET\n\n\
+             The image is written into this partition only. The disk's partition table \
+             is not changed, so the result will usually NOT boot. Use this only for \
+             recovery or firmware partitions you know the layout of.\n\n",
+        ));
+    }
+    message.push_str(&i18n_f(
         "TARGET DEVICE\n\n\
          Device: {}\n\
          Model: {} {}\n\
@@ -342,195 +915,1677 @@ fn show_confirmation_dialog(
          ALL DATA WILL BE PERMANENTLY ERASED\n\
          This action cannot be undone.\n\n\
          Continue?",
-        device.path.display(),
-        device.vendor,
-        device.model,
-        device.capacity_human()
-    );
-
+        &[
+            &device.path.display().to_string(),
+            &device.vendor,
+            &device.model,
+            &device.capacity_human(),
+        ],
+    ));
+
+    let erase_title = gettext("Confirm Destructive Operation");
+    let erase_label = gettext("ERASE & WRITE");
+
+    // Ventoy sticks get the image as a file; erasing them needs an explicit override
     let dialog = MessageDialog::new(
         Some(window),
         gtk4::DialogFlags::MODAL,
         MessageType::Warning,
         ButtonsType::None,
-        "Confirm Destructive Operation",
+        &erase_title,
     );
-
     dialog.set_secondary_text(Some(&message));
-    dialog.add_button("Cancel", ResponseType::Cancel);
-    dialog.add_button("ERASE & WRITE", ResponseType::Accept);
+    dialog.add_button(&gettext("Cancel"), ResponseType::Cancel);
+    let accept_button = dialog.add_button(&erase_label, ResponseType::Accept);
+
+    // Enter cancels; the confirm button unlocks after a countdown
+    dialog.set_default_response(ResponseType::Cancel);
+    let accept_text = Rc::new(RefCell::new(erase_label.clone()));
+    let locked_seconds = Rc::new(Cell::new(CONFIRM_DELAY_SECONDS));
+    update_confirm_button(&accept_button, &erase_label, CONFIRM_DELAY_SECONDS);
+
+    // Read out by screen readers when the dialog opens
+    dialog.update_property(&[gtk4::accessible::Property::Description(&i18n_f(
+        "Target device {}, {} {}, {}",
+        &[
+            &device.path.display().to_string(),
+            &device.vendor,
+            &device.model,
+            &device.capacity_human(),
+        ],
+    ))]);
+
+    let raw_write_check = gtk4::CheckButton::with_label(&gettext(
+        "Erase the stick and write the image directly (removes Ventoy)",
+    ));
+    raw_write_check.set_visible(device.is_ventoy);
+
+    // Optional pre-flight check of the source image
+    let checksum_entry = gtk4::Entry::builder()
+        .placeholder_text(gettext("Expected SHA-256 of the ISO (optional)"))
+        .build();
+    checksum_entry.add_css_class("checksum-entry");
+
+    let mode_labels: Vec<String> = VERIFY_MODES
+        .iter()
+        .map(|&mode| verify_mode_label(mode))
+        .collect();
+    let verify_dropdown =
+        DropDown::from_strings(&mode_labels.iter().map(String::as_str).collect::<Vec<_>>());
+    verify_dropdown.add_css_class("dropdown-compact");
+    let default_mode = default_verify_mode();
+    if let Some(index) = VERIFY_MODES.iter().position(|&mode| mode == default_mode) {
+        verify_dropdown.set_selected(u32::try_from(index).unwrap_or_default());
+    }
+
+    let background_check =
+        gtk4::CheckButton::with_label(&gettext("Background write (lower priority)"));
+    background_check.set_tooltip_text(Some(&gettext(
+        "Keeps the desktop responsive on slower machines, at some cost in speed",
+    )));
+    background_check.set_active(background_write_default());
+
+    // A partition has no table of its own to clear
+    let clear_tables_check =
+        gtk4::CheckButton::with_label(&gettext("Clear old partition table first"));
+    clear_tables_check.set_tooltip_text(Some(&gettext(
+        "Zeroes the start and end of the device, so a leftover backup GPT cannot show up as phantom partitions",
+    )));
+    clear_tables_check.set_active(true);
+    clear_tables_check.set_visible(!device.is_partition);
+
+    let used_space_check =
+        gtk4::CheckButton::with_label(&gettext("Write only used space (experimental)"));
+    used_space_check.set_tooltip_text(Some(&gettext(
+        "For disk images with ext4 filesystems, skips blocks the filesystems do not use; other images are written in full",
+    )));
+    used_space_check.set_visible(used_space_writes_enabled());
+
+    if let Ok(message_area) = dialog.message_area().downcast::<GtkBox>() {
+        message_area.append(&checksum_entry);
+        message_area.append(&verify_dropdown);
+        message_area.append(&background_check);
+        message_area.append(&clear_tables_check);
+        message_area.append(&used_space_check);
+        message_area.append(&raw_write_check);
+    }
+
+    if device.is_ventoy {
+        let ventoy_title = gettext("Add to Ventoy Stick");
+        let ventoy_label = gettext("ADD TO VENTOY");
+        let ventoy_message = i18n_f(
+            "VENTOY STICK\n\n\
+             Device: {}\n\
+             Model: {} {}\n\
+             Capacity: {}\n\n\
+             {} will be copied onto the stick and added to its Ventoy boot menu. \
+             Existing images and files are preserved.\n\n\
+             Continue?",
+            &[
+                &device.path.display().to_string(),
+                &device.vendor,
+                &device.model,
+                &device.capacity_human(),
+                &iso.file_name()
+                    .map_or_else(|| iso.to_string_lossy(), |n| n.to_string_lossy()),
+            ],
+        );
+
+        // The copy is always read back in full, and leaves the partition table alone
+        let show_mode = {
+            let dialog = dialog.clone();
+            let accept_button = accept_button.clone();
+            let accept_text = accept_text.clone();
+            let locked_seconds = locked_seconds.clone();
+            let verify_dropdown = verify_dropdown.clone();
+            let clear_tables_check = clear_tables_check.clone();
+            let used_space_check = used_space_check.clone();
+            move |raw_write: bool| {
+                let (title, text, label) = if raw_write {
+                    (&erase_title, &message, &erase_label)
+                } else {
+                    (&ventoy_title, &ventoy_message, &ventoy_label)
+                };
+                dialog.set_text(Some(title));
+                dialog.set_secondary_text(Some(text));
+                accept_text.replace(label.clone());
+                update_confirm_button(&accept_button, label, locked_seconds.get());
+                verify_dropdown.set_visible(raw_write);
+                clear_tables_check.set_visible(raw_write);
+                used_space_check.set_visible(raw_write && used_space_writes_enabled());
+            }
+        };
+        show_mode(false);
+        raw_write_check.connect_toggled(move |check| show_mode(check.is_active()));
+    }
 
     dialog.connect_response(move |dialog, response| {
         if response == ResponseType::Accept {
-            // Validate device before starting
-            if let Err(e) = crate::io::devices::validate_device(&device.path) {
-                show_error_dialog(dialog, &format!("Cannot write to device:\n\n{e}"));
-                dialog.close();
+            let expected_sha256 = checksum_entry.text().trim().to_string();
+            let expected_sha256 = if expected_sha256.is_empty() {
+                None
+            } else if crate::core::verification::is_sha256_hex(&expected_sha256) {
+                Some(expected_sha256)
+            } else {
+                show_error_dialog(
+                    dialog,
+                    &gettext(
+                        "The expected checksum must be a 64-character hexadecimal SHA-256 digest.",
+                    ),
+                );
                 return;
-            }
+            };
+
+            // Validate device before starting, then claim it for this process.
+            // Adding to Ventoy only needs the (possibly mounted) data partition.
+            let add_to_ventoy = device.is_ventoy && !raw_write_check.is_active();
+            let checks = crate::core::safety::validate_iso_selection(&iso)
+                .and_then(|()| {
+                    if add_to_ventoy {
+                        return Ok(());
+                    }
+                    crate::core::safety::ensure_not_hosting(&iso, &device.path)
+                        .and_then(|()| crate::io::devices::validate_device(&device.path))
+                        .and_then(|()| crate::core::safety::ensure_fits(&iso, &device.path))
+                })
+                .and_then(|()| DeviceLock::acquire(&device.path));
+            let lock = match checks {
+                Ok(lock) => lock,
+                Err(e) => {
+                    show_error_dialog(
+                        dialog,
+                        &i18n_f("Cannot write to device:\n\n{}", &[&describe_error(&e)]),
+                    );
+                    dialog.close();
+                    return;
+                }
+            };
+
+            let verify_mode = VERIFY_MODES
+                .get(verify_dropdown.selected() as usize)
+                .copied()
+                .unwrap_or(VerifyMode::Full);
 
             state.borrow_mut().is_working = true;
             ui.write_button.set_sensitive(false);
             ui.iso_button.set_sensitive(false);
             ui.device_dropdown.set_sensitive(false);
-            
+            ui.identify_button.set_sensitive(false);
+
             // Activate status dot
             ui.status_dot.remove_css_class("idle");
             ui.status_dot.add_css_class("active");
 
-            start_write_operation(iso.clone(), device.clone(), state.clone(), ui.clone());
+            let options = WriteOptions {
+                expected_sha256,
+                verify_mode,
+                background: background_check.is_active(),
+                clear_tables: !device.is_partition && clear_tables_check.is_active(),
+                used_space_only: used_space_check.is_visible() && used_space_check.is_active(),
+                add_to_ventoy,
+            };
+            start_write_operation(
+                iso.clone(),
+                device.clone(),
+                options,
+                lock,
+                state.clone(),
+                ui.clone(),
+            );
         }
         dialog.close();
     });
 
     dialog.show();
-}
+    if let Some(cancel_button) = dialog.widget_for_response(ResponseType::Cancel) {
+        cancel_button.grab_focus();
+    }
 
-#[allow(clippy::too_many_lines)] // Worker thread coordination requires comprehensive error handling
-fn start_write_operation(
-    iso: PathBuf,
+    glib::spawn_future_local(async move {
+        while locked_seconds.get() > 0 {
+            glib::timeout_future_seconds(1).await;
+            locked_seconds.set(locked_seconds.get() - 1);
+            update_confirm_button(&accept_button, &accept_text.borrow(), locked_seconds.get());
+        }
+    });
+}
+
+/// Show `text` on the confirm button, with the seconds left while it is locked
+fn update_confirm_button(button: &gtk4::Widget, text: &str, locked_seconds: u32) {
+    let Some(button) = button.downcast_ref::<Button>() else {
+        return;
+    };
+    if locked_seconds == 0 {
+        button.set_label(text);
+    } else {
+        button.set_label(&format!("{text} ({locked_seconds})"));
+    }
+    button.set_sensitive(locked_seconds == 0);
+}
+
+/// Verification choices in the confirmation dialog, in display order
+const VERIFY_MODES: [VerifyMode; 3] = [VerifyMode::Full, VerifyMode::Quick, VerifyMode::Skip];
+
+fn verify_mode_label(mode: VerifyMode) -> String {
+    match mode {
+        VerifyMode::Full => gettext("Full verification"),
+        VerifyMode::Quick => gettext("Quick verification (sampled regions)"),
+        VerifyMode::Skip => gettext("Skip verification"),
+    }
+}
+
+fn start_write_operation(
+    iso: PathBuf,
     device: crate::core::models::BlockDevice,
+    options: WriteOptions,
+    lock: DeviceLock,
     state: Rc<RefCell<AppState>>,
     ui: UIComponents,
 ) {
     let (tx, rx) = mpsc::channel();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let pause = Arc::new(AtomicBool::new(false));
+    state.borrow_mut().pause_requested = pause.clone();
+    state.borrow_mut().cancel_requested = cancel.clone();
+
+    let oplog = match OperationLog::create(&device.path) {
+        Ok(oplog) => Some(oplog),
+        Err(e) => {
+            eprintln!("WARNING: Continuing without an operation log: {e:#}");
+            None
+        }
+    };
+    state.borrow_mut().operation_log = oplog.as_ref().map(|oplog| oplog.path().to_path_buf());
+
+    ui.throughput.clear();
+    ui.log_button.set_visible(false);
+    ui.boot_test_button.set_visible(false);
+    if let Some(bus) = &ui.progress_bus {
+        bus.start(&device.path);
+    }
+    start_temperature_monitor(&device.path, &state, &ui);
+    let target_path = device.path.clone();
+
+    let job = WriteJob {
+        iso,
+        device,
+        options,
+        lock,
+        cancel: cancel.clone(),
+        pause,
+        oplog,
+    };
+    thread::spawn(move || run_write(job, &tx));
+    watch_write_worker(rx, WriteSession::new(target_path), cancel, state, ui);
+}
+
+/// Drive `session` from the write worker's messages until it finishes
+///
+/// Also watches for a stalled worker and for a pause left on too long.
+fn watch_write_worker(
+    rx: mpsc::Receiver<WorkMessage>,
+    mut session: WriteSession,
+    cancel: Arc<AtomicBool>,
+    state: Rc<RefCell<AppState>>,
+    ui: UIComponents,
+) {
+    glib::spawn_future_local(async move {
+        let mut title_updated: Option<Instant> = None;
+        let mut stall_dialog: Option<MessageDialog> = None;
+        let stall_timeout = stall_timeout();
+        let last_activity = Rc::new(Cell::new(Instant::now()));
+        let watchdog = Rc::new(Cell::new(Watchdog::Watching));
+        let mut paused_since: Option<Instant> = None;
+        let mut long_pause_warned = false;
+
+        while !session.is_finished() {
+            let event = match rx.try_recv() {
+                Ok(message) => {
+                    last_activity.set(Instant::now());
+                    WriteEvent::Worker(message)
+                }
+                Err(mpsc::TryRecvError::Empty) => match watchdog.get() {
+                    Watchdog::AbortRequested => {
+                        // Aborting keeps the watchdog quiet until the worker exits
+                        watchdog.set(Watchdog::Watching);
+                        WriteEvent::StallAbort {
+                            timeout: stall_timeout,
+                        }
+                    }
+                    // A paused write is idle on purpose
+                    // So is the final flush, which reports nothing until it ends
+                    Watchdog::Watching
+                        if !session.expects_silence()
+                            && last_activity.get().elapsed() >= stall_timeout =>
+                    {
+                        watchdog.set(Watchdog::Prompting);
+                        stall_dialog = Some(show_stall_dialog(
+                            &ui.window,
+                            stall_timeout,
+                            watchdog.clone(),
+                            last_activity.clone(),
+                        ));
+                        continue;
+                    }
+                    _ => {
+                        if !long_pause_warned
+                            && paused_since
+                                .is_some_and(|since| since.elapsed() >= LONG_PAUSE_WARNING)
+                        {
+                            long_pause_warned = true;
+                            show_long_pause_warning(&ui.window);
+                        }
+                        glib::timeout_future(MESSAGE_POLL_INTERVAL).await;
+                        continue;
+                    }
+                },
+                // Channel closed, worker thread finished
+                Err(mpsc::TryRecvError::Disconnected) => WriteEvent::WorkerGone,
+            };
+
+            let (next, commands) = reduce(session, event);
+            session = next;
+            if session.phase != Phase::Paused {
+                paused_since = None;
+            } else if paused_since.is_none() {
+                paused_since = Some(Instant::now());
+                long_pause_warned = false;
+            }
+            for command in commands {
+                apply_write_command(&ui, &state, &cancel, &session, &mut title_updated, command);
+            }
+        }
+
+        if let Some(dialog) = stall_dialog {
+            dialog.close();
+        }
+    });
+}
+
+/// Apply one widget change requested by `reduce`
+fn apply_write_command(
+    ui: &UIComponents,
+    state: &Rc<RefCell<AppState>>,
+    cancel: &AtomicBool,
+    session: &WriteSession,
+    title_updated: &mut Option<Instant>,
+    command: Command,
+) {
+    match command {
+        Command::Progress {
+            label,
+            title,
+            bus_phase,
+            bytes,
+            total,
+            bps,
+        } => {
+            show_progress(ui, &label, bytes, total, bps);
+            publish_progress(ui, bus_phase, bytes, total, bps);
+            ui.throughput.record(bytes);
+            update_progress_title(ui, title_updated, &title, bytes, total, bps);
+        }
+        Command::ClearThroughput => ui.throughput.clear(),
+        Command::Status(text) => ui.progress_label.set_text(&text),
+        Command::Detail(text) => ui.speed_label.set_text(&text),
+        Command::Pause(button) => {
+            match button {
+                PauseButton::Pause => set_icon_button_content(
+                    &ui.pause_button,
+                    &gettext("Pause"),
+                    "media-playback-pause-symbolic",
+                ),
+                PauseButton::Resume => set_icon_button_content(
+                    &ui.pause_button,
+                    &gettext("Resume"),
+                    "media-playback-start-symbolic",
+                ),
+                PauseButton::Disabled | PauseButton::Hidden => {}
+            }
+            ui.pause_button
+                .set_sensitive(matches!(button, PauseButton::Pause | PauseButton::Resume));
+            ui.pause_button.set_visible(button != PauseButton::Hidden);
+        }
+        Command::ResetProgressBar => ui.progress_bar.set_fraction(0.0),
+        Command::HookWarning(error) => {
+            ui.log_button
+                .set_visible(state.borrow().operation_log.is_some());
+            show_hook_warning(&ui.window, &error);
+        }
+        Command::VerifyDiff(diff) => show_verify_diff_dialog(&ui.window, &diff),
+        Command::Cancel => cancel.store(true, Ordering::Relaxed),
+        Command::Succeeded { detail } => {
+            ui.window
+                .set_title(Some(&format_window_title(&TitleState::Done)));
+            ui.progress_bar.set_fraction(1.0);
+            ui.progress_bar.set_text(Some("100%"));
+            ui.progress_label.set_text(&gettext("Complete"));
+            ui.progress_label.add_css_class("success-text");
+            ui.speed_label.set_text(&detail);
+
+            // Success status dot
+            ui.status_dot.remove_css_class("active");
+            ui.status_dot.add_css_class("success");
+
+            let mut state_mut = state.borrow_mut();
+            state_mut.is_working = false;
+            state_mut.last_target = Some(session.target.clone());
+            ui.boot_test_button.set_visible(state_mut.qemu.is_some());
+            drop(state_mut);
+            ui.write_button.set_sensitive(true);
+            ui.iso_button.set_sensitive(true);
+            ui.device_dropdown.set_sensitive(true);
+            ui.identify_button.set_sensitive(true);
+            finish_operation(ui, state, true);
+        }
+        // Only a spanned operation asks, and its message loop owns the reply channel
+        Command::RequestDevice { .. } => {}
+        Command::SpanSucceeded(summary) => {
+            ui.window
+                .set_title(Some(&format_window_title(&TitleState::Done)));
+            ui.progress_bar.set_fraction(1.0);
+            ui.progress_bar.set_text(Some("100%"));
+            ui.progress_label.set_text(&summary);
+            ui.progress_label.add_css_class("success-text");
+            ui.speed_label.set_text("");
+            ui.status_dot.remove_css_class("active");
+            ui.status_dot.add_css_class("success");
+
+            state.borrow_mut().is_working = false;
+            ui.write_button
+                .set_sensitive(state.borrow().selected_iso.is_some());
+            ui.iso_button.set_sensitive(true);
+            ui.device_dropdown.set_sensitive(true);
+            ui.identify_button.set_sensitive(true);
+            finish_operation(ui, state, true);
+        }
+        Command::Failed(error) => show_failure(ui, state, &error),
+    }
+}
+
+/// Show the target's temperature every `TEMPERATURE_INTERVAL` while this write runs
+///
+/// Does nothing for devices without a sensor. Readings also go to the
+/// operation log, so a throughput drop can be matched against heat later.
+fn start_temperature_monitor(
+    device: &std::path::Path,
+    state: &Rc<RefCell<AppState>>,
+    ui: &UIComponents,
+) {
+    const TEMPERATURE_INTERVAL: Duration = Duration::from_secs(30);
+
+    ui.temperature_label.set_visible(false);
+    let Some(sensor) = crate::io::devices::temperature_sensor(device) else {
+        return;
+    };
+    // A later operation gets a new cancel flag; stop when this one's is replaced
+    let operation = state.borrow().cancel_requested.clone();
+    let state = state.clone();
+    let label = ui.temperature_label.clone();
+
+    glib::spawn_future_local(async move {
+        let is_current = |state: &Rc<RefCell<AppState>>| {
+            let state = state.borrow();
+            state.is_working && Arc::ptr_eq(&state.cancel_requested, &operation)
+        };
+
+        while is_current(&state) {
+            // drivetemp may have to ask the drive, so keep the read off the UI thread
+            let path = sensor.clone();
+            let reading =
+                gtk4::gio::spawn_blocking(move || crate::io::devices::read_temperature(&path))
+                    .await
+                    .ok()
+                    .flatten();
+            if !is_current(&state) {
+                break;
+            }
+
+            match reading {
+                Some(celsius) => {
+                    label.set_text(&format!("{celsius} °C"));
+                    label.set_visible(true);
+                    if let Some(log) = &state.borrow().operation_log {
+                        if let Ok(oplog) = OperationLog::append_to(log) {
+                            oplog.log(format!("temperature: {celsius} °C"));
+                        }
+                    }
+                }
+                None => label.set_visible(false),
+            }
+            glib::timeout_future(TEMPERATURE_INTERVAL).await;
+        }
+
+        // A newer operation owns the label now
+        if !state.borrow().is_working {
+            label.set_visible(false);
+        }
+    });
+}
 
-    // Spawn worker thread
+/// Blink the selected device's LED, showing what to look for meanwhile
+fn start_identify(
+    button: &Button,
+    window: &ApplicationWindow,
+    progress_label: &Label,
+    state: &Rc<RefCell<AppState>>,
+) {
+    let state_ref = state.borrow();
+    if state_ref.is_working {
+        return;
+    }
+    let Some(device) = state_ref.selected_device.clone() else {
+        return;
+    };
+    drop(state_ref);
+
+    button.set_sensitive(false);
+    let previous = progress_label.text();
+    progress_label.set_text(&i18n_f(
+        "Watch your device's LED · reading {} for {}s",
+        &[
+            &device.path.display().to_string(),
+            &crate::io::devices::IDENTIFY_DURATION.as_secs().to_string(),
+        ],
+    ));
+
+    let (tx, rx) = mpsc::channel();
     thread::spawn(move || {
-        // Write phase
-        let tx_clone = tx.clone();
-        let write_result =
-            crate::io::writer::write_iso(&iso, &device.path, move |bytes, total, bps| {
-                // Channel send errors are not critical during progress updates
-                // If channel is closed, UI thread has terminated
-                let _ = tx_clone.send(WorkMessage::WriteProgress(bytes, total, bps));
-            });
+        let result = crate::io::devices::identify(&device);
+        let _ = tx.send(result.map_err(|e| format!("{e:#}")));
+    });
 
-        if let Err(e) = write_result {
-            // Error notification is critical - if this fails, log to stderr
-            if tx
-                .send(WorkMessage::Error(format!("Write failed: {e}")))
-                .is_err()
-            {
-                eprintln!("CRITICAL: Write failed but UI channel closed: {e}");
+    let button = button.clone();
+    let window = window.clone();
+    let progress_label = progress_label.clone();
+    let state = state.clone();
+    glib::spawn_future_local(async move {
+        let result = loop {
+            match rx.try_recv() {
+                Ok(result) => break result,
+                Err(mpsc::TryRecvError::Empty) => {
+                    glib::timeout_future(MESSAGE_POLL_INTERVAL).await;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    break Err(gettext("Identification ended unexpectedly"))
+                }
+            }
+        };
+
+        // A write may have started meanwhile; it owns the label and controls then
+        let is_working = state.borrow().is_working;
+        if !is_working {
+            progress_label.set_text(&previous);
+        }
+        button.set_sensitive(!is_working);
+        if let Err(e) = result {
+            show_error_dialog(&window, &e);
+        }
+    });
+}
+
+/// Ask where to save an image of the selected device, then start reading it
+fn show_create_image_dialog(state: &Rc<RefCell<AppState>>, ui: &UIComponents) {
+    let state_ref = state.borrow();
+    if state_ref.is_working {
+        return;
+    }
+    let Some(device) = state_ref.selected_device.clone() else {
+        show_error_dialog(
+            &ui.window,
+            &gettext("Select a target device to read first."),
+        );
+        return;
+    };
+    drop(state_ref);
+
+    let dialog = FileChooserDialog::new(
+        Some(&gettext("Save Device Image")),
+        Some(&ui.window),
+        FileChooserAction::Save,
+        &[
+            (&gettext("Cancel"), ResponseType::Cancel),
+            (&gettext("Save"), ResponseType::Accept),
+        ],
+    );
+    let device_name = device
+        .path
+        .file_name()
+        .map_or_else(|| "device".into(), |name| name.to_string_lossy());
+    dialog.set_current_name(&format!("{device_name}.img"));
+    dialog.add_choice("trim", gettext("Leave out empty space at the end"), &[]);
+    dialog.set_choice("trim", "true");
+
+    let state = state.clone();
+    let ui = ui.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            if let Some(output) = dialog.file().and_then(|file| file.path()) {
+                let trim = dialog.choice("trim").is_some_and(|value| value == "true");
+                match DeviceLock::acquire(&device.path) {
+                    Ok(lock) => {
+                        start_image_operation(device.clone(), output, trim, lock, &state, &ui);
+                    }
+                    Err(e) => show_error_dialog(&ui.window, &describe_error(&e)),
+                }
             }
-            return;
         }
+        dialog.close();
+    });
 
-        if tx.send(WorkMessage::WriteComplete).is_err() {
-            eprintln!("WARNING: Write completed but UI channel closed");
-            return;
+    dialog.show();
+}
+
+/// Read a device into an image file, reporting through the write progress widgets
+fn start_image_operation(
+    device: crate::core::models::BlockDevice,
+    output: PathBuf,
+    trim: bool,
+    lock: DeviceLock,
+    state: &Rc<RefCell<AppState>>,
+    ui: &UIComponents,
+) {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let worker_cancel = cancel.clone();
+    let mut state_mut = state.borrow_mut();
+    state_mut.is_working = true;
+    state_mut.cancel_requested = cancel;
+    let oplog = match OperationLog::create(&device.path) {
+        Ok(oplog) => Some(oplog),
+        Err(e) => {
+            eprintln!("WARNING: Continuing without an operation log: {e:#}");
+            None
         }
+    };
+    state_mut.operation_log = oplog.as_ref().map(|oplog| oplog.path().to_path_buf());
+    drop(state_mut);
+    if let Some(bus) = &ui.progress_bus {
+        bus.start(&device.path);
+    }
 
-        // Verification phase
-        let tx_clone = tx.clone();
-        let verify_result = crate::core::verification::verify_write(
-            &iso,
+    ui.write_button.set_sensitive(false);
+    ui.iso_button.set_sensitive(false);
+    ui.device_dropdown.set_sensitive(false);
+    ui.identify_button.set_sensitive(false);
+    ui.log_button.set_visible(false);
+    ui.boot_test_button.set_visible(false);
+    ui.progress_label.remove_css_class("success-text");
+    ui.progress_label.remove_css_class("error-text");
+    ui.throughput.clear();
+    ui.status_dot.remove_css_class("idle");
+    ui.status_dot.add_css_class("active");
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        // Held until the worker finishes, however it exits
+        let _lock = lock;
+        let log = |line: &str| {
+            if let Some(oplog) = &oplog {
+                oplog.log(line);
+            }
+        };
+        log(&format!(
+            "image: reading {} ({} bytes) into {}, trim {trim}",
+            device.path.display(),
+            device.capacity_bytes,
+            output.display()
+        ));
+
+        let progress_tx = tx.clone();
+        let result = crate::io::reader::read_device(
             &device.path,
+            &output,
+            trim,
+            &worker_cancel,
             move |bytes, total, bps| {
-                let _ = tx_clone.send(WorkMessage::VerifyProgress(bytes, total, bps));
+                let _ = progress_tx.send(ImageMessage::Progress(bytes, total, bps));
             },
         );
 
-        if let Err(e) = verify_result {
-            if tx
-                .send(WorkMessage::Error(format!("Verification failed: {e}")))
-                .is_err()
-            {
-                eprintln!("CRITICAL: Verification failed but UI channel closed: {e}");
+        let message = match result {
+            Ok(image) => {
+                log(&format!(
+                    "image: complete, {} bytes, SHA-256 {}",
+                    image.image_size, image.sha256
+                ));
+                ImageMessage::Complete(image)
             }
-            return;
-        }
-
-        if tx.send(WorkMessage::VerifyComplete).is_err() {
-            eprintln!("WARNING: Verification completed but UI channel closed");
+            Err(e) => {
+                if let Some(oplog) = &oplog {
+                    oplog.error(format_args!("image failed: {e:#}"));
+                }
+                ImageMessage::Error(i18n_f(
+                    "Reading the device failed: {}",
+                    &[&describe_error(&e)],
+                ))
+            }
+        };
+        if tx.send(message).is_err() {
+            eprintln!("WARNING: Image creation finished but UI channel closed");
         }
     });
 
-    // Handle messages from worker thread
+    let state = state.clone();
+    let ui = ui.clone();
     glib::spawn_future_local(async move {
+        let mut title_updated: Option<Instant> = None;
         loop {
-            match rx.recv() {
-                Ok(WorkMessage::WriteProgress(bytes, total, bps)) => {
-                    #[allow(clippy::cast_precision_loss)] // Acceptable for UI display
-                    let fraction = bytes as f64 / total as f64;
-                    ui.progress_bar.set_fraction(fraction);
-                    ui.progress_bar
-                        .set_text(Some(&format!("{:.0}%", fraction * 100.0)));
-                    ui.progress_label.set_text("Writing...");
-
-                    #[allow(clippy::cast_precision_loss)] // Acceptable for UI display
-                    let mb_per_sec = bps as f64 / 1_000_000.0;
-                    #[allow(clippy::cast_precision_loss)]
-                    let mb_written = bytes as f64 / 1_000_000.0;
-                    #[allow(clippy::cast_precision_loss)]
-                    let mb_total = total as f64 / 1_000_000.0;
-                    ui.speed_label.set_text(&format!(
-                        "{mb_written:.0}/{mb_total:.0} MB · {mb_per_sec:.1} MB/s"
-                    ));
+            let message = match rx.try_recv() {
+                Ok(message) => message,
+                Err(mpsc::TryRecvError::Empty) => {
+                    glib::timeout_future(MESSAGE_POLL_INTERVAL).await;
+                    continue;
                 }
-                Ok(WorkMessage::WriteComplete) => {
-                    ui.progress_label.set_text("Verifying");
-                    ui.progress_bar.set_fraction(0.0);
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    ImageMessage::Error(gettext("Image creation ended unexpectedly"))
                 }
-                Ok(WorkMessage::VerifyProgress(bytes, total, bps)) => {
-                    #[allow(clippy::cast_precision_loss)] // Acceptable for UI display
-                    let fraction = bytes as f64 / total as f64;
-                    ui.progress_bar.set_fraction(fraction);
-                    ui.progress_bar
-                        .set_text(Some(&format!("{:.0}%", fraction * 100.0)));
-                    ui.progress_label.set_text("Verifying");
-
-                    #[allow(clippy::cast_precision_loss)] // Acceptable for UI display
-                    let mb_per_sec = bps as f64 / 1_000_000.0;
-                    #[allow(clippy::cast_precision_loss)]
-                    let mb_verified = bytes as f64 / 1_000_000.0;
-                    #[allow(clippy::cast_precision_loss)]
-                    let mb_total = total as f64 / 1_000_000.0;
-                    ui.speed_label.set_text(&format!(
-                        "{mb_verified:.0}/{mb_total:.0} MB · {mb_per_sec:.1} MB/s"
-                    ));
+            };
+
+            match message {
+                ImageMessage::Progress(bytes, total, bps) => {
+                    show_progress(&ui, &gettext("Reading..."), bytes, total, bps);
+                    publish_progress(&ui, "reading", bytes, total, bps);
+                    ui.throughput.record(bytes);
+                    update_progress_title(
+                        &ui,
+                        &mut title_updated,
+                        &gettext("Reading"),
+                        bytes,
+                        total,
+                        bps,
+                    );
                 }
-                Ok(WorkMessage::VerifyComplete) => {
+                ImageMessage::Complete(image) => {
+                    ui.window
+                        .set_title(Some(&format_window_title(&TitleState::Done)));
                     ui.progress_bar.set_fraction(1.0);
                     ui.progress_bar.set_text(Some("100%"));
-                    ui.progress_label.set_text("Complete");
+                    ui.progress_label
+                        .set_text(&i18n_f("Image saved · SHA-256 {}", &[&image.sha256]));
                     ui.progress_label.add_css_class("success-text");
                     ui.speed_label.set_text("");
-                    
-                    // Success status dot
                     ui.status_dot.remove_css_class("active");
                     ui.status_dot.add_css_class("success");
 
                     state.borrow_mut().is_working = false;
-                    ui.write_button.set_sensitive(true);
+                    ui.write_button
+                        .set_sensitive(state.borrow().selected_iso.is_some());
                     ui.iso_button.set_sensitive(true);
                     ui.device_dropdown.set_sensitive(true);
+                    ui.identify_button.set_sensitive(true);
+                    finish_operation(&ui, &state, true);
                     break;
                 }
-                Ok(WorkMessage::Error(err)) => {
-                    ui.progress_label.set_text(&format!("Error: {err}"));
-                    ui.progress_label.add_css_class("error-text");
-                    ui.progress_bar.set_fraction(0.0);
-                    ui.speed_label.set_text("");
-                    
-                    // Error status - back to idle
-                    ui.status_dot.remove_css_class("active");
-                    ui.status_dot.add_css_class("idle");
-
-                    state.borrow_mut().is_working = false;
-                    ui.write_button.set_sensitive(true);
-                    ui.iso_button.set_sensitive(true);
-                    ui.device_dropdown.set_sensitive(true);
+                ImageMessage::Error(err) => {
+                    show_failure(&ui, &state, &err);
                     break;
                 }
-                Err(_) => {
-                    // Channel closed, worker thread finished
-                    break;
+            }
+        }
+    });
+}
+
+/// Boot the freshly written device in QEMU and report what happened
+fn start_boot_test(button: &Button, progress_label: &Label, state: &Rc<RefCell<AppState>>) {
+    let state_ref = state.borrow();
+    let (Some(qemu), Some(device)) = (state_ref.qemu.clone(), state_ref.last_target.clone()) else {
+        return;
+    };
+    let log_path = state_ref.operation_log.clone();
+
+    // Prefer UEFI when the image supports it and firmware is installed
+    let wants_uefi = state_ref
+        .selected_iso
+        .as_deref()
+        .and_then(|iso| crate::core::iso::inspect(iso).ok())
+        .is_some_and(|info| info.has_efi);
+    drop(state_ref);
+    let firmware = wants_uefi
+        .then(crate::io::qemu::find_uefi_firmware)
+        .flatten();
+
+    button.set_sensitive(false);
+    progress_label.remove_css_class("success-text");
+    progress_label.set_text(&i18n_f(
+        "Test boot running · QEMU closes after {}s",
+        &[&crate::io::qemu::BOOT_TEST_TIMEOUT.as_secs().to_string()],
+    ));
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let result = crate::io::qemu::test_boot(&qemu, &device, firmware.as_deref());
+
+        let oplog = log_path.and_then(|path| OperationLog::append_to(&path).ok());
+        if let Some(oplog) = &oplog {
+            match &result {
+                Ok(result) => oplog.log(format_args!(
+                    "test boot: {}, exit {:?}, bootloader seen: {}",
+                    if result.used_uefi { "UEFI" } else { "BIOS" },
+                    result.exit_status,
+                    result.bootloader_seen.unwrap_or("none")
+                )),
+                Err(e) => oplog.error(format_args!("test boot failed: {e:#}")),
+            }
+        }
+
+        let _ = tx.send(result.map_err(|e| format!("{e:#}")));
+    });
+
+    let button = button.clone();
+    let progress_label = progress_label.clone();
+    glib::spawn_future_local(async move {
+        let result = loop {
+            match rx.try_recv() {
+                Ok(result) => break result,
+                Err(mpsc::TryRecvError::Empty) => {
+                    glib::timeout_future(MESSAGE_POLL_INTERVAL).await;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    break Err(gettext("Test boot ended unexpectedly"))
+                }
+            }
+        };
+
+        let summary = match result {
+            Ok(result) => match result.bootloader_seen {
+                Some(bootloader) => i18n_f("Test boot reached {}", &[bootloader]),
+                None => gettext(
+                    "Test boot finished · no bootloader output on serial (normal for graphical menus)",
+                ),
+            },
+            Err(e) => i18n_f("Test boot failed: {}", &[&e]),
+        };
+        progress_label.set_text(&summary);
+        button.set_sensitive(true);
+    });
+}
+
+/// Reset the UI after an operation ended without success
+fn show_failure(ui: &UIComponents, state: &Rc<RefCell<AppState>>, err: &str) {
+    ui.window
+        .set_title(Some(&format_window_title(&TitleState::Error)));
+    ui.progress_label.set_text(&i18n_f("Error: {}", &[err]));
+    ui.progress_label.add_css_class("error-text");
+    ui.progress_bar.set_fraction(0.0);
+    ui.speed_label.set_text("");
+    ui.pause_button.set_visible(false);
+    ui.log_button
+        .set_visible(state.borrow().operation_log.is_some());
+    ui.boot_test_button.set_visible(false);
+
+    // Error status - back to idle
+    ui.status_dot.remove_css_class("active");
+    ui.status_dot.add_css_class("idle");
+
+    state.borrow_mut().is_working = false;
+    ui.write_button.set_sensitive(true);
+    ui.iso_button.set_sensitive(true);
+    ui.device_dropdown.set_sensitive(true);
+    ui.identify_button.set_sensitive(true);
+
+    finish_operation(ui, state, false);
+}
+
+/// Ask what to do when the window is closed while an operation runs
+fn show_close_during_write_dialog(window: &ApplicationWindow, state: &Rc<RefCell<AppState>>) {
+    const CANCEL_AND_QUIT: ResponseType = ResponseType::Other(1);
+
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::None,
+        gettext("A write is in progress"),
+    );
+    dialog.set_secondary_text(Some(&gettext(
+        "Quitting now would leave the device half-written. Etch can finish in the \
+         background and quit when done (launch it again to bring the window back), \
+         or stop the write cleanly and quit.",
+    )));
+    dialog.add_button(&gettext("Stay"), ResponseType::Cancel);
+    dialog.add_button(&gettext("Cancel Write and Quit"), CANCEL_AND_QUIT);
+    dialog.add_button(&gettext("Keep Writing in Background"), ResponseType::Accept);
+    dialog.set_default_response(ResponseType::Cancel);
+
+    let window = window.clone();
+    let state = state.clone();
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+
+        // The operation may have finished while the dialog was open
+        if !state.borrow().is_working {
+            window.destroy();
+            return;
+        }
+
+        match response {
+            ResponseType::Accept => {}
+            CANCEL_AND_QUIT => state
+                .borrow()
+                .cancel_requested
+                .store(true, Ordering::Relaxed),
+            _ => return,
+        }
+
+        // The worker keeps the process alive; the window goes once it is done
+        state.borrow_mut().quit_when_idle = true;
+        window.set_visible(false);
+    });
+
+    dialog.show();
+}
+
+/// Stop a running operation and quit once it has flushed, or quit right away
+fn handle_termination_signal(
+    window: &ApplicationWindow,
+    state: &Rc<RefCell<AppState>>,
+    signal: i32,
+) {
+    let mut state_mut = state.borrow_mut();
+    if state_mut.is_working {
+        eprintln!("INFO: Received signal {signal}, stopping at the next chunk boundary");
+        state_mut.cancel_requested.store(true, Ordering::Relaxed);
+        state_mut.quit_when_idle = true;
+    } else {
+        drop(state_mut);
+        window.destroy();
+    }
+}
+
+/// Common end of every operation, whether it succeeded, failed or was cancelled
+///
+/// Publishes the outcome on D-Bus, then quits if the operation outlived a
+/// closed window and otherwise plays the configured alerts.
+fn finish_operation(ui: &UIComponents, state: &Rc<RefCell<AppState>>, succeeded: bool) {
+    if let Some(bus) = &ui.progress_bus {
+        bus.finish(succeeded);
+    }
+
+    if state.borrow().quit_when_idle {
+        ui.window.destroy();
+        return;
+    }
+
+    if completion_sound_enabled() {
+        play_theme_sound(if succeeded {
+            "complete"
+        } else {
+            "dialog-error"
+        });
+    }
+    // GTK 4 has no urgency hint; presenting an inactive window makes the
+    // compositor flag it ("Etch is ready") without stealing focus
+    if completion_attention_enabled() && !ui.window.is_active() {
+        ui.window.present();
+    }
+}
+
+/// Play a sound from the freedesktop sound theme, doing nothing if it or audio is missing
+fn play_theme_sound(name: &str) {
+    const ALERT_LENGTH: Duration = Duration::from_secs(5);
+
+    let Some(file) = [
+        "/usr/share/sounds/freedesktop/stereo",
+        "/usr/share/sounds/stereo",
+    ]
+    .iter()
+    .map(|dir| PathBuf::from(dir).join(format!("{name}.oga")))
+    .find(|file| file.exists()) else {
+        return;
+    };
+
+    let media = gtk4::MediaFile::for_filename(file);
+    media.play();
+    // The stream stops when the last reference goes; keep it for the chime's length
+    glib::timeout_add_local_once(ALERT_LENGTH, move || drop(media));
+}
+
+/// Ask whether to abort an operation that has stopped reporting progress
+fn show_stall_dialog(
+    window: &ApplicationWindow,
+    stall_timeout: Duration,
+    watchdog: Rc<Cell<Watchdog>>,
+    last_activity: Rc<Cell<Instant>>,
+) -> MessageDialog {
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::None,
+        i18n_f(
+            "No progress for {}s — abort?",
+            &[&stall_timeout.as_secs().to_string()],
+        ),
+    );
+    dialog.set_secondary_text(Some(
+        "The device has stopped responding. Aborting releases the window, \
+         but a device that is hung in the kernel may need to be unplugged.",
+    ));
+    dialog.add_button(&gettext("Keep Waiting"), ResponseType::Cancel);
+    dialog.add_button(&gettext("Abort"), ResponseType::Accept);
+
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            watchdog.set(Watchdog::AbortRequested);
+        } else {
+            // Give the device another full timeout before asking again
+            last_activity.set(Instant::now());
+            watchdog.set(Watchdog::Watching);
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+    dialog
+}
+
+/// Show the bytes around a verification mismatch, for bug reports
+fn show_verify_diff_dialog(window: &ApplicationWindow, diff: &str) {
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Error,
+        ButtonsType::Ok,
+        gettext("Verification mismatch"),
+    );
+    dialog.set_secondary_text(Some(&gettext(
+        "The device returned different data than was written. A single garbled \
+         region usually means a bad sector; repeated failures at the same offset \
+         point to the stick itself.",
+    )));
+
+    let diff_label = Label::new(Some(diff));
+    diff_label.add_css_class("diff-block");
+    diff_label.set_selectable(true);
+    diff_label.set_halign(gtk4::Align::Start);
+    if let Ok(message_area) = dialog.message_area().downcast::<GtkBox>() {
+        message_area.append(&diff_label);
+    }
+
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
+
+/// Tell the user a long pause may have let the device autosuspend
+fn show_long_pause_warning(window: &ApplicationWindow) {
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::Ok,
+        gettext("The write has been paused for over 10 minutes"),
+    );
+    dialog.set_secondary_text(Some(&gettext(
+        "The device may have been suspended by USB power management. \
+         If resuming fails, unplug it and start the write again.",
+    )));
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
+
+/// Warn that the image file is shorter than the filesystem it contains
+fn show_truncation_warning(
+    window: &ApplicationWindow,
+    info: &crate::core::iso::IsoInfo,
+    missing: u64,
+) {
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::Ok,
+        gettext("This image is incomplete"),
+    );
+
+    #[allow(clippy::cast_precision_loss)] // Acceptable for human-readable display
+    let to_mb = |bytes: u64| format!("{:.0}", bytes as f64 / 1_000_000.0);
+    let declared = info.file_size + missing;
+    dialog.set_secondary_text(Some(&i18n_f(
+        "The file is {} MB but its filesystem claims {} MB. It was probably cut off while \
+         being copied, for example to a FAT32 drive, which cannot hold files over 4 GiB.\n\n\
+         Writing it will produce a drive that fails to boot, and verification cannot detect \
+         this. Download the image again to a drive formatted as exFAT, NTFS or ext4.",
+        &[&to_mb(info.file_size), &to_mb(declared)],
+    )));
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
+
+/// Warn that a recognised image is unusually small for an installer
+fn show_small_image_warning(window: &ApplicationWindow, size: u64) {
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::Ok,
+        gettext("This image is unusually small"),
+    );
+
+    #[allow(clippy::cast_precision_loss)] // Acceptable for human-readable display
+    let size_mb = format!("{:.1}", size as f64 / 1_000_000.0);
+    dialog.set_secondary_text(Some(&i18n_f(
+        "The file is only {} MB. Rescue and network boot images can be this small, but \
+         an operating system installer usually is not. If you expected an installer, the \
+         download may have failed; compare the size with the one on the download page.",
+        &[&size_mb],
+    )));
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
+
+/// Warn that an optical-only image will not boot from USB on BIOS machines
+fn show_non_hybrid_warning(window: &ApplicationWindow, info: &crate::core::iso::IsoInfo) {
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::Ok,
+        gettext("This image is made for CDs and DVDs"),
+    );
+
+    let mut text = gettext(
+        "It has an El Torito boot record but no MBR boot signature, so BIOS machines \
+         will not boot it from a USB stick.",
+    );
+    text.push(' ');
+    text.push_str(&if info.has_efi {
+        gettext("UEFI machines may still boot it.")
+    } else {
+        gettext("It has no /EFI directory either, so it will probably not boot from USB at all.")
+    });
+    if info
+        .bootloaders
+        .contains(&crate::core::iso::Bootloader::Isolinux)
+    {
+        text.push_str("\n\n");
+        text.push_str(&gettext(
+            "It uses ISOLINUX: running \"isohybrid\" from syslinux on a copy of the image \
+             makes it bootable from USB.",
+        ));
+    } else {
+        text.push_str("\n\n");
+        text.push_str(&gettext(
+            "Look for a \"USB\" or \"hybrid\" edition of the image on the download page.",
+        ));
+    }
+    dialog.set_secondary_text(Some(&text));
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
+
+fn show_hook_warning(window: &ApplicationWindow, error: &str) {
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::Ok,
+        gettext("Post-write hook failed"),
+    );
+    dialog.set_secondary_text(Some(&i18n_f(
+        "The image was written successfully, but the command in ETCH_POST_WRITE_HOOK \
+         reported a problem:\n\n{}\n\nIts output is in the operation log (Open Log).",
+        &[error],
+    )));
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
+
+/// Multi-line summary of an image for the details panel
+fn describe_iso(info: &crate::core::iso::IsoInfo) -> String {
+    let yes_no = |value: bool| if value { gettext("Yes") } else { gettext("No") };
+
+    #[allow(clippy::cast_precision_loss)] // Acceptable for human-readable display
+    let size = format!("{:.2} GB", info.file_size as f64 / 1_000_000_000.0);
+    let mut lines = vec![i18n_f("Size: {}", &[&size])];
+
+    match &info.pvd {
+        Some(pvd) => {
+            lines.push(i18n_f("Label: {}", &[&pvd.volume_label]));
+            if !pvd.publisher.is_empty() {
+                lines.push(i18n_f("Publisher: {}", &[&pvd.publisher]));
+            }
+        }
+        None => lines.push(gettext("Raw image (no ISO9660 filesystem)")),
+    }
+
+    if let Some(missing) = info.missing_bytes() {
+        lines.push(i18n_f(
+            "TRUNCATED: {} bytes missing",
+            &[&missing.to_string()],
+        ));
+    }
+
+    lines.push(i18n_f(
+        "Hybrid (USB bootable): {}",
+        &[&yes_no(info.is_hybrid)],
+    ));
+    lines.push(i18n_f("El Torito: {}", &[&yes_no(info.has_el_torito)]));
+    lines.push(i18n_f("UEFI: {}", &[&yes_no(info.has_efi)]));
+
+    let bootloaders = if info.bootloaders.is_empty() {
+        gettext("Not detected")
+    } else {
+        info.bootloaders
+            .iter()
+            .map(|bootloader| bootloader.label())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    lines.push(i18n_f("Bootloader: {}", &[&bootloaders]));
+
+    lines.join("\n")
+}
+
+/// Top-level entries of the image, directories marked with a trailing slash
+fn describe_contents(info: &crate::core::iso::IsoInfo) -> String {
+    if info.pvd.is_none() {
+        return gettext("Not an ISO9660 filesystem (raw image)");
+    }
+    if info.root_entries.is_empty() {
+        return gettext("No entries could be read");
+    }
+
+    let mut lines: Vec<String> = info
+        .root_entries
+        .iter()
+        .take(MAX_PREVIEW_ENTRIES)
+        .map(|entry| {
+            if entry.is_dir {
+                format!("{}/", entry.name)
+            } else {
+                #[allow(clippy::cast_precision_loss)] // Acceptable for human-readable display
+                let size_mb = entry.size as f64 / 1_000_000.0;
+                format!("{}  ({size_mb:.1} MB)", entry.name)
+            }
+        })
+        .collect();
+
+    let hidden = info.root_entries.len().saturating_sub(MAX_PREVIEW_ENTRIES);
+    if hidden > 0 {
+        lines.push(i18n_f("… and {} more", &[&hidden.to_string()]));
+    }
+
+    lines.join("\n")
+}
+
+/// Render one progress update for the current phase
+fn show_progress(ui: &UIComponents, phase: &str, bytes: u64, total: u64, bps: u64) {
+    let text = ProgressText::new(bytes, total, bps);
+    ui.progress_bar.set_fraction(text.fraction);
+    ui.progress_bar.set_text(Some(&text.percent));
+    ui.progress_label.set_text(phase);
+    ui.speed_label.set_text(&text.detail);
+}
+
+/// Mirror a progress update on D-Bus under a stable, untranslated phase name
+fn publish_progress(ui: &UIComponents, phase: &'static str, bytes: u64, total: u64, bps: u64) {
+    if let Some(bus) = &ui.progress_bus {
+        bus.update(phase, bytes, total, bps);
+    }
+}
+
+/// Reflect progress in the window title, at most once per `TITLE_UPDATE_INTERVAL`
+fn update_progress_title(
+    ui: &UIComponents,
+    last_update: &mut Option<Instant>,
+    phase: &str,
+    bytes: u64,
+    total: u64,
+    bps: u64,
+) {
+    let now = Instant::now();
+    if last_update.is_some_and(|last| now.duration_since(last) < TITLE_UPDATE_INTERVAL) {
+        return;
+    }
+    *last_update = Some(now);
+
+    let progress = crate::core::models::Progress {
+        bytes_processed: bytes,
+        total_bytes: total,
+        bytes_per_second: bps,
+    };
+    ui.window
+        .set_title(Some(&format_window_title(&TitleState::Working {
+            phase,
+            progress: &progress,
+        })));
+}
+
+fn show_diagnostics_dialog(window: &ApplicationWindow) {
+    let checks = crate::core::diagnostics::run_checks();
+
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        if crate::core::diagnostics::has_failures(&checks) {
+            MessageType::Warning
+        } else {
+            MessageType::Info
+        },
+        ButtonsType::Ok,
+        gettext("Diagnostics"),
+    );
+    dialog.set_secondary_text(Some(&gettext(
+        "The same report is printed by running: etch --doctor",
+    )));
+
+    let report = Label::new(Some(
+        crate::core::diagnostics::format_report(&checks).trim_end(),
+    ));
+    report.add_css_class("report-block");
+    report.set_selectable(true);
+    report.set_wrap(true);
+    report.set_xalign(0.0);
+    if let Ok(message_area) = dialog.message_area().downcast::<GtkBox>() {
+        message_area.append(&report);
+    }
+
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show();
+}
+
+/// Check any file against a pasted checksum, independent of writing
+#[allow(clippy::too_many_lines)] // Dialog layout and worker polling belong together
+fn show_verify_file_dialog(window: &ApplicationWindow) {
+    use crate::core::verification::ChecksumAlgorithm;
+
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Other,
+        ButtonsType::None,
+        gettext("Verify a File"),
+    );
+    dialog.set_secondary_text(Some(&gettext(
+        "Compare a file with the checksum published for it. SHA-224, SHA-256, SHA-384 and \
+         SHA-512 are supported.",
+    )));
+    dialog.add_button(&gettext("Close"), ResponseType::Close);
+    dialog.add_button(&gettext("Verify"), ResponseType::Accept);
+
+    let chosen_file: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    let file_button = Button::with_label(&gettext("Choose File…"));
+
+    let checksum_entry = gtk4::Entry::builder()
+        .placeholder_text(gettext("Expected checksum"))
+        .build();
+    checksum_entry.add_css_class("checksum-entry");
+
+    // "Detect" first, then ChecksumAlgorithm::ALL in order
+    let mut algorithm_labels = vec![gettext("Detect from length")];
+    algorithm_labels.extend(
+        ChecksumAlgorithm::ALL
+            .iter()
+            .map(|algorithm| algorithm.label().to_string()),
+    );
+    let algorithm_dropdown = DropDown::from_strings(
+        &algorithm_labels
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>(),
+    );
+    algorithm_dropdown.add_css_class("dropdown-compact");
+
+    let progress_bar = ProgressBar::new();
+    progress_bar.set_show_text(true);
+    progress_bar.add_css_class("progress-compact");
+    progress_bar.set_visible(false);
+
+    let result_label = Label::new(None);
+    result_label.set_selectable(true);
+    result_label.set_wrap(true);
+    result_label.set_wrap_mode(gtk4::pango::WrapMode::Char);
+    result_label.set_xalign(0.0);
+    result_label.set_visible(false);
+
+    if let Ok(message_area) = dialog.message_area().downcast::<GtkBox>() {
+        message_area.append(&file_button);
+        message_area.append(&checksum_entry);
+        message_area.append(&algorithm_dropdown);
+        message_area.append(&progress_bar);
+        message_area.append(&result_label);
+    }
+
+    let dialog_clone = dialog.clone();
+    let chosen_clone = chosen_file.clone();
+    file_button.connect_clicked(move |button| {
+        let chooser = FileChooserDialog::new(
+            Some(&gettext("Select File")),
+            Some(&dialog_clone),
+            FileChooserAction::Open,
+            &[
+                (&gettext("Cancel"), ResponseType::Cancel),
+                (&gettext("Open"), ResponseType::Accept),
+            ],
+        );
+        let button = button.clone();
+        let chosen = chosen_clone.clone();
+        chooser.connect_response(move |chooser, response| {
+            if response == ResponseType::Accept {
+                if let Some(path) = chooser.file().and_then(|file| file.path()) {
+                    let name = path
+                        .file_name()
+                        .map_or_else(|| path.to_string_lossy(), |n| n.to_string_lossy())
+                        .into_owned();
+                    button.set_label(&name);
+                    *chosen.borrow_mut() = Some(path);
+                }
+            }
+            chooser.close();
+        });
+        chooser.show();
+    });
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    dialog.connect_response(move |dialog, response| {
+        if response != ResponseType::Accept {
+            cancel.store(true, Ordering::Relaxed);
+            dialog.close();
+            return;
+        }
+
+        let Some(path) = chosen_file.borrow().clone() else {
+            show_error_dialog(dialog, &gettext("Choose the file to verify first."));
+            return;
+        };
+        let expected = checksum_entry.text().trim().to_ascii_lowercase();
+        let algorithm = match algorithm_dropdown.selected() {
+            0 => ChecksumAlgorithm::detect(&expected),
+            index => ChecksumAlgorithm::ALL
+                .get(index as usize - 1)
+                .copied()
+                .filter(|algorithm| {
+                    ChecksumAlgorithm::detect(&expected) == Some(*algorithm)
+                }),
+        };
+        let Some(algorithm) = algorithm else {
+            show_error_dialog(
+                dialog,
+                &gettext(
+                    "The expected checksum must be a hexadecimal SHA-224, SHA-256, SHA-384 or SHA-512 digest.",
+                ),
+            );
+            return;
+        };
+
+        dialog.set_response_sensitive(ResponseType::Accept, false);
+        file_button.set_sensitive(false);
+        result_label.set_visible(false);
+        result_label.remove_css_class("success-text");
+        result_label.remove_css_class("error-text");
+        progress_bar.set_fraction(0.0);
+        progress_bar.set_visible(true);
+
+        let (tx, rx) = mpsc::channel();
+        let worker_cancel = cancel.clone();
+        thread::spawn(move || {
+            let progress_tx = tx.clone();
+            let result = crate::core::verification::file_digest(
+                &path,
+                algorithm,
+                &worker_cancel,
+                |bytes, total, bps| {
+                    let _ = progress_tx.send(ChecksumMessage::Progress(bytes, total, bps));
+                },
+            );
+            let _ = tx.send(match result {
+                Ok(digest) => ChecksumMessage::Complete(digest),
+                Err(e) => ChecksumMessage::Error(describe_error(&e)),
+            });
+        });
+
+        let dialog = dialog.clone();
+        let file_button = file_button.clone();
+        let progress_bar = progress_bar.clone();
+        let result_label = result_label.clone();
+        glib::spawn_future_local(async move {
+            let result = loop {
+                match rx.try_recv() {
+                    Ok(ChecksumMessage::Progress(bytes, total, bps)) => {
+                        let text = ProgressText::new(bytes, total, bps);
+                        progress_bar.set_fraction(text.fraction);
+                        progress_bar.set_text(Some(&text.percent));
+                    }
+                    Ok(ChecksumMessage::Complete(digest)) => break Ok(digest),
+                    Ok(ChecksumMessage::Error(err)) => break Err(err),
+                    Err(mpsc::TryRecvError::Empty) => {
+                        glib::timeout_future(MESSAGE_POLL_INTERVAL).await;
+                    }
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        break Err(gettext("Checksum calculation ended unexpectedly"));
+                    }
+                }
+            };
+
+            progress_bar.set_visible(false);
+            match result {
+                Ok(digest) if digest == expected => {
+                    result_label.set_text(&i18n_f(
+                        "✓ {} matches\n{}",
+                        &[algorithm.label(), &digest],
+                    ));
+                    result_label.add_css_class("success-text");
+                }
+                Ok(digest) => {
+                    result_label.set_text(&i18n_f(
+                        "✗ {} does not match. The file differs from the one the checksum was published for.\n\nExpected: {}\nActual: {}",
+                        &[algorithm.label(), &expected, &digest],
+                    ));
+                    result_label.add_css_class("error-text");
+                }
+                Err(err) => {
+                    result_label.set_text(&i18n_f("Error: {}", &[&err]));
+                    result_label.add_css_class("error-text");
+                }
+            }
+            result_label.set_visible(true);
+            file_button.set_sensitive(true);
+            dialog.set_response_sensitive(ResponseType::Accept, true);
+        });
+    });
+
+    dialog.show();
+}
+
+/// Save a support report, optionally copying it to the clipboard as well
+fn show_support_report_dialog(window: &ApplicationWindow, state: &Rc<RefCell<AppState>>) {
+    let dialog = FileChooserDialog::new(
+        Some(&gettext("Export Support Report")),
+        Some(window),
+        FileChooserAction::Save,
+        &[
+            (&gettext("Cancel"), ResponseType::Cancel),
+            (&gettext("Save"), ResponseType::Accept),
+        ],
+    );
+    let timestamp = glib::DateTime::now_local()
+        .and_then(|now| now.format("%Y%m%d-%H%M%S"))
+        .map_or_else(|_| "report".to_string(), |stamp| stamp.to_string());
+    dialog.set_current_name(&format!("etch-support-{timestamp}.txt"));
+    dialog.add_choice("full-paths", gettext("Include full paths"), &[]);
+    dialog.set_choice("full-paths", "false");
+    dialog.add_choice("clipboard", gettext("Also copy to clipboard"), &[]);
+    dialog.set_choice("clipboard", "true");
+
+    let window = window.clone();
+    let state = state.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            if let Some(output) = dialog.file().and_then(|file| file.path()) {
+                let full_paths = dialog
+                    .choice("full-paths")
+                    .is_some_and(|value| value == "true");
+                let state = state.borrow();
+                let context = crate::core::support_bundle::SupportContext {
+                    gtk_version: format!(
+                        "{}.{}.{}",
+                        gtk4::major_version(),
+                        gtk4::minor_version(),
+                        gtk4::micro_version()
+                    ),
+                    iso: state.selected_iso.as_deref(),
+                    device: state.selected_device.as_ref(),
+                    operation_log: state.operation_log.as_deref(),
+                };
+                let report = crate::core::support_bundle::build(&context, full_paths);
+
+                if let Err(e) = std::fs::write(&output, &report) {
+                    show_error_dialog(
+                        &window,
+                        &i18n_f(
+                            "Could not save {}: {}",
+                            &[&output.display().to_string(), &e.to_string()],
+                        ),
+                    );
+                } else if dialog
+                    .choice("clipboard")
+                    .is_some_and(|value| value == "true")
+                {
+                    window.clipboard().set_text(&report);
                 }
             }
         }
+        dialog.close();
     });
+
+    dialog.show();
+}
+
+fn show_about_dialog(window: &ApplicationWindow) {
+    let version = format!(
+        "{} ({} on {})",
+        env!("CARGO_PKG_VERSION"),
+        env!("ETCH_GIT_HASH"),
+        env!("ETCH_GIT_BRANCH")
+    );
+
+    let dialog = gtk4::AboutDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .program_name("Etch")
+        .logo_icon_name("media-removable-symbolic")
+        .version(version)
+        .comments(gettext(
+            "A transparent, trustworthy ISO-to-USB writer for Linux",
+        ))
+        .website(env!("CARGO_PKG_REPOSITORY"))
+        .authors(env!("CARGO_PKG_AUTHORS").split(':').collect::<Vec<_>>())
+        .license(i18n_f(
+            "Etch is licensed under {}.",
+            &[env!("CARGO_PKG_LICENSE")],
+        ))
+        .wrap_license(true)
+        .build();
+
+    // Generated by build.rs from cargo metadata
+    let licenses: Vec<&str> = include_str!(concat!(env!("OUT_DIR"), "/licenses.txt"))
+        .lines()
+        .collect();
+    if !licenses.is_empty() {
+        dialog.add_credit_section(&gettext("Third-party crates"), &licenses);
+    }
+
+    dialog.present();
 }
 
 fn show_error_dialog(parent: &impl IsA<gtk4::Window>, message: &str) {
@@ -539,9 +2594,470 @@ fn show_error_dialog(parent: &impl IsA<gtk4::Window>, message: &str) {
         gtk4::DialogFlags::MODAL,
         MessageType::Error,
         ButtonsType::Ok,
-        "Error",
+        gettext("Error"),
     );
     dialog.set_secondary_text(Some(message));
     dialog.connect_response(|dialog, _| dialog.close());
     dialog.show();
 }
+
+/// Offer to split an image that does not fit the device across several devices of its size
+fn show_spanned_confirmation_dialog(
+    window: &ApplicationWindow,
+    iso: PathBuf,
+    device: crate::core::models::BlockDevice,
+    image_size: u64,
+    state: Rc<RefCell<AppState>>,
+    ui: UIComponents,
+) {
+    let Some(parts) = crate::core::span::plan_parts(image_size, device.capacity_bytes)
+        .and_then(|parts| u32::try_from(parts.len()).ok())
+    else {
+        show_error_dialog(
+            window,
+            &i18n_f(
+                "The image ({} bytes) does not fit on {} ({}), and would need more than {} devices of this size to split it. Choose a larger device.",
+                &[
+                    &image_size.to_string(),
+                    &device.path.display().to_string(),
+                    &device.capacity_human(),
+                    &crate::core::span::MAX_PARTS.to_string(),
+                ],
+            ),
+        );
+        return;
+    };
+
+    let message = i18n_f(
+        "THE IMAGE DOES NOT FIT\n\n\
+         Image: {} ({} bytes)\n\
+         Device: {}\n\
+         Model: {} {}\n\
+         Capacity: {}\n\n\
+         The image can be split across {} devices of this size, written one after \
+         another. Etch pauses after each part so you can insert the next device.\n\n\
+         NOT BOOTABLE\n\n\
+         This is for raw data archival only. None of the devices will boot or mount. \
+         Keep them in order and use Restore Spanned Image… to join the parts back into \
+         an image file.\n\n\
+         DANGER ZONE\n\n\
+         ALL DATA ON EVERY DEVICE WILL BE PERMANENTLY ERASED\n\
+         This action cannot be undone.\n\n\
+         Continue?",
+        &[
+            &iso.file_name().unwrap_or_default().to_string_lossy(),
+            &image_size.to_string(),
+            &device.path.display().to_string(),
+            &device.vendor,
+            &device.model,
+            &device.capacity_human(),
+            &parts.to_string(),
+        ],
+    );
+
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::None,
+        gettext("Spanned Write: Raw Data Archival Only"),
+    );
+    dialog.set_secondary_text(Some(&message));
+    dialog.add_button(&gettext("Cancel"), ResponseType::Cancel);
+    let split_label = gettext("ERASE & SPLIT");
+    let accept_button = dialog.add_button(&split_label, ResponseType::Accept);
+
+    // Enter cancels; the confirm button unlocks after a countdown
+    dialog.set_default_response(ResponseType::Cancel);
+    update_confirm_button(&accept_button, &split_label, CONFIRM_DELAY_SECONDS);
+    dialog.update_property(&[gtk4::accessible::Property::Description(&i18n_f(
+        "Target device {}, {} {}, {}",
+        &[
+            &device.path.display().to_string(),
+            &device.vendor,
+            &device.model,
+            &device.capacity_human(),
+        ],
+    ))]);
+
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            let checks = crate::core::safety::validate_iso_selection(&iso)
+                .and_then(|()| crate::core::safety::ensure_not_hosting(&iso, &device.path))
+                .and_then(|()| crate::io::devices::validate_device(&device.path))
+                .and_then(|()| DeviceLock::acquire(&device.path));
+            let lock = match checks {
+                Ok(lock) => lock,
+                Err(e) => {
+                    show_error_dialog(
+                        dialog,
+                        &i18n_f("Cannot write to device:\n\n{}", &[&describe_error(&e)]),
+                    );
+                    dialog.close();
+                    return;
+                }
+            };
+            start_span_operation(
+                SpanJob::Write(iso.clone()),
+                device.clone(),
+                parts,
+                lock,
+                &state,
+                &ui,
+            );
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+    if let Some(cancel_button) = dialog.widget_for_response(ResponseType::Cancel) {
+        cancel_button.grab_focus();
+    }
+
+    glib::spawn_future_local(async move {
+        let mut locked_seconds = CONFIRM_DELAY_SECONDS;
+        while locked_seconds > 0 {
+            glib::timeout_future_seconds(1).await;
+            locked_seconds -= 1;
+            update_confirm_button(&accept_button, &split_label, locked_seconds);
+        }
+    });
+}
+
+/// Check the selected device holds part 1 of a spanned write, then ask where to restore it
+fn show_restore_image_dialog(state: &Rc<RefCell<AppState>>, ui: &UIComponents) {
+    let state_ref = state.borrow();
+    if state_ref.is_working {
+        return;
+    }
+    let Some(device) = state_ref.selected_device.clone() else {
+        show_error_dialog(
+            &ui.window,
+            &gettext("Select the device holding part 1 of the spanned write first."),
+        );
+        return;
+    };
+    drop(state_ref);
+
+    let header = match std::fs::File::open(&device.path) {
+        Ok(mut file) => crate::io::span::read_header(&mut file),
+        Err(e) => {
+            show_error_dialog(
+                &ui.window,
+                &i18n_f(
+                    "Cannot read {}: {}",
+                    &[&device.path.display().to_string(), &e.to_string()],
+                ),
+            );
+            return;
+        }
+    };
+    let parts = match header {
+        Some(header) if header.index == 0 => header.total,
+        Some(header) => {
+            show_error_dialog(
+                &ui.window,
+                &i18n_f(
+                    "{} holds part {} of a spanned write. Select the device holding part 1.",
+                    &[
+                        &device.path.display().to_string(),
+                        &(header.index + 1).to_string(),
+                    ],
+                ),
+            );
+            return;
+        }
+        None => {
+            show_error_dialog(
+                &ui.window,
+                &i18n_f(
+                    "{} does not hold a part of a spanned write, or the part was not finished.",
+                    &[&device.path.display().to_string()],
+                ),
+            );
+            return;
+        }
+    };
+
+    let dialog = FileChooserDialog::new(
+        Some(&gettext("Save Restored Image")),
+        Some(&ui.window),
+        FileChooserAction::Save,
+        &[
+            (&gettext("Cancel"), ResponseType::Cancel),
+            (&gettext("Save"), ResponseType::Accept),
+        ],
+    );
+    dialog.set_current_name("restored.img");
+
+    let state = state.clone();
+    let ui = ui.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            if let Some(output) = dialog.file().and_then(|file| file.path()) {
+                match DeviceLock::acquire(&device.path) {
+                    Ok(lock) => start_span_operation(
+                        SpanJob::Restore(output),
+                        device.clone(),
+                        parts,
+                        lock,
+                        &state,
+                        &ui,
+                    ),
+                    Err(e) => show_error_dialog(dialog, &describe_error(&e)),
+                }
+            }
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
+/// Run a spanned write or restore, pausing for the user to swap devices between parts
+///
+/// `device` holds the first part; `parts` is the expected number of parts.
+fn start_span_operation(
+    job: SpanJob,
+    device: crate::core::models::BlockDevice,
+    parts: u32,
+    lock: DeviceLock,
+    state: &Rc<RefCell<AppState>>,
+    ui: &UIComponents,
+) {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let mut state_mut = state.borrow_mut();
+    state_mut.is_working = true;
+    state_mut.cancel_requested = cancel.clone();
+    drop(state_mut);
+    let oplog = match OperationLog::create(&device.path) {
+        Ok(oplog) => Some(oplog),
+        Err(e) => {
+            eprintln!("WARNING: Continuing without an operation log: {e:#}");
+            None
+        }
+    };
+    state.borrow_mut().operation_log = oplog.as_ref().map(|oplog| oplog.path().to_path_buf());
+    if let Some(bus) = &ui.progress_bus {
+        bus.start(&device.path);
+    }
+
+    ui.write_button.set_sensitive(false);
+    ui.iso_button.set_sensitive(false);
+    ui.device_dropdown.set_sensitive(false);
+    ui.identify_button.set_sensitive(false);
+    ui.log_button.set_visible(false);
+    ui.boot_test_button.set_visible(false);
+    ui.progress_label.remove_css_class("success-text");
+    ui.progress_label.remove_css_class("error-text");
+    ui.throughput.clear();
+    ui.status_dot.remove_css_class("idle");
+    ui.status_dot.add_css_class("active");
+
+    // The image being split, so no later device can be the one holding it
+    let source_image = match &job {
+        SpanJob::Write(image) => Some(image.clone()),
+        SpanJob::Restore(_) => None,
+    };
+    let session = WriteSession::spanned(device.path.clone(), source_image.is_some(), parts);
+    let (tx, rx) = mpsc::channel();
+    let (reply_tx, reply_rx) = mpsc::channel::<DeviceReply>();
+    let worker_cancel = cancel.clone();
+    thread::spawn(move || {
+        run_span(
+            &job,
+            &device,
+            lock,
+            &worker_cancel,
+            oplog.as_ref(),
+            &tx,
+            &reply_rx,
+        );
+    });
+    watch_span_worker(rx, session, source_image, reply_tx, cancel, state, ui);
+}
+
+/// Drive `session` from the span worker's messages until it finishes
+///
+/// Device requests open the next-device dialog, which answers on `reply_tx`.
+fn watch_span_worker(
+    rx: mpsc::Receiver<SpanMessage>,
+    mut session: WriteSession,
+    source_image: Option<PathBuf>,
+    reply_tx: mpsc::Sender<DeviceReply>,
+    cancel: Arc<AtomicBool>,
+    state: &Rc<RefCell<AppState>>,
+    ui: &UIComponents,
+) {
+    let state = state.clone();
+    let ui = ui.clone();
+    glib::spawn_future_local(async move {
+        let mut device_dialog: Option<MessageDialog> = None;
+        let mut title_updated = None;
+        while !session.is_finished() {
+            // Never block the main loop: the worker may be waiting on a dialog
+            let event = match rx.try_recv() {
+                Ok(message) => WriteEvent::Span(message),
+                Err(mpsc::TryRecvError::Empty) => {
+                    glib::timeout_future(MESSAGE_POLL_INTERVAL).await;
+                    continue;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => WriteEvent::WorkerGone,
+            };
+
+            let (next, commands) = reduce(session, event);
+            session = next;
+            for command in commands {
+                match command {
+                    Command::RequestDevice {
+                        title,
+                        text,
+                        min_bytes,
+                    } => {
+                        device_dialog = Some(show_next_device_dialog(
+                            &ui,
+                            &title,
+                            &text,
+                            min_bytes,
+                            source_image.clone(),
+                            reply_tx.clone(),
+                        ));
+                    }
+                    command => {
+                        if matches!(command, Command::Failed(_)) {
+                            if let Some(dialog) = device_dialog.take() {
+                                dialog.close();
+                            }
+                        }
+                        apply_write_command(
+                            &ui,
+                            &state,
+                            &cancel,
+                            &session,
+                            &mut title_updated,
+                            command,
+                        );
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Ask for the device for the next part of a spanned operation and hand it to the waiting worker
+///
+/// `source_image` is set when writing. Continue replies with the chosen
+/// device, checked and locked; closing the dialog any other way stops the operation. A device that
+/// cannot be used, or that holds the image being split, keeps the dialog open.
+fn show_next_device_dialog(
+    ui: &UIComponents,
+    title: &str,
+    text: &str,
+    min_bytes: u64,
+    source_image: Option<PathBuf>,
+    reply: mpsc::Sender<DeviceReply>,
+) -> MessageDialog {
+    const REFRESH: ResponseType = ResponseType::Other(1);
+
+    let window = ui.write_button.root().and_downcast::<ApplicationWindow>();
+    let dialog = MessageDialog::new(
+        window.as_ref(),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Question,
+        ButtonsType::None,
+        title,
+    );
+    dialog.set_secondary_text(Some(text));
+    dialog.add_button(&gettext("Stop"), ResponseType::Cancel);
+    dialog.add_button(&gettext("Refresh"), REFRESH);
+    dialog.add_button(&gettext("Continue"), ResponseType::Accept);
+
+    let dropdown = DropDown::from_strings(&[]);
+    dropdown.add_css_class("dropdown-compact");
+    let devices = Rc::new(RefCell::new(Vec::new()));
+    let refresh = {
+        let dropdown = dropdown.clone();
+        let devices = devices.clone();
+        move || {
+            let found: Vec<crate::core::models::BlockDevice> =
+                crate::io::devices::list_removable_devices()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|device| device.capacity_bytes >= min_bytes)
+                    .collect();
+            let labels: Vec<String> = if found.is_empty() {
+                vec![gettext("No suitable removable devices detected")]
+            } else {
+                found
+                    .iter()
+                    .map(|device| {
+                        format!(
+                            "{} · {} {} · {}",
+                            device.path.display(),
+                            device.vendor,
+                            device.model,
+                            device.capacity_human()
+                        )
+                    })
+                    .collect()
+            };
+            if let Some(strings) = dropdown.model().and_downcast::<StringList>() {
+                let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+                strings.splice(0, strings.n_items(), &labels);
+            }
+            dropdown.set_sensitive(!found.is_empty());
+            *devices.borrow_mut() = found;
+        }
+    };
+    refresh();
+    if let Ok(message_area) = dialog.message_area().downcast::<GtkBox>() {
+        message_area.append(&dropdown);
+    }
+
+    // Closing the dialog from the handler responds again
+    let answered = Cell::new(false);
+    dialog.connect_response(move |dialog, response| {
+        if answered.get() {
+            return;
+        }
+        match response {
+            REFRESH => {
+                refresh();
+                return;
+            }
+            ResponseType::Accept => {
+                let Some(device) = devices.borrow().get(dropdown.selected() as usize).cloned()
+                else {
+                    return;
+                };
+                let checks = source_image
+                    .as_ref()
+                    .map_or(Ok(()), |image| {
+                        crate::core::safety::ensure_not_hosting(image, &device.path)
+                            .and_then(|()| crate::io::devices::validate_device(&device.path))
+                    })
+                    .and_then(|()| DeviceLock::acquire(&device.path));
+                match checks {
+                    Ok(lock) => {
+                        let _ = reply.send(Some((device.path, lock)));
+                    }
+                    Err(e) => {
+                        show_error_dialog(
+                            dialog,
+                            &i18n_f("Cannot write to device:\n\n{}", &[&describe_error(&e)]),
+                        );
+                        return;
+                    }
+                }
+            }
+            _ => {
+                let _ = reply.send(None);
+            }
+        }
+        answered.set(true);
+        dialog.close();
+    });
+
+    dialog.show();
+    dialog
+}
@@ -0,0 +1,721 @@
+//! The write and spanned-operation workers, run off the main thread
+//!
+//! Nothing here touches GTK: each worker reports through a channel that
+//! `window.rs` drains on the main loop, so the order of a write's phases
+//! reads apart from the widgets that show them.
+
+use crate::core::error::EtchError;
+use crate::core::models::BlockDevice;
+use crate::core::verification::{ReadRetry, VerifyMode};
+use crate::i18n::{gettext, i18n_f};
+use crate::io::lock::DeviceLock;
+use crate::io::oplog::OperationLog;
+use crate::io::writer::WriteStatus;
+use crate::ui::state::{
+    describe_error, verbose_diagnostics, SpanMessage, WorkMessage, MESSAGE_POLL_INTERVAL,
+};
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Instant;
+
+/// Choices made in the confirmation dialog
+pub struct WriteOptions {
+    pub expected_sha256: Option<String>,
+    pub verify_mode: VerifyMode,
+    /// Run the worker at lower I/O and CPU priority
+    pub background: bool,
+    /// Zero the old partition tables before writing
+    pub clear_tables: bool,
+    /// Skip the unused blocks of ext4 filesystems in the image, if they can be mapped
+    pub used_space_only: bool,
+    /// Copy the image onto a Ventoy stick instead of writing the device
+    pub add_to_ventoy: bool,
+}
+
+/// Everything a write needs, moved onto the worker thread
+pub struct WriteJob {
+    pub iso: PathBuf,
+    pub device: BlockDevice,
+    pub options: WriteOptions,
+    /// Held until the worker finishes, however it exits
+    pub lock: DeviceLock,
+    pub cancel: Arc<AtomicBool>,
+    pub pause: Arc<AtomicBool>,
+    pub oplog: Option<OperationLog>,
+}
+
+/// Check, write, re-read and verify one image, reporting each step on `tx`
+///
+/// Returns once the worker has sent its final message (`VerifyComplete`,
+/// `Cancelled` or `Error`), or as soon as the UI has gone away.
+pub fn run_write(job: WriteJob, tx: &mpsc::Sender<WorkMessage>) {
+    let WriteJob {
+        iso,
+        device,
+        options,
+        lock: _lock,
+        cancel,
+        pause,
+        oplog,
+    } = job;
+    let worker = Worker {
+        iso: &iso,
+        cancel: &cancel,
+        pause: &pause,
+        oplog: oplog.as_ref(),
+        tx,
+    };
+
+    // The device may come back under a new node after a reconnect
+    let identity = crate::io::devices::device_identity(&device.path);
+    worker.log_header(&device, identity.as_deref());
+    if options.background {
+        worker.lower_priority();
+    }
+
+    // Refuse to write an image that is already corrupt
+    if let Some(expected) = &options.expected_sha256 {
+        if let Err(e) = worker.check_source(expected) {
+            worker.error(&format!("source check failed: {e:#}"));
+            if tx.send(WorkMessage::Error(describe_error(&e))).is_err() {
+                eprintln!("CRITICAL: Source check failed but UI channel closed: {e}");
+            }
+            return;
+        }
+    }
+
+    if options.add_to_ventoy {
+        match worker.add_to_ventoy_stick(&device.path) {
+            Ok(()) => {
+                if tx.send(WorkMessage::VerifyComplete).is_err() {
+                    eprintln!("WARNING: Copy completed but UI channel closed");
+                }
+            }
+            Err(e) => {
+                worker.error(&format!("ventoy: {e:#}"));
+                if tx.send(WorkMessage::Error(describe_error(&e))).is_err() {
+                    eprintln!("CRITICAL: Copy failed but UI channel closed: {e}");
+                }
+            }
+        }
+        return;
+    }
+
+    // Any doubt about the filesystem layout means the whole image is written
+    let extents = if options.used_space_only {
+        worker.plan_extents()
+    } else {
+        None
+    };
+
+    let device_path = match worker.write(&device.path, options.clear_tables, extents.as_deref()) {
+        Ok(path) => path,
+        Err(e) => {
+            worker.error(&format!("write failed: {e:#}"));
+            let message = match e.downcast_ref::<EtchError>() {
+                Some(EtchError::Cancelled { bytes_done }) => {
+                    eprintln!(
+                        "ABORTED: {bytes_done} bytes written and flushed to {}",
+                        device.path.display()
+                    );
+                    WorkMessage::Cancelled(*bytes_done)
+                }
+                _ => WorkMessage::Error(i18n_f("Write failed: {}", &[&describe_error(&e)])),
+            };
+            // Error notification is critical - if this fails, log to stderr
+            if tx.send(message).is_err() {
+                eprintln!("CRITICAL: Write failed but UI channel closed: {e}");
+            }
+            return;
+        }
+    };
+
+    // Let the kernel (and the desktop) see the new partitions right away
+    if !device.is_partition {
+        worker.inspect_written_disk(&device_path);
+    }
+    if tx.send(WorkMessage::WriteComplete).is_err() {
+        eprintln!("WARNING: Write completed but UI channel closed");
+        return;
+    }
+
+    if options.verify_mode == VerifyMode::Skip {
+        worker.log("verify: skipped at the user's request");
+        worker.run_hook(&device_path, "unverified");
+        if tx.send(WorkMessage::VerifyComplete).is_err() {
+            eprintln!("WARNING: Write completed but UI channel closed");
+        }
+        return;
+    }
+
+    let verify_path = match worker.verify(
+        device_path,
+        identity.as_deref(),
+        options.verify_mode,
+        extents.as_deref(),
+    ) {
+        Ok(path) => path,
+        Err(e) => {
+            worker.report_verify_failure(&e);
+            return;
+        }
+    };
+    worker.run_hook(&verify_path, "verified");
+    if tx.send(WorkMessage::VerifyComplete).is_err() {
+        eprintln!("WARNING: Verification completed but UI channel closed");
+    }
+}
+
+/// The handles one write's phases share
+struct Worker<'a> {
+    iso: &'a Path,
+    cancel: &'a AtomicBool,
+    pause: &'a AtomicBool,
+    oplog: Option<&'a OperationLog>,
+    tx: &'a mpsc::Sender<WorkMessage>,
+}
+
+impl Worker<'_> {
+    fn log(&self, line: &str) {
+        if let Some(oplog) = self.oplog {
+            oplog.log(line);
+        }
+    }
+
+    fn error(&self, line: &str) {
+        if let Some(oplog) = self.oplog {
+            oplog.error(line);
+        }
+    }
+
+    /// Channel send errors are not critical during progress updates:
+    /// if the channel is closed, the UI thread has terminated
+    fn send(&self, message: WorkMessage) {
+        let _ = self.tx.send(message);
+    }
+
+    fn log_header(&self, device: &BlockDevice, identity: Option<&str>) {
+        self.log(&format!(
+            "etch {} ({})",
+            env!("CARGO_PKG_VERSION"),
+            env!("ETCH_GIT_HASH")
+        ));
+        self.log(&format!(
+            "source {} ({} bytes)",
+            self.iso.display(),
+            std::fs::metadata(self.iso).map_or(0, |metadata| metadata.len())
+        ));
+        self.log(&format!(
+            "target {} ({} {}, {} bytes, identity {})",
+            device.path.display(),
+            device.vendor,
+            device.model,
+            device.capacity_bytes,
+            identity.unwrap_or("unknown")
+        ));
+    }
+
+    fn lower_priority(&self) {
+        match crate::io::priority::lower_current_thread() {
+            Ok(()) => self.log("priority: background (best-effort I/O level 7, nice 10)"),
+            Err(e) => {
+                eprintln!("WARNING: Could not lower write priority: {e}");
+                self.log(&format!("priority: could not lower: {e}"));
+            }
+        }
+    }
+
+    fn check_source(&self, expected: &str) -> anyhow::Result<()> {
+        self.log(&format!("source check: expecting SHA-256 {expected}"));
+        let tx = self.tx.clone();
+        crate::core::verification::verify_source_checksum(
+            self.iso,
+            expected,
+            self.cancel,
+            move |bytes, total, bps| {
+                let _ = tx.send(WorkMessage::SourceCheckProgress(bytes, total, bps));
+            },
+        )
+    }
+
+    /// Copy the image onto a Ventoy stick's data partition and read it back
+    ///
+    /// Sends the same progress messages as a raw write, up to but not including
+    /// `VerifyComplete`.
+    fn add_to_ventoy_stick(&self, disk: &Path) -> anyhow::Result<()> {
+        let partition = crate::io::ventoy::data_partition(disk)
+            .ok_or_else(|| anyhow::anyhow!("{} no longer has a Ventoy layout", disk.display()))?;
+        let mount = crate::io::ventoy::DataMount::mount(&partition)?;
+        self.log(&format!(
+            "ventoy: copying into {} ({})",
+            mount.path().display(),
+            partition.display()
+        ));
+
+        let tx_progress = self.tx.clone();
+        let (copy, digest) = crate::io::ventoy::copy_image(
+            self.iso,
+            &mount,
+            self.cancel,
+            self.pause,
+            move |bytes, total, bps| {
+                let _ = tx_progress.send(WorkMessage::WriteProgress(bytes, total, bps));
+            },
+            |status| {
+                let message = match status {
+                    WriteStatus::Paused(offset) => WorkMessage::Paused(offset),
+                    WriteStatus::Resumed => WorkMessage::Resumed,
+                    WriteStatus::Syncing => WorkMessage::Syncing,
+                    WriteStatus::Synced(duration) => {
+                        self.log(&format!("ventoy: flush took {} ms", duration.as_millis()));
+                        WorkMessage::Synced(duration)
+                    }
+                    _ => return,
+                };
+                self.send(message);
+            },
+        )?;
+        self.log(&format!(
+            "ventoy: copied to {}, SHA-256 {digest}",
+            copy.display()
+        ));
+        self.send(WorkMessage::WriteComplete);
+
+        let tx_progress = self.tx.clone();
+        crate::io::ventoy::verify_copy(&copy, &digest, self.cancel, move |bytes, total, bps| {
+            let _ = tx_progress.send(WorkMessage::VerifyProgress(bytes, total, bps));
+        })?;
+        self.log("ventoy: copy verified");
+
+        Ok(())
+    }
+
+    fn plan_extents(&self) -> Option<Vec<Range<u64>>> {
+        let extents = crate::core::extents::used_extents(self.iso);
+        match &extents {
+            Some(extents) => self.log(&format!(
+                "used space: writing {} bytes in {} extents",
+                crate::core::extents::extent_bytes(extents),
+                extents.len()
+            )),
+            None => self.log("used space: no ext4 map for this image, writing all of it"),
+        }
+        extents
+    }
+
+    /// Write the image, returning the device's path once the write has been flushed
+    ///
+    /// The path differs from `target` if the device reconnected mid-write.
+    fn write(
+        &self,
+        target: &Path,
+        clear_tables: bool,
+        extents: Option<&[Range<u64>]>,
+    ) -> anyhow::Result<PathBuf> {
+        let device_path = RefCell::new(target.to_path_buf());
+        let last_logged_decile = Cell::new(0);
+        self.log(&format!(
+            "write: O_WRONLY, {} byte chunks, flush every {} bytes",
+            crate::io::writer::CHUNK_SIZE,
+            crate::io::writer::SYNC_INTERVAL
+        ));
+        let write_started = Instant::now();
+        crate::io::writer::write_iso(
+            self.iso,
+            target,
+            clear_tables,
+            extents,
+            self.cancel,
+            self.pause,
+            |bytes, total, bps| {
+                // Timings per 10% make slowdowns visible in the log
+                let decile = (bytes * 10).checked_div(total).unwrap_or(0);
+                if decile > last_logged_decile.get() {
+                    last_logged_decile.set(decile);
+                    self.log(&format!("write: {bytes}/{total} bytes, {bps} B/s"));
+                }
+                self.send(WorkMessage::WriteProgress(bytes, total, bps));
+            },
+            |status| {
+                let message = self.write_status(status);
+                if let WorkMessage::Reconnected(path) = &message {
+                    device_path.replace(path.clone());
+                }
+                self.send(message);
+            },
+        )?;
+
+        self.log(&format!(
+            "write: complete in {:.1}s",
+            write_started.elapsed().as_secs_f64()
+        ));
+        Ok(device_path.into_inner())
+    }
+
+    /// Log a status change from the writer and translate it for the UI
+    fn write_status(&self, status: WriteStatus) -> WorkMessage {
+        match status {
+            WriteStatus::Preparing => {
+                self.log(&format!(
+                    "prepare: zeroing first and last {} bytes",
+                    crate::io::writer::TABLE_CLEAR_BYTES
+                ));
+                WorkMessage::Preparing
+            }
+            WriteStatus::Reconnecting => {
+                self.log("write: device disappeared, waiting for it to return");
+                WorkMessage::Reconnecting
+            }
+            WriteStatus::Reconnected(path) => {
+                self.log(&format!(
+                    "write: reconnected as {}, resuming from last flush",
+                    path.display()
+                ));
+                WorkMessage::Reconnected(path)
+            }
+            WriteStatus::Paused(offset) => {
+                self.log(&format!("write: paused at {offset} bytes"));
+                WorkMessage::Paused(offset)
+            }
+            WriteStatus::Resumed => {
+                self.log("write: resumed");
+                WorkMessage::Resumed
+            }
+            WriteStatus::Syncing => {
+                self.log("write: flushing device cache");
+                WorkMessage::Syncing
+            }
+            WriteStatus::Synced(duration) => {
+                self.log(&format!("write: flush took {} ms", duration.as_millis()));
+                WorkMessage::Synced(duration)
+            }
+        }
+    }
+
+    /// Re-read the partition table and check the boot signature of a whole written disk
+    fn inspect_written_disk(&self, disk: &Path) {
+        match crate::io::devices::reread_partition_table(disk) {
+            Ok(()) => self.log("write: partition table re-read"),
+            Err(e) => self.log(&format!("write: could not re-read partition table: {e}")),
+        }
+
+        // An image without the signature only boots on UEFI (or from optical media)
+        match crate::io::devices::has_boot_signature(disk) {
+            Ok(bootable) => {
+                self.log(&format!(
+                    "write: MBR boot signature {}",
+                    if bootable { "present" } else { "absent" }
+                ));
+                self.send(WorkMessage::BiosBootable(bootable));
+            }
+            Err(e) => self.log(&format!("write: could not read back boot sector: {e}")),
+        }
+    }
+
+    /// Read the device back against the image, returning the path it was read from
+    ///
+    /// Some card readers drop off briefly when the kernel re-reads the partition
+    /// table after a write; wait for the device once and restart from offset 0.
+    fn verify(
+        &self,
+        mut path: PathBuf,
+        identity: Option<&str>,
+        mode: VerifyMode,
+        extents: Option<&[Range<u64>]>,
+    ) -> anyhow::Result<PathBuf> {
+        let mut reconnected = false;
+        let verify_started = Instant::now();
+        self.log(&format!("verify: {mode:?} read-back of {}", path.display()));
+        if mode == VerifyMode::Quick {
+            self.log("verify: sampled regions, read in place without read-ahead");
+        } else {
+            self.log(&format!(
+                "verify: reading up to {} chunks of {} bytes ahead of the comparison",
+                crate::core::verification::READ_AHEAD,
+                crate::core::verification::CHUNK_SIZE
+            ));
+        }
+        loop {
+            let tx = self.tx.clone();
+            let progress = move |bytes, total, bps| {
+                let _ = tx.send(WorkMessage::VerifyProgress(bytes, total, bps));
+            };
+            let on_retry = |retry: &ReadRetry| self.log(&format!("verify: {retry}"));
+            let result = if mode == VerifyMode::Quick {
+                crate::core::verification::verify_samples(
+                    self.iso,
+                    &path,
+                    extents,
+                    self.cancel,
+                    progress,
+                    on_retry,
+                )
+            } else {
+                crate::core::verification::verify_write(
+                    self.iso,
+                    &path,
+                    extents,
+                    self.cancel,
+                    progress,
+                    on_retry,
+                )
+            };
+
+            match result {
+                Err(e) if !reconnected && is_device_gone_error(&e) => {
+                    reconnected = true;
+                    self.send(WorkMessage::Reconnecting);
+                    let (_, new_path) = crate::io::devices::wait_for_reconnect(
+                        identity,
+                        &path,
+                        self.cancel,
+                        |path| std::fs::File::open(path),
+                    )?;
+                    eprintln!(
+                        "INFO: Device reconnected as {} before verification, restarting",
+                        new_path.display()
+                    );
+                    self.log(&format!(
+                        "verify: reconnected as {}, restarting",
+                        new_path.display()
+                    ));
+                    path = new_path.clone();
+                    self.send(WorkMessage::Reconnected(new_path));
+                }
+                result => {
+                    result?;
+                    break;
+                }
+            }
+        }
+
+        self.log(&format!(
+            "verify: complete in {:.1}s",
+            verify_started.elapsed().as_secs_f64()
+        ));
+        Ok(path)
+    }
+
+    fn report_verify_failure(&self, e: &anyhow::Error) {
+        self.error(&format!("verify failed: {e:#}"));
+        if let Some(mismatch) = e.downcast_ref::<crate::core::verification::Mismatch>() {
+            let diff = mismatch.hex_diff();
+            self.log(&diff);
+            if verbose_diagnostics() {
+                eprintln!("VERIFY_DIFF\n{diff}");
+                self.send(WorkMessage::VerifyDiff(diff));
+            }
+        }
+        if self
+            .tx
+            .send(WorkMessage::Error(i18n_f(
+                "Verification failed: {}",
+                &[&describe_error(e)],
+            )))
+            .is_err()
+        {
+            eprintln!("CRITICAL: Verification failed but UI channel closed: {e}");
+        }
+    }
+
+    /// Run `ETCH_POST_WRITE_HOOK`, if set, once the device is known good (or unchecked)
+    ///
+    /// A failing hook is reported as a warning; the write itself still succeeds.
+    fn run_hook(&self, device: &Path, result: &str) {
+        let Some(command) = crate::io::hook::configured_command() else {
+            return;
+        };
+        self.send(WorkMessage::RunningHook);
+        self.log(&format!(
+            "hook: running {command:?} with ETCH_RESULT={result}"
+        ));
+
+        let failure = match crate::io::hook::run(
+            &command,
+            device,
+            self.iso,
+            result,
+            self.cancel,
+            crate::io::hook::HOOK_TIMEOUT,
+        ) {
+            Ok(outcome) => {
+                for line in outcome.output.lines() {
+                    self.log(&format!("hook: {line}"));
+                }
+                match outcome.status {
+                    Some(status) if status.success() => {
+                        self.log("hook: succeeded");
+                        return;
+                    }
+                    Some(status) => {
+                        i18n_f("The post-write hook failed ({})", &[&status.to_string()])
+                    }
+                    None if self.cancel.load(Ordering::Relaxed) => gettext(
+                        "The post-write hook was stopped because the operation was cancelled",
+                    ),
+                    None => i18n_f(
+                        "The post-write hook was stopped after running for {} seconds",
+                        &[&crate::io::hook::HOOK_TIMEOUT.as_secs().to_string()],
+                    ),
+                }
+            }
+            Err(e) => format!("{e:#}"),
+        };
+        self.error(&format!("hook: {failure}"));
+        self.send(WorkMessage::HookFailed(failure));
+    }
+}
+
+/// Whether an operation failed because the device node vanished or went away
+fn is_device_gone_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|io_error| {
+                crate::io::devices::is_device_gone(io_error)
+                    || io_error.kind() == std::io::ErrorKind::NotFound
+            })
+    })
+}
+
+/// What a spanned operation does with its devices
+pub enum SpanJob {
+    /// Split this image across the devices
+    Write(PathBuf),
+    /// Join the parts on the devices back into this image file
+    Restore(PathBuf),
+}
+
+/// The worker's answer to `SpanMessage::NeedDevice`: the device, locked, or `None` to stop
+pub type DeviceReply = Option<(PathBuf, DeviceLock)>;
+
+/// Run a spanned write or restore, asking on `tx` for each device after the first
+///
+/// `first` holds the first part and is held under `lock`. Each
+/// `SpanMessage::NeedDevice` waits for an answer on `replies`; cancelling stops
+/// the wait without one.
+pub fn run_span(
+    job: &SpanJob,
+    first: &BlockDevice,
+    lock: DeviceLock,
+    cancel: &AtomicBool,
+    oplog: Option<&OperationLog>,
+    tx: &mpsc::Sender<SpanMessage>,
+    replies: &mpsc::Receiver<DeviceReply>,
+) {
+    let log = |line: &str| {
+        if let Some(oplog) = oplog {
+            oplog.log(line);
+        }
+    };
+
+    // Only the device in use is locked, so a refused one can be chosen again
+    let mut held = Some(lock);
+    let next_device = |request: crate::io::span::DeviceRequest<'_>| {
+        drop(held.take());
+        if let Some(e) = request.rejected {
+            log(&format!(
+                "span: device for part {} refused: {e:#}",
+                request.index + 1
+            ));
+        }
+        let _ = tx.send(SpanMessage::NeedDevice {
+            index: request.index,
+            total: request.total,
+            min_bytes: request.min_bytes,
+            rejected: request.rejected.map(describe_error),
+        });
+        // Cancelling (e.g. on quit) must not wait for the dialog
+        let (path, lock) = loop {
+            match replies.recv_timeout(MESSAGE_POLL_INTERVAL) {
+                Ok(reply) => break reply?,
+                Err(mpsc::RecvTimeoutError::Timeout) if !cancel.load(Ordering::Relaxed) => {}
+                Err(_) => return None,
+            }
+        };
+        log(&format!(
+            "span: part {} on {}",
+            request.index + 1,
+            path.display()
+        ));
+        held = Some(lock);
+        Some(path)
+    };
+    let progress = |part, phase, bytes, part_bytes, bps| {
+        let _ = tx.send(SpanMessage::Progress {
+            part,
+            phase,
+            bytes,
+            part_bytes,
+            bps,
+        });
+    };
+
+    let result = match job {
+        SpanJob::Write(image) => {
+            log(&format!(
+                "span: splitting {} across devices like {} ({} bytes)",
+                image.display(),
+                first.path.display(),
+                first.capacity_bytes
+            ));
+            crate::io::span::write_spanned(image, &first.path, cancel, next_device, progress).map(
+                |headers| {
+                    for header in &headers {
+                        log(&format!(
+                            "span: part {} of {} holds {} bytes at {}, SHA-256 {}",
+                            header.index + 1,
+                            header.total,
+                            header.length,
+                            header.offset,
+                            header.sha256_hex()
+                        ));
+                    }
+                    i18n_f(
+                        "Image split across {} devices · keep them in order to restore it",
+                        &[&headers.len().to_string()],
+                    )
+                },
+            )
+        }
+        SpanJob::Restore(output) => {
+            log(&format!(
+                "span: restoring from {} into {}",
+                first.path.display(),
+                output.display()
+            ));
+            crate::io::span::restore_spanned(&first.path, output, cancel, next_device, progress)
+                .map(|image| {
+                    log(&format!(
+                        "span: restored {} bytes from {} parts, SHA-256 {}",
+                        image.image_size, image.parts, image.sha256
+                    ));
+                    i18n_f(
+                        "Image restored from {} parts · SHA-256 {}",
+                        &[&image.parts.to_string(), &image.sha256],
+                    )
+                })
+        }
+    };
+
+    let message = match result {
+        Ok(summary) => SpanMessage::Complete(summary),
+        Err(e) => {
+            if let Some(oplog) = oplog {
+                oplog.error(format_args!("span failed: {e:#}"));
+            }
+            SpanMessage::Error(match job {
+                SpanJob::Write(_) => i18n_f("Spanned write failed: {}", &[&describe_error(&e)]),
+                SpanJob::Restore(_) => {
+                    i18n_f("Restoring the image failed: {}", &[&describe_error(&e)])
+                }
+            })
+        }
+    };
+    if tx.send(message).is_err() {
+        eprintln!("WARNING: Spanned operation finished but UI channel closed");
+    }
+}
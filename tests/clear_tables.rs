@@ -0,0 +1,71 @@
+//! Clearing old partition tables before a write (root only)
+
+mod common;
+
+use common::{image_bytes, LoopDevice, MIB};
+use std::sync::atomic::AtomicBool;
+
+const SECTOR: u64 = 512;
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+const DEVICE_SIZE: u64 = 64 * MIB;
+const IMAGE_SIZE: u64 = 8 * MIB;
+
+/// A device that was GPT-partitioned before: primary header at LBA 1, backup at the last LBA
+fn previously_partitioned_device() -> LoopDevice {
+    let device = LoopDevice::new(DEVICE_SIZE);
+    device.plant(SECTOR, GPT_SIGNATURE);
+    device.plant(DEVICE_SIZE - SECTOR, GPT_SIGNATURE);
+    device
+}
+
+/// Write an `IMAGE_SIZE` image and return what the device flushed
+fn write_image(device: &LoopDevice, clear_tables: bool) -> (Vec<u8>, Vec<u8>) {
+    let image = image_bytes(IMAGE_SIZE);
+    let mut source = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut source, &image).unwrap();
+
+    etch::io::writer::write_iso(
+        source.path(),
+        device.path(),
+        clear_tables,
+        None,
+        &AtomicBool::new(false),
+        &AtomicBool::new(false),
+        |_, _, _| {},
+        |_| {},
+    )
+    .unwrap();
+    (image, device.flushed_contents())
+}
+
+/// Offsets of sectors past the image that start with a GPT header signature
+fn gpt_headers_beyond_image(contents: &[u8]) -> Vec<usize> {
+    let image_end = usize::try_from(IMAGE_SIZE).unwrap();
+    let sector = usize::try_from(SECTOR).unwrap();
+    (image_end..contents.len())
+        .step_by(sector)
+        .filter(|&offset| contents[offset..].starts_with(GPT_SIGNATURE))
+        .collect()
+}
+
+#[test]
+#[ignore = "needs root and a free loop device"]
+fn stale_backup_gpt_is_cleared_beyond_the_image() {
+    let device = previously_partitioned_device();
+    let (image, contents) = write_image(&device, true);
+
+    assert!(contents[..image.len()] == image[..]);
+    assert_eq!(gpt_headers_beyond_image(&contents), Vec::<usize>::new());
+    etch::io::devices::reread_partition_table(device.path()).unwrap();
+}
+
+#[test]
+#[ignore = "needs root and a free loop device"]
+fn stale_backup_gpt_survives_without_clearing() {
+    let device = previously_partitioned_device();
+    let (image, contents) = write_image(&device, false);
+
+    assert!(contents[..image.len()] == image[..]);
+    let backup = usize::try_from(DEVICE_SIZE - SECTOR).unwrap();
+    assert_eq!(gpt_headers_beyond_image(&contents), [backup]);
+}
@@ -0,0 +1,77 @@
+//! Loop devices for the integration tests that need a real block device
+//!
+//! These tests need root and a free loop device, so they are `#[ignore]`d;
+//! run them with `sudo cargo test -- --ignored`.
+
+// Each test binary uses only some of these helpers
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const MIB: u64 = 1024 * 1024;
+
+/// A loop device over a sparse temporary file, detached on drop
+pub struct LoopDevice {
+    path: PathBuf,
+    backing: tempfile::NamedTempFile,
+}
+
+impl LoopDevice {
+    /// Attach a zeroed device of `size` bytes, with partition scanning on
+    pub fn new(size: u64) -> Self {
+        let backing = tempfile::NamedTempFile::new().expect("create backing file");
+        backing.as_file().set_len(size).expect("size backing file");
+
+        let output = Command::new("losetup")
+            .args(["--find", "--show", "--partscan"])
+            .arg(backing.path())
+            .output()
+            .expect("run losetup");
+        assert!(
+            output.status.success(),
+            "losetup failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        let path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        Self { path, backing }
+    }
+
+    /// The `/dev/loopN` node
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// What reached the backing file, i.e. what the device flushed
+    pub fn flushed_contents(&self) -> Vec<u8> {
+        fs::read(self.backing.path()).expect("read backing file")
+    }
+
+    /// Overwrite bytes of the backing file directly, e.g. to plant stale data
+    pub fn plant(&self, offset: u64, bytes: &[u8]) {
+        use std::os::unix::fs::FileExt;
+        self.backing
+            .as_file()
+            .write_all_at(bytes, offset)
+            .expect("write backing file");
+        self.backing
+            .as_file()
+            .sync_all()
+            .expect("sync backing file");
+    }
+}
+
+impl Drop for LoopDevice {
+    fn drop(&mut self) {
+        let _ = Command::new("losetup")
+            .arg("--detach")
+            .arg(&self.path)
+            .status();
+    }
+}
+
+/// `len` bytes of a pattern that never contains a GPT signature
+pub fn image_bytes(len: u64) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
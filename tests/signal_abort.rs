@@ -0,0 +1,97 @@
+//! A termination signal mid-write stops the writer cleanly (root only)
+//!
+//! The window turns SIGTERM, SIGINT and SIGHUP into the operation's cancel
+//! flag through glib; a plain handler stands in for it here.
+
+mod common;
+
+use common::{image_bytes, LoopDevice, MIB};
+use etch::core::error::EtchError;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+static CANCEL: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_cancel(_signal: libc::c_int) {
+    CANCEL.store(true, Ordering::Relaxed);
+}
+
+fn install_sigterm_handler() {
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe;
+    // SA_RESTART keeps the writer's blocking reads from failing with EINTR
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = request_cancel as extern "C" fn(libc::c_int) as usize;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        assert_eq!(
+            libc::sigaction(libc::SIGTERM, &action, std::ptr::null_mut()),
+            0
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs root and a free loop device"]
+fn sigterm_mid_write_syncs_and_reports_bytes_written() {
+    install_sigterm_handler();
+    let device = LoopDevice::new(64 * MIB);
+
+    // A pipe as the source decides exactly where the write is when the signal arrives
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("image.fifo");
+    let name = std::ffi::CString::new(source.as_os_str().as_encoded_bytes()).unwrap();
+    // SAFETY: `name` is a valid NUL-terminated path
+    assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o600) }, 0);
+
+    let before_signal = 16 * MIB;
+    let after_signal = 64 * 1024;
+    let pattern = image_bytes(before_signal + after_signal);
+    let before = usize::try_from(before_signal).unwrap();
+
+    let feeder = {
+        let source = source.clone();
+        let pattern = pattern.clone();
+        thread::spawn(move || {
+            let mut pipe = std::fs::File::options().write(true).open(&source).unwrap();
+            pipe.write_all(&pattern[..before]).unwrap();
+            // SAFETY: signalling our own process has no preconditions
+            assert_eq!(unsafe { libc::kill(libc::getpid(), libc::SIGTERM) }, 0);
+            while !CANCEL.load(Ordering::Relaxed) {
+                thread::yield_now();
+            }
+            // Wakes the writer, which then notices the flag before reading on
+            pipe.write_all(&pattern[before..]).unwrap();
+        })
+    };
+
+    let result = etch::io::writer::write_iso(
+        &source,
+        device.path(),
+        false,
+        None,
+        &CANCEL,
+        &AtomicBool::new(false),
+        |_, _, _| {},
+        |_| {},
+    );
+    feeder.join().unwrap();
+
+    let error = result.expect_err("the write must stop");
+    let Some(EtchError::Cancelled { bytes_done }) = error.downcast_ref::<EtchError>() else {
+        panic!("unexpected error: {error:#}");
+    };
+    let bytes_done = *bytes_done;
+    assert!(bytes_done > before_signal, "stopped at {bytes_done}");
+    assert!(bytes_done <= before_signal + after_signal);
+
+    // The backing file only sees what the device flushed
+    let done = usize::try_from(bytes_done).unwrap();
+    let flushed = device.flushed_contents();
+    assert!(
+        flushed[..done] == pattern[..done],
+        "written data was not synced"
+    );
+    assert!(flushed[done..].iter().all(|&byte| byte == 0));
+}
@@ -0,0 +1,179 @@
+//! Writing only the used blocks of an ext4 image (root and e2fsprogs only)
+
+mod common;
+
+use common::{image_bytes, LoopDevice, MIB};
+use etch::core::extents::{extent_bytes, next_extent, used_extents};
+use std::ops::Range;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::AtomicBool;
+
+const FILESYSTEM_SIZE: u64 = 64 * MIB;
+/// The filesystem sits in an MBR partition at 1 MiB
+const PARTITION_START: u64 = MIB;
+const DEVICE_SIZE: u64 = 128 * MIB;
+const STALE: u8 = 0xA5;
+
+fn run(program: &str, args: &[&str], target: &Path) -> String {
+    let output = Command::new(program)
+        .args(args)
+        .arg(target)
+        .output()
+        .unwrap_or_else(|e| panic!("run {program}: {e}"));
+    assert!(
+        output.status.success(),
+        "{program} {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Filesystem layouts the map must handle: mkfs options and block size
+const LAYOUTS: [(&[&str], u64); 5] = [
+    (&[], 4096),
+    (&["-b", "1024"], 1024),
+    // No checksums, so no BLOCK_UNINIT groups
+    (&["-O", "^metadata_csum,^uninit_bg"], 4096),
+    // Bitmaps and inode tables inside each group
+    (&["-O", "^flex_bg"], 4096),
+    (&["-O", "64bit"], 4096),
+];
+
+/// A fresh ext4 filesystem holding `file` as /data.bin
+fn filesystem_with(options: &[&str], block: u64, file: &[u8]) -> tempfile::NamedTempFile {
+    let filesystem = tempfile::NamedTempFile::new().unwrap();
+    filesystem.as_file().set_len(FILESYSTEM_SIZE).unwrap();
+    let block = block.to_string();
+    let mkfs: Vec<&str> = ["-q", "-F", "-b", &block]
+        .into_iter()
+        .chain(options.iter().copied())
+        .collect();
+    run("mkfs.ext4", &mkfs, filesystem.path());
+
+    let mut data = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut data, file).unwrap();
+    let write = format!("write {} data.bin", data.path().display());
+    run("debugfs", &["-w", "-R", &write], filesystem.path());
+    filesystem
+}
+
+/// Blocks `dumpe2fs` lists as free, group by group
+fn free_blocks(filesystem: &Path) -> Vec<Range<u64>> {
+    run("dumpe2fs", &[], filesystem)
+        .lines()
+        .filter_map(|line| line.strip_prefix("  Free blocks: "))
+        .flat_map(|list| list.split(", ").filter(|run| !run.is_empty()))
+        .map(|run| match run.split_once('-') {
+            Some((first, last)) => first.parse().unwrap()..last.parse::<u64>().unwrap() + 1,
+            None => run.parse().unwrap()..run.parse::<u64>().unwrap() + 1,
+        })
+        .collect()
+}
+
+/// `filesystem` behind an MBR with one Linux partition at `PARTITION_START`
+fn disk_image(filesystem: &Path) -> tempfile::NamedTempFile {
+    let mut image = vec![0u8; usize::try_from(PARTITION_START).unwrap()];
+    image[510] = 0x55;
+    image[511] = 0xAA;
+    let entry = &mut image[446..462];
+    entry[4] = 0x83;
+    let first_sector = u32::try_from(PARTITION_START / 512).unwrap();
+    let sectors = u32::try_from(FILESYSTEM_SIZE / 512).unwrap();
+    entry[8..12].copy_from_slice(&first_sector.to_le_bytes());
+    entry[12..16].copy_from_slice(&sectors.to_le_bytes());
+    image.extend(std::fs::read(filesystem).unwrap());
+
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut file, &image).unwrap();
+    file
+}
+
+fn covered(extents: &[Range<u64>], range: &Range<u64>) -> bool {
+    next_extent(extents, range.start)
+        .is_some_and(|extent| extent.start == range.start && extent.end >= range.end)
+}
+
+/// Map, write and verify one layout, then check the filesystem on the device
+fn write_used_space(options: &[&str], block: u64) {
+    let data = image_bytes(8 * MIB);
+    let filesystem = filesystem_with(options, block, &data);
+    let image = disk_image(filesystem.path());
+    let image_size = PARTITION_START + FILESYSTEM_SIZE;
+
+    let extents = used_extents(image.path()).expect("ext4 image is mapped");
+    assert!(extent_bytes(&extents) < image_size / 2);
+    assert_eq!(extents[0].start, 0, "partition table is written");
+
+    // Every block the filesystem uses is in the map
+    let free = free_blocks(filesystem.path());
+    let end = FILESYSTEM_SIZE / block;
+    let mut used_from = 0;
+    for gap in free.iter().chain([&(end..end)]) {
+        for used in used_from..gap.start {
+            let offset = PARTITION_START + used * block;
+            assert!(
+                covered(&extents, &(offset..offset + block)),
+                "block {used} skipped"
+            );
+        }
+        used_from = gap.end;
+    }
+
+    // A previously used device: the skipped space keeps its old contents
+    let device = LoopDevice::new(DEVICE_SIZE);
+    device.plant(0, &vec![STALE; usize::try_from(image_size).unwrap()]);
+    etch::io::writer::write_iso(
+        image.path(),
+        device.path(),
+        false,
+        Some(&extents),
+        &AtomicBool::new(false),
+        &AtomicBool::new(false),
+        |_, _, _| {},
+        |_| {},
+    )
+    .unwrap();
+    etch::core::verification::verify_write(
+        image.path(),
+        device.path(),
+        Some(&extents),
+        &AtomicBool::new(false),
+        |_, _, _| {},
+        |_| {},
+    )
+    .unwrap();
+
+    let contents = device.flushed_contents();
+    let skipped = extents[0].end;
+    assert_eq!(contents[usize::try_from(skipped).unwrap()], STALE);
+
+    // The filesystem on the device checks clean and holds the file
+    let written = tempfile::NamedTempFile::new().unwrap();
+    let partition = usize::try_from(PARTITION_START).unwrap()..usize::try_from(image_size).unwrap();
+    std::fs::write(written.path(), &contents[partition]).unwrap();
+    run("e2fsck", &["-f", "-n"], written.path());
+
+    let dumped = tempfile::NamedTempFile::new().unwrap();
+    let dump = format!("dump data.bin {}", dumped.path().display());
+    run("debugfs", &["-R", &dump], written.path());
+    assert!(std::fs::read(dumped.path()).unwrap() == data);
+}
+
+#[test]
+#[ignore = "needs root, a free loop device and e2fsprogs"]
+fn used_space_write_yields_an_intact_filesystem() {
+    for (options, block) in LAYOUTS {
+        eprintln!("layout: {options:?}, {block}-byte blocks");
+        write_used_space(options, block);
+    }
+}
+
+#[test]
+#[ignore = "needs e2fsprogs"]
+fn unsupported_layout_is_not_mapped() {
+    // Backup superblocks in groups the bitmap reader does not know about
+    let filesystem = filesystem_with(&["-O", "sparse_super2"], 4096, &image_bytes(MIB));
+    let image = disk_image(filesystem.path());
+    assert_eq!(used_extents(image.path()), None);
+}
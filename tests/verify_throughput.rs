@@ -0,0 +1,79 @@
+//! Full verification against a loop device, with and without read-ahead (root only)
+//!
+//! A benchmark rather than a check: it prints both throughputs, so run it
+//! with `sudo cargo test --release --test verify_throughput -- --ignored --nocapture`.
+
+mod common;
+
+use common::{image_bytes, LoopDevice, MIB};
+use etch::core::verification::{verify_write, CHUNK_SIZE};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+const IMAGE_SIZE: u64 = 256 * MIB;
+
+/// Drop the device's cached pages so each pass reads from the backing file
+fn flush_buffers(device: &Path) {
+    let status = Command::new("blockdev")
+        .arg("--flushbufs")
+        .arg(device)
+        .status()
+        .expect("run blockdev");
+    assert!(status.success(), "blockdev --flushbufs failed");
+}
+
+/// Read the source, then the device, then compare, one chunk at a time
+fn verify_sequentially(source: &Path, device: &Path) {
+    let (mut source, mut device) = (File::open(source).unwrap(), File::open(device).unwrap());
+    let mut expected = vec![0u8; CHUNK_SIZE];
+    let mut actual = vec![0u8; CHUNK_SIZE];
+    for _ in 0..IMAGE_SIZE / CHUNK_SIZE as u64 {
+        source.read_exact(&mut expected).unwrap();
+        device.read_exact(&mut actual).unwrap();
+        assert!(expected == actual, "device differs from the image");
+    }
+}
+
+fn throughput(elapsed: Duration) -> String {
+    #[allow(clippy::cast_precision_loss)]
+    let mib = IMAGE_SIZE as f64 / MIB as f64;
+    format!("{:.0} MiB/s", mib / elapsed.as_secs_f64())
+}
+
+#[test]
+#[ignore = "needs root and a free loop device"]
+fn read_ahead_verification_throughput() {
+    let device = LoopDevice::new(IMAGE_SIZE);
+    let image = image_bytes(IMAGE_SIZE);
+    let mut source = tempfile::NamedTempFile::new().unwrap();
+    source.write_all(&image).unwrap();
+    device.plant(0, &image);
+
+    flush_buffers(device.path());
+    let started = Instant::now();
+    verify_sequentially(source.path(), device.path());
+    let sequential = started.elapsed();
+
+    flush_buffers(device.path());
+    let started = Instant::now();
+    verify_write(
+        source.path(),
+        device.path(),
+        None,
+        &AtomicBool::new(false),
+        |_, _, _| {},
+        |_| {},
+    )
+    .expect("verification of an identical device failed");
+    let read_ahead = started.elapsed();
+
+    println!(
+        "sequential: {}, read-ahead: {}",
+        throughput(sequential),
+        throughput(read_ahead)
+    );
+}